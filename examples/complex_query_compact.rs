@@ -59,7 +59,7 @@ fn main() {
          .having("count(*)", Equality::GT, &1)
          .asc("product.name")
          .desc("product.created");
-    let frag = query.build(db.as_ref());
+    let frag = query.build(db.as_ref()).unwrap();
 
     let expected = "
    SELECT *
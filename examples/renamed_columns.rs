@@ -63,7 +63,7 @@ fn main() {
          .having("count(*)", Equality::GT, &1)
          .asc("product.name")
          .desc("product.created");
-    let frag = query.build(db.as_ref());
+    let frag = query.build(db.as_ref()).unwrap();
 
     let expected = "
 SELECT product.product_id AS product_product_id, product.name AS product_name, category.product_id AS category_product_id,\x20
@@ -23,7 +23,7 @@ fn main() {
     let url = "sqlite:///file1.db";
     let pool = ManagedPool::init(&url, 1).unwrap();
     let db = pool.connect().unwrap();
-    db.as_ddl().create_table(&Product::table());
+    db.as_ddl().create_table(&Product::table()).unwrap();
 }
 
 
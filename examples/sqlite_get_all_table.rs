@@ -11,7 +11,7 @@ fn main() {
     let db = pool.connect().unwrap();
 
     //let table = db.as_dev().get_table_metadata("","product_availability", false);
-    let table = db.as_dev().get_all_tables();
+    let table = db.as_dev().get_all_tables().unwrap();
 
     println!("all_tables: {:#?}", table);
 }
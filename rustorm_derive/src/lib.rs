@@ -0,0 +1,338 @@
+//! Derive `IsDao` and `IsTable` for structs which are mapped to a table,
+//! instead of writing the `from_dao`, `to_dao` and the `Table` definition by hand.
+//!
+//! ```ignore
+//! #[macro_use]
+//! extern crate rustorm_derive;
+//!
+//! #[derive(Debug, Clone, IsDao, IsTable)]
+//! #[rustorm(schema = "bazaar", table = "product")]
+//! pub struct Product {
+//!     #[rustorm(primary)]
+//!     pub product_id: Uuid,
+//!     #[rustorm(column = "name")]
+//!     pub title: Option<String>,
+//!     #[rustorm(skip)]
+//!     pub selected: bool,
+//! }
+//! ```
+//!
+//! Struct attributes:
+//!
+//! * `schema = ".."` the schema of the table, defaults to no schema, ie: the search path of the connection
+//! * `table = ".."` the table name, defaults to the struct name in snake case
+//!
+//! Field attributes:
+//!
+//! * `column = ".."` the column name, defaults to the field name
+//! * `db_type = ".."` the database data type of the column
+//! * `primary` the column is part of the primary key
+//! * `unique` the column has a unique constraint
+//! * `nullable` the column of a field that is not an `Option` is nullable, a NULL is read as the `Default` of the field.
+//!   `Option` fields are always nullable, except for a primary column where `None` lets the database generate the key
+//! * `default = ".."` the default of the column, ie: `now()`,
+//!   which `update_ignore_defaulted_columns` resets the column to. Without it the column has no default
+//! * `skip` the field is not stored, it is filled with its `Default` when read
+//!
+//! This crate is edition 2018 and needs rust 1.56 or later, which `syn` 1.0 and `proc-macro2` 1.0 require,
+//! unlike `rustorm` itself which is still edition 2015.
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate syn;
+#[macro_use]
+extern crate quote;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as Tokens;
+use syn::{Attribute, Data, DeriveInput, Error, Fields, GenericArgument, Ident, Lit, Meta,
+          NestedMeta, PathArguments, Type};
+
+#[proc_macro_derive(IsDao, attributes(rustorm))]
+pub fn derive_is_dao(input: TokenStream) -> TokenStream {
+    let ast = parse_macro_input!(input as DeriveInput);
+    match impl_is_dao(&ast) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+#[proc_macro_derive(IsTable, attributes(rustorm))]
+pub fn derive_is_table(input: TokenStream) -> TokenStream {
+    let ast = parse_macro_input!(input as DeriveInput);
+    match impl_is_table(&ast) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+/// the table the struct is mapped to
+struct TableAttr {
+    schema: String,
+    name: String,
+}
+
+/// the column a field is mapped to
+struct ColumnAttr {
+    ident: Ident,
+    column: String,
+    /// the rust type, unwrapped out of the Option
+    data_type: String,
+    db_data_type: String,
+    is_option: bool,
+    is_primary: bool,
+    is_unique: bool,
+    nullable: bool,
+    default: Option<String>,
+    skip: bool,
+}
+
+fn impl_is_dao(ast: &DeriveInput) -> Result<Tokens, Error> {
+    let columns = column_attrs(ast)?;
+    let name = &ast.ident;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+
+    let mut from_fields = vec![];
+    let mut to_fields = vec![];
+    for c in &columns {
+        let ident = &c.ident;
+        let column = &c.column;
+        if c.skip {
+            from_fields.push(quote! { #ident: ::std::default::Default::default() });
+        } else if c.is_option {
+            from_fields.push(quote! { #ident: dao.get_opt(#column) });
+            to_fields.push(quote! {
+                match self.#ident {
+                    Some(ref _value) => dao.set(#column, _value),
+                    None => dao.set_null(#column),
+                }
+            });
+        } else if c.nullable {
+            from_fields.push(quote! { #ident: dao.get_opt(#column).unwrap_or_default() });
+            to_fields.push(quote! { dao.set(#column, &self.#ident); });
+        } else {
+            from_fields.push(quote! { #ident: dao.get(#column) });
+            to_fields.push(quote! { dao.set(#column, &self.#ident); });
+        }
+    }
+
+    Ok(quote! {
+        impl #impl_generics ::rustorm::dao::IsDao for #name #ty_generics #where_clause {
+            fn from_dao(dao: &::rustorm::dao::Dao) -> Self {
+                #name {
+                    #(#from_fields,)*
+                }
+            }
+
+            fn to_dao(&self) -> ::rustorm::dao::Dao {
+                let mut dao = ::rustorm::dao::Dao::new();
+                #(#to_fields)*
+                dao
+            }
+        }
+    })
+}
+
+fn impl_is_table(ast: &DeriveInput) -> Result<Tokens, Error> {
+    let table = table_attr(ast)?;
+    let columns = column_attrs(ast)?;
+    let name = &ast.ident;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+
+    let schema = &table.schema;
+    let table_name = &table.name;
+    let mut column_defs = vec![];
+    for c in columns.iter().filter(|c| !c.skip) {
+        let column = &c.column;
+        let data_type = &c.data_type;
+        let db_data_type = &c.db_data_type;
+        let is_primary = c.is_primary;
+        let is_unique = c.is_unique;
+        let not_null = !c.nullable;
+        let default = match c.default {
+            Some(ref default) => quote! { Some(#default.to_owned()) },
+            None => quote! { None },
+        };
+        column_defs.push(quote! {
+            ::rustorm::table::Column {
+                name: #column.to_owned(),
+                data_type: #data_type.to_owned(),
+                db_data_type: #db_data_type.to_owned(),
+                is_primary: #is_primary,
+                is_unique: #is_unique,
+                default: #default,
+                comment: None,
+                not_null: #not_null,
+                foreign: None,
+                is_inherited: false,
+            }
+        });
+    }
+
+    Ok(quote! {
+        impl #impl_generics ::rustorm::table::IsTable for #name #ty_generics #where_clause {
+            fn table() -> ::rustorm::table::Table {
+                ::rustorm::table::Table {
+                    schema: #schema.to_owned(),
+                    name: #table_name.to_owned(),
+                    parent_table: None,
+                    sub_table: vec![],
+                    comment: None,
+                    columns: vec![#(#column_defs),*],
+                    is_view: false,
+                }
+            }
+        }
+    })
+}
+
+fn table_attr(ast: &DeriveInput) -> Result<TableAttr, Error> {
+    let mut table = TableAttr {
+        schema: String::new(),
+        name: snake_case(&ast.ident.to_string()),
+    };
+    for meta in rustorm_attrs(&ast.attrs)? {
+        match meta {
+            Meta::NameValue(ref nv) if nv.path.is_ident("schema") => {
+                table.schema = lit_str(&nv.lit)?;
+            }
+            Meta::NameValue(ref nv) if nv.path.is_ident("table") => {
+                table.name = lit_str(&nv.lit)?;
+            }
+            other => return Err(Error::new_spanned(other, "unknown rustorm table attribute")),
+        }
+    }
+    Ok(table)
+}
+
+fn column_attrs(ast: &DeriveInput) -> Result<Vec<ColumnAttr>, Error> {
+    let fields = match ast.data {
+        Data::Struct(ref data) => {
+            match data.fields {
+                Fields::Named(ref fields) => &fields.named,
+                _ => return Err(Error::new_spanned(ast, "only structs with named fields are supported")),
+            }
+        }
+        _ => return Err(Error::new_spanned(ast, "only structs are supported")),
+    };
+    let mut columns = vec![];
+    for field in fields {
+        let ident = field.ident.clone().unwrap();
+        let option_type = option_inner(&field.ty);
+        let data_type = match option_type {
+            Some(inner) => type_name(inner),
+            None => type_name(&field.ty),
+        };
+        let mut column = ColumnAttr {
+            column: ident.to_string(),
+            ident,
+            data_type,
+            db_data_type: String::new(),
+            is_option: option_type.is_some(),
+            is_primary: false,
+            is_unique: false,
+            nullable: false,
+            default: None,
+            skip: false,
+        };
+        let mut is_nullable = false;
+        for meta in rustorm_attrs(&field.attrs)? {
+            match meta {
+                Meta::NameValue(ref nv) if nv.path.is_ident("column") => {
+                    column.column = lit_str(&nv.lit)?;
+                }
+                Meta::NameValue(ref nv) if nv.path.is_ident("db_type") => {
+                    column.db_data_type = lit_str(&nv.lit)?;
+                }
+                Meta::NameValue(ref nv) if nv.path.is_ident("default") => {
+                    column.default = Some(lit_str(&nv.lit)?);
+                }
+                Meta::Path(ref p) if p.is_ident("primary") => column.is_primary = true,
+                Meta::Path(ref p) if p.is_ident("unique") => column.is_unique = true,
+                Meta::Path(ref p) if p.is_ident("nullable") => {
+                    if column.is_option {
+                        return Err(Error::new_spanned(p, "an Option field is already nullable"));
+                    }
+                    is_nullable = true;
+                }
+                Meta::Path(ref p) if p.is_ident("skip") => column.skip = true,
+                other => return Err(Error::new_spanned(other, "unknown rustorm field attribute")),
+            }
+        }
+        if column.is_primary && is_nullable {
+            return Err(Error::new_spanned(field, "a primary column can not be nullable"));
+        }
+        // an Option primary column is None until the database generates the key
+        column.nullable = is_nullable || (column.is_option && !column.is_primary);
+        columns.push(column);
+    }
+    Ok(columns)
+}
+
+/// the items inside all the `#[rustorm(..)]` attributes
+fn rustorm_attrs(attrs: &[Attribute]) -> Result<Vec<Meta>, Error> {
+    let mut metas = vec![];
+    for attr in attrs {
+        if !attr.path.is_ident("rustorm") {
+            continue;
+        }
+        match attr.parse_meta()? {
+            Meta::List(list) => {
+                for nested in list.nested {
+                    match nested {
+                        NestedMeta::Meta(meta) => metas.push(meta),
+                        NestedMeta::Lit(lit) => {
+                            return Err(Error::new_spanned(lit, "expecting a rustorm attribute"))
+                        }
+                    }
+                }
+            }
+            other => return Err(Error::new_spanned(other, "expecting #[rustorm(..)]")),
+        }
+    }
+    Ok(metas)
+}
+
+fn lit_str(lit: &Lit) -> Result<String, Error> {
+    match *lit {
+        Lit::Str(ref s) => Ok(s.value()),
+        _ => Err(Error::new_spanned(lit, "expecting a string literal")),
+    }
+}
+
+/// the `T` in `Option<T>`
+fn option_inner(ty: &Type) -> Option<&Type> {
+    if let Type::Path(ref type_path) = *ty {
+        if let Some(segment) = type_path.path.segments.last() {
+            if segment.ident == "Option" {
+                if let PathArguments::AngleBracketed(ref args) = segment.arguments {
+                    if let Some(GenericArgument::Type(inner)) = args.args.first() {
+                        return Some(inner);
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
+/// the type as written, ie: Uuid, String, Vec<u8>
+fn type_name(ty: &Type) -> String {
+    quote!(#ty).to_string().replace(" ", "")
+}
+
+/// ProductAvailability -> product_availability
+fn snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() {
+            if i > 0 {
+                snake.push('_');
+            }
+            snake.extend(c.to_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
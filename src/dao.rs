@@ -7,6 +7,8 @@ use chrono::naive::datetime::NaiveDateTime;
 use chrono::offset::utc::UTC;
 use std::fmt;
 use query::ColumnName;
+use query::Error;
+use database::DbError;
 use table::IsTable;
 use rustc_serialize::{Encodable, Encoder};
 use rustc_serialize::json::{self, ToJson, Json};
@@ -202,10 +204,14 @@ pub struct DaoResult {
 
     /// the total number of records
     pub total: Option<usize>,
-    /// page of the query
+    /// page of the query, the first page is 0
     pub page: Option<usize>,
     /// page size
     pub page_size: Option<usize>,
+    /// when keyset paging, the cursor of the page after this one, None when this is the last page
+    pub next_cursor: Option<String>,
+    /// when keyset paging, the cursor of the page before this one, None when this is the first page
+    pub prev_cursor: Option<String>,
 }
 
 /// a serializable array of dao to be serialized to json request
@@ -215,6 +221,8 @@ pub struct SerDaoResult {
     pub total: Option<usize>,
     pub page: Option<usize>,
     pub page_size: Option<usize>,
+    pub next_cursor: Option<String>,
+    pub prev_cursor: Option<String>,
 }
 
 impl SerDaoResult {
@@ -225,6 +233,8 @@ impl SerDaoResult {
             total: daoresult.total,
             page: daoresult.page,
             page_size: daoresult.page_size,
+            next_cursor: daoresult.next_cursor,
+            prev_cursor: daoresult.prev_cursor,
         }
     }
 }
@@ -350,6 +360,20 @@ impl Dao {
         let value = self.values.get(column).unwrap();
         FromValue::from_type(value.clone())
     }
+    /// get the value without panicking,
+    /// errors when the column is missing or the value is not of the expected type
+    pub fn try_get<T>(&self, column: &str) -> Result<T, DbError>
+        where T: TryFromValue
+    {
+        match self.values.get(column) {
+            Some(value) => {
+                let value = try!(TryFromValue::try_from_value(value.clone()));
+                Ok(value)
+            }
+            None => Err(DbError::from(Error::MissingColumn(column.to_owned()))),
+        }
+    }
+
     /// get optional value
     pub fn get_opt<T>(&self, column: &str) -> Option<T>
         where T: FromValue
@@ -522,165 +546,192 @@ impl ToValue for Json {
 ///
 ///
 ///
-///
-///
-///
-///
-///
+/// convert the Value into the rust type,
+/// panics when the value is not of the expected type
 pub trait FromValue {
     fn from_type(ty: Value) -> Self;
 }
 
-impl FromValue for bool {
-    fn from_type(ty: Value) -> Self {
+/// FromValue of the types which has TryFromValue, implemented per type
+/// so custom types can still have their own FromValue
+macro_rules! impl_from_value {
+    ($($ty: ty),*) => {
+        $(
+            impl FromValue for $ty {
+                fn from_type(ty: Value) -> Self {
+                    match TryFromValue::try_from_value(ty) {
+                        Ok(value) => value,
+                        Err(e) => panic!("{}", e),
+                    }
+                }
+            }
+        )*
+    }
+}
+
+impl_from_value!(bool, i8, i16, i32, i64, u8, u16, u32, u64, f32, f64, String, Uuid,
+                 DateTime<UTC>, NaiveTime, NaiveDate, NaiveDateTime, Json);
+
+/// the fallible version of FromValue,
+/// returns an error when the value is not of the expected type
+pub trait TryFromValue: Sized {
+    fn try_from_value(ty: Value) -> Result<Self, Error>;
+}
+
+fn mismatch(expected: &str, ty: &Value) -> Error {
+    Error::TypeMismatch(format!("expecting {}, but found {:?}", expected, ty))
+}
+
+impl TryFromValue for bool {
+    fn try_from_value(ty: Value) -> Result<Self, Error> {
         match ty {
-            Value::Bool(x) => x,
-            _ => panic!("error!"),
+            Value::Bool(x) => Ok(x),
+            _ => Err(mismatch("bool", &ty)),
         }
     }
 }
 
-impl FromValue for i8 {
-    fn from_type(ty: Value) -> Self {
+impl TryFromValue for i8 {
+    fn try_from_value(ty: Value) -> Result<Self, Error> {
         match ty {
-            Value::I8(x) => x,
-            _ => panic!("error!"),
+            Value::I8(x) => Ok(x),
+            _ => Err(mismatch("i8", &ty)),
         }
     }
 }
-impl FromValue for i16 {
-    fn from_type(ty: Value) -> Self {
+impl TryFromValue for i16 {
+    fn try_from_value(ty: Value) -> Result<Self, Error> {
         match ty {
-            Value::I16(x) => x,
-            _ => panic!("error!"),
+            Value::I16(x) => Ok(x),
+            _ => Err(mismatch("i16", &ty)),
         }
     }
 }
-impl FromValue for i32 {
-    fn from_type(ty: Value) -> Self {
+impl TryFromValue for i32 {
+    fn try_from_value(ty: Value) -> Result<Self, Error> {
         match ty {
-            Value::I32(x) => x,
-            _ => panic!("error!"),
+            Value::I32(x) => Ok(x),
+            _ => Err(mismatch("i32", &ty)),
         }
     }
 }
-impl FromValue for i64 {
-    fn from_type(ty: Value) -> Self {
+impl TryFromValue for i64 {
+    fn try_from_value(ty: Value) -> Result<Self, Error> {
         match ty {
-            Value::I64(x) => x,
-            _ => panic!("error!"),
+            Value::I64(x) => Ok(x),
+            _ => Err(mismatch("i64", &ty)),
         }
     }
 }
-impl FromValue for u8 {
-    fn from_type(ty: Value) -> Self {
+impl TryFromValue for u8 {
+    fn try_from_value(ty: Value) -> Result<Self, Error> {
         match ty {
-            Value::U8(x) => x,
-            _ => panic!("error!"),
+            Value::U8(x) => Ok(x),
+            _ => Err(mismatch("u8", &ty)),
         }
     }
 }
-impl FromValue for u16 {
-    fn from_type(ty: Value) -> Self {
+impl TryFromValue for u16 {
+    fn try_from_value(ty: Value) -> Result<Self, Error> {
         match ty {
-            Value::U16(x) => x,
-            _ => panic!("error!"),
+            Value::U16(x) => Ok(x),
+            _ => Err(mismatch("u16", &ty)),
         }
     }
 }
-impl FromValue for u32 {
-    fn from_type(ty: Value) -> Self {
+impl TryFromValue for u32 {
+    fn try_from_value(ty: Value) -> Result<Self, Error> {
         match ty {
-            Value::U32(x) => x,
-            _ => panic!("error!"),
+            Value::U32(x) => Ok(x),
+            _ => Err(mismatch("u32", &ty)),
         }
     }
 }
-impl FromValue for u64 {
-    fn from_type(ty: Value) -> Self {
+impl TryFromValue for u64 {
+    fn try_from_value(ty: Value) -> Result<Self, Error> {
         match ty {
-            Value::U64(x) => x,
-            _ => panic!("error!"),
+            Value::U64(x) => Ok(x),
+            _ => Err(mismatch("u64", &ty)),
         }
     }
 }
 
-impl FromValue for f32 {
-    fn from_type(ty: Value) -> Self {
+impl TryFromValue for f32 {
+    fn try_from_value(ty: Value) -> Result<Self, Error> {
         match ty {
-            Value::F32(x) => x,
-            _ => panic!("error!"),
+            Value::F32(x) => Ok(x),
+            _ => Err(mismatch("f32", &ty)),
         }
     }
 }
-impl FromValue for f64 {
-    fn from_type(ty: Value) -> Self {
+impl TryFromValue for f64 {
+    fn try_from_value(ty: Value) -> Result<Self, Error> {
         match ty {
-            Value::F64(x) => x,
-            _ => panic!("error! {:?}", ty),
+            Value::F64(x) => Ok(x),
+            _ => Err(mismatch("f64", &ty)),
         }
     }
 }
 
-impl FromValue for String {
-    fn from_type(ty: Value) -> Self {
+impl TryFromValue for String {
+    fn try_from_value(ty: Value) -> Result<Self, Error> {
         match ty {
-            Value::String(x) => x,
-            _ => panic!("error! {:?}", ty),
+            Value::String(x) => Ok(x),
+            _ => Err(mismatch("String", &ty)),
         }
     }
 }
 
-impl FromValue for Uuid {
-    fn from_type(ty: Value) -> Self {
+impl TryFromValue for Uuid {
+    fn try_from_value(ty: Value) -> Result<Self, Error> {
         match ty {
-            Value::Uuid(x) => x,
-            _ => panic!("error!"),
+            Value::Uuid(x) => Ok(x),
+            _ => Err(mismatch("Uuid", &ty)),
         }
     }
 }
 
-impl FromValue for DateTime<UTC> {
-    fn from_type(ty: Value) -> Self {
+impl TryFromValue for DateTime<UTC> {
+    fn try_from_value(ty: Value) -> Result<Self, Error> {
         match ty {
-            Value::DateTime(x) => x,
-            _ => panic!("error!"),
+            Value::DateTime(x) => Ok(x),
+            _ => Err(mismatch("DateTime<UTC>", &ty)),
         }
     }
 }
 
-impl FromValue for NaiveTime {
-    fn from_type(ty: Value) -> Self {
+impl TryFromValue for NaiveTime {
+    fn try_from_value(ty: Value) -> Result<Self, Error> {
         match ty {
-            Value::NaiveTime(x) => x,
-            _ => panic!("error!"),
+            Value::NaiveTime(x) => Ok(x),
+            _ => Err(mismatch("NaiveTime", &ty)),
         }
     }
 }
 
-impl FromValue for NaiveDate {
-    fn from_type(ty: Value) -> Self {
+impl TryFromValue for NaiveDate {
+    fn try_from_value(ty: Value) -> Result<Self, Error> {
         match ty {
-            Value::NaiveDate(x) => x,
-            _ => panic!("error!"),
+            Value::NaiveDate(x) => Ok(x),
+            _ => Err(mismatch("NaiveDate", &ty)),
         }
     }
 }
 
-impl FromValue for NaiveDateTime {
-    fn from_type(ty: Value) -> Self {
+impl TryFromValue for NaiveDateTime {
+    fn try_from_value(ty: Value) -> Result<Self, Error> {
         match ty {
-            Value::NaiveDateTime(x) => x,
-            _ => panic!("error!"),
+            Value::NaiveDateTime(x) => Ok(x),
+            _ => Err(mismatch("NaiveDateTime", &ty)),
         }
     }
 }
 
-impl FromValue for Json {
-    fn from_type(ty: Value) -> Self {
+impl TryFromValue for Json {
+    fn try_from_value(ty: Value) -> Result<Self, Error> {
         match ty {
-            Value::Json(x) => x,
-            _ => panic!("error!"),
+            Value::Json(x) => Ok(x),
+            _ => Err(mismatch("Json", &ty)),
         }
     }
 }
@@ -722,3 +773,18 @@ fn test_json() {
     println!("expected: {}", expected);
     println!("actual: {}", actual);
 }
+
+#[test]
+fn test_try_get() {
+    let mut dao = Dao::new();
+    dao.set("name", &"lee");
+    let name: Result<String, DbError> = dao.try_get("name");
+    assert_eq!(name.unwrap(), "lee");
+    let age: Result<i32, DbError> = dao.try_get("name");
+    assert!(age.is_err());
+    let missing: Result<String, DbError> = dao.try_get("missing");
+    match missing {
+        Err(DbError::QueryError(Error::MissingColumn(column))) => assert_eq!(column, "missing"),
+        _ => panic!("expecting a missing column error"),
+    }
+}
@@ -1,13 +1,21 @@
 use query::Query;
+use migration::split_statements;
 use table::Table;
+use diff::Change;
 use dao::{Dao, DaoResult, Value};
 use writer::SqlFrag;
 use query::{Connector, Equality, Operand, Field};
-use query::{Direction, Modifier, JoinType};
-use query::{Filter, Condition};
+use query::{Direction, Modifier, JoinType, SetOperation};
+use query::{Window, FrameUnit, FrameBound};
+use query::{Filter, Condition, Join, ColumnName, TableName};
+use query::{Range, Keyset};
+use query::{Lock, LockStrength, LockWait};
 use query::SqlType;
+use query::Error as QueryError;
 use std::error::Error;
 use std::fmt;
+use std::cell::Cell;
+use std::cmp;
 use r2d2;
 use postgres::error::Error as PgError;
 use postgres::error::ConnectError as PgConnectError;
@@ -36,6 +44,29 @@ pub enum SqlOption {
     /// wheter the returned rows in a query included Meta columns for easy extraction of records
     /// (postgres returns this), sqlite does not return meta columns, so you have to extract it by index yourself.
     ReturnMetaColumns,
+    /// upsert using `INSERT ... ON CONFLICT (columns) DO UPDATE` (postgresql >= 9.5, sqlite >= 3.24)
+    SupportsOnConflict,
+    /// upsert using `INSERT ... ON DUPLICATE KEY UPDATE` (mysql)
+    SupportsOnDuplicateKey,
+    /// supports INTERSECT and EXCEPT besides UNION (postgresql, sqlite)
+    SupportsIntersect,
+    /// a combined query which is itself combined can be wrapped in parentheses,
+    /// ie: `a EXCEPT (b UNION c)` (postgresql)
+    SupportsNestedCombine,
+    /// supports window functions, ie: row_number() OVER (..) (postgresql, sqlite >= 3.25)
+    SupportsWindowFunction,
+    /// supports case insensitive ILIKE (postgresql)
+    SupportsILike,
+    /// update and delete joined with other tables using `UPDATE .. FROM` and `DELETE .. USING` (postgresql)
+    SupportsUpdateFrom,
+    /// update and delete joined with other tables using `UPDATE t JOIN ..` and `DELETE t FROM t JOIN ..` (mysql)
+    SupportsMultiTableUpdate,
+    /// supports setting a column to its default in an update, ie: SET updated = DEFAULT (postgresql, mysql)
+    SupportsSetDefault,
+    /// supports locking the selected rows, ie: FOR UPDATE SKIP LOCKED (postgresql >= 9.5, mysql)
+    SupportsRowLocking,
+    /// the shared lock is `LOCK IN SHARE MODE` and there is no OF, NOWAIT or SKIP LOCKED (mysql before 8.0, mariadb)
+    UsesLockInShareMode,
 }
 
 #[derive(Debug)]
@@ -43,6 +74,40 @@ pub enum DbError {
     Error(String),
     PoolError(r2d2::InitializationError),
     PlatformError(PlatformError),
+    /// the query is incomplete or not supported by the database
+    QueryError(QueryError),
+    /// a unique or primary key constraint is violated, ie: duplicate records
+    UniqueViolation {
+        constraint: Option<String>,
+        table: Option<String>,
+        cause: PlatformError,
+    },
+    /// the referenced record does not exist, or is still referred to by other records
+    ForeignKeyViolation {
+        constraint: Option<String>,
+        table: Option<String>,
+        cause: PlatformError,
+    },
+    /// a null value is supplied to a column which does not accept null values
+    NotNullViolation {
+        column: Option<String>,
+        table: Option<String>,
+        cause: PlatformError,
+    },
+    /// the transaction can not be serialized or is in a deadlock, it is safe to retry it
+    SerializationFailure(PlatformError),
+    /// the connection to the database is lost or can not be established
+    ConnectionLost(PlatformError),
+    /// the statement or a lock wait took too long
+    Timeout(PlatformError),
+    /// the transaction failed and rolling it back failed too,
+    /// the connection may still be in the failed transaction
+    RollbackFailed {
+        /// the error that caused the rollback
+        cause: Box<DbError>,
+        /// the error of the rollback itself
+        rollback: Box<DbError>,
+    },
 }
 
 impl DbError {
@@ -57,6 +122,14 @@ impl Error for DbError {
             DbError::Error(ref description) => description,
             DbError::PoolError(ref err) => err.description(),
             DbError::PlatformError(ref err) => err.description(),
+            DbError::QueryError(ref err) => err.description(),
+            DbError::UniqueViolation { .. } => "unique constraint violation",
+            DbError::ForeignKeyViolation { .. } => "foreign key constraint violation",
+            DbError::NotNullViolation { .. } => "not null constraint violation",
+            DbError::SerializationFailure(_) => "serialization failure",
+            DbError::ConnectionLost(_) => "connection lost",
+            DbError::Timeout(_) => "timeout",
+            DbError::RollbackFailed { .. } => "rollback failed",
         }
     }
 
@@ -65,6 +138,14 @@ impl Error for DbError {
             DbError::Error(_) => None,
             DbError::PoolError(ref err) => Some(err),
             DbError::PlatformError(ref err) => Some(err),
+            DbError::QueryError(ref err) => Some(err),
+            DbError::UniqueViolation { ref cause, .. } => Some(cause),
+            DbError::ForeignKeyViolation { ref cause, .. } => Some(cause),
+            DbError::NotNullViolation { ref cause, .. } => Some(cause),
+            DbError::SerializationFailure(ref err) => Some(err),
+            DbError::ConnectionLost(ref err) => Some(err),
+            DbError::Timeout(ref err) => Some(err),
+            DbError::RollbackFailed { ref cause, .. } => Some(&**cause),
         }
     }
 }
@@ -73,8 +154,36 @@ impl fmt::Display for DbError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             DbError::PoolError(ref err) => write!(f, "Pool error: {}", err),
-            DbError::PlatformError(ref err) => write!(f, "PostgreSQL error: {}", err),
+            DbError::PlatformError(ref err) => write!(f, "{}", err),
+            DbError::QueryError(ref err) => write!(f, "Query error: {}", err),
             DbError::Error(_) => write!(f, "{}", self.description()),
+            DbError::UniqueViolation { ref constraint, ref table, ref cause } => {
+                write!(f,
+                       "Unique violation on {} ({}): {}",
+                       table.as_ref().map_or("?", |t| t),
+                       constraint.as_ref().map_or("?", |c| c),
+                       cause)
+            }
+            DbError::ForeignKeyViolation { ref constraint, ref table, ref cause } => {
+                write!(f,
+                       "Foreign key violation on {} ({}): {}",
+                       table.as_ref().map_or("?", |t| t),
+                       constraint.as_ref().map_or("?", |c| c),
+                       cause)
+            }
+            DbError::NotNullViolation { ref column, ref table, ref cause } => {
+                write!(f,
+                       "Not null violation on {}.{}: {}",
+                       table.as_ref().map_or("?", |t| t),
+                       column.as_ref().map_or("?", |c| c),
+                       cause)
+            }
+            DbError::SerializationFailure(ref err) => write!(f, "Serialization failure: {}", err),
+            DbError::ConnectionLost(ref err) => write!(f, "Connection lost: {}", err),
+            DbError::Timeout(ref err) => write!(f, "Timeout: {}", err),
+            DbError::RollbackFailed { ref cause, ref rollback } => {
+                write!(f, "{}, and the rollback failed: {}", cause, rollback)
+            }
         }
     }
 }
@@ -91,6 +200,12 @@ impl From<PlatformError> for DbError {
     }
 }
 
+impl From<QueryError> for DbError {
+    fn from(err: QueryError) -> Self {
+        DbError::QueryError(err)
+    }
+}
+
 impl From<RegexError> for DbError {
     fn from(err: RegexError) -> Self {
         DbError::new(err.description())
@@ -99,26 +214,76 @@ impl From<RegexError> for DbError {
 
 impl From<PgError> for DbError {
     fn from(err: PgError) -> Self {
-        DbError::PlatformError(From::from(err))
+        ::platform::postgres::to_db_error(err)
     }
 }
 
 impl From<PgConnectError> for DbError {
     fn from(err: PgConnectError) -> Self {
-        DbError::PlatformError(From::from(err))
+        match err {
+            PgConnectError::Io(_) => DbError::ConnectionLost(From::from(err)),
+            _ => DbError::PlatformError(From::from(err)),
+        }
     }
 }
 #[cfg(feature = "mysql")]
 impl From<MyError> for DbError {
     fn from(err: MyError) -> Self {
-        DbError::PlatformError(From::from(err))
+        ::platform::mysql::to_db_error(err)
     }
 }
 
 #[cfg(feature = "sqlite")]
 impl From<SqliteError> for DbError {
     fn from(err: SqliteError) -> Self {
-        DbError::PlatformError(From::from(err))
+        ::platform::sqlite::to_db_error(err)
+    }
+}
+
+/// the name of the savepoint created by a transaction nested at this depth
+fn savepoint_name(depth: usize) -> String {
+    format!("rustorm_savepoint_{}", depth)
+}
+
+/// split the raw sql at each `?` placeholder,
+/// a `??` is unescaped into `?` and the `?` inside quoted string literals are not placeholders
+fn split_placeholders(sql: &str) -> Vec<String> {
+    let mut parts = vec![];
+    let mut current = String::new();
+    let mut chars = sql.chars().peekable();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        if c == '\'' {
+            quoted = !quoted;
+            current.push(c);
+        } else if c == '?' && !quoted {
+            if chars.peek() == Some(&'?') {
+                chars.next();
+                current.push('?');
+            } else {
+                parts.push(current);
+                current = String::new();
+            }
+        } else {
+            current.push(c);
+        }
+    }
+    parts.push(current);
+    parts
+}
+
+/// rolls back the transaction when dropped before it is finished,
+/// which only happens when the transaction closure panics
+struct TransactionGuard<'a> {
+    db: &'a Database,
+    finished: bool,
+}
+
+impl<'a> Drop for TransactionGuard<'a> {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = self.db.rollback();
+        }
     }
 }
 
@@ -136,17 +301,62 @@ pub trait Database {
     /// lower version of database has fewer supported features
     fn version(&self) -> Result<String, DbError>;
 
-    /// begin database transaction
-    fn begin(&self);
+    /// the number of nested transactions currently open on this connection
+    fn transaction_depth(&self) -> &Cell<usize>;
+
+    /// begin database transaction,
+    /// when there is already an ongoing transaction a savepoint is created instead
+    fn begin(&self) -> Result<(), DbError> {
+        let depth = self.transaction_depth().get();
+        if depth == 0 {
+            try!(self.execute_sql("BEGIN", &[]));
+        } else {
+            try!(self.execute_sql(&format!("SAVEPOINT {}", savepoint_name(depth)), &[]));
+        }
+        self.transaction_depth().set(depth + 1);
+        Ok(())
+    }
 
-    /// commit database transaction
-    fn commit(&self);
+    /// commit database transaction,
+    /// releases the savepoint when this is a nested transaction
+    fn commit(&self) -> Result<(), DbError> {
+        let depth = self.transaction_depth().get();
+        if depth == 0 {
+            return Err(DbError::new("There is no ongoing transaction to commit"));
+        }
+        if depth == 1 {
+            try!(self.execute_sql("COMMIT", &[]));
+        } else {
+            try!(self.execute_sql(&format!("RELEASE SAVEPOINT {}", savepoint_name(depth - 1)),
+                                  &[]));
+        }
+        // the transaction is still open when the statement failed
+        self.transaction_depth().set(depth - 1);
+        Ok(())
+    }
 
-    /// rollback data changes executed prior to calling the begin method
-    fn rollback(&self);
+    /// rollback data changes executed prior to calling the begin method,
+    /// only rolls back up to the savepoint when this is a nested transaction
+    fn rollback(&self) -> Result<(), DbError> {
+        let depth = self.transaction_depth().get();
+        if depth == 0 {
+            return Err(DbError::new("There is no ongoing transaction to rollback"));
+        }
+        if depth == 1 {
+            try!(self.execute_sql("ROLLBACK", &[]));
+        } else {
+            let savepoint = savepoint_name(depth - 1);
+            try!(self.execute_sql(&format!("ROLLBACK TO SAVEPOINT {}", savepoint), &[]));
+            try!(self.execute_sql(&format!("RELEASE SAVEPOINT {}", savepoint), &[]));
+        }
+        self.transaction_depth().set(depth - 1);
+        Ok(())
+    }
 
-    /// determine if this transaction has been committed or rolledback
-    fn is_transacted(&self) -> bool;
+    /// determine if there is an ongoing transaction in this connection
+    fn is_transacted(&self) -> bool {
+        self.transaction_depth().get() > 0
+    }
 
     /// determine if the database connection closed
     fn is_closed(&self) -> bool;
@@ -171,19 +381,323 @@ pub trait Database {
 
     /// insert
     /// insert an object, returns the inserted Dao value
-    /// including the value generated via the defaults
+    /// including the value generated via the defaults,
+    /// when the returning clause is not supported the inserted record is selected back
     fn insert(&self, query: &Query) -> Result<Dao, DbError> {
-        let sql_frag = self.build_insert(query);
-        match self.execute_sql_with_one_return(&sql_frag.sql, &sql_frag.params) {
-            Ok(Some(result)) => Ok(result),
-            Ok(None) => Err(DbError::new("No result from insert")),
-            Err(e) => Err(e),
+        let sql_frag = try!(self.build_insert(query));
+        if self.sql_options().contains(&SqlOption::SupportsReturningClause) {
+            match self.execute_sql_with_one_return(&sql_frag.sql, &sql_frag.params) {
+                Ok(Some(result)) => Ok(result),
+                Ok(None) => Err(DbError::new("No result from insert")),
+                Err(e) => Err(e),
+            }
+        } else {
+            try!(self.execute_sql(&sql_frag.sql, &sql_frag.params));
+            let filters = try!(self.inserted_filters(query));
+            match try!(self.reselect(query, filters)) {
+                Some(result) => Ok(result),
+                None => Err(DbError::new("The inserted record is not found")),
+            }
         }
     }
 
+    /// the filters that match the record just inserted by the query on this connection,
+    /// used for selecting back the record when the returning clause is not supported
+    fn inserted_filters(&self, _query: &Query) -> Result<Vec<Filter>, DbError> {
+        Err(DbError::new("This database can not select back the inserted record"))
+    }
+
+    /// the primary columns of the table, used for selecting back the updated record
+    /// when the returning clause is not supported
+    fn primary_columns(&self, table: &TableName) -> Result<Vec<String>, DbError> {
+        Err(DbError::new(&format!("This database can not look up the primary columns of {}", table.name)))
+    }
+
+    /// the filters that match the first record to be updated by the query, looked up before the update,
+    /// primary columns changed by the update are matched with their new values.
+    /// None when no record matches the query
+    fn updated_filters(&self, query: &Query) -> Result<Option<Vec<Filter>>, DbError> {
+        let (table, qualifier) = match query.from {
+            Some(ref from) => {
+                match from.operand {
+                    Operand::TableName(ref table) => {
+                        let qualifier = from.name.clone().unwrap_or(table.name.to_owned());
+                        (table.clone(), qualifier)
+                    }
+                    _ => return Err(DbError::new("There should be a table to select back from")),
+                }
+            }
+            None => return Err(DbError::new("There should be a table to select back from")),
+        };
+        let keys = try!(self.primary_columns(&table));
+        if keys.is_empty() {
+            return Err(DbError::new(&format!("Table {} has no primary key to select back the updated record",
+                                             table.name)));
+        }
+        let mut matched = Query::select();
+        matched.from = query.from.clone();
+        matched.joins = query.joins.clone();
+        matched.filters = query.filters.clone();
+        for key in &keys {
+            let column = ColumnName {
+                column: key.to_owned(),
+                table: Some(qualifier.to_owned()),
+                schema: None,
+            };
+            matched.add_field(Field {
+                operand: Operand::ColumnName(column),
+                name: None,
+            });
+        }
+        matched.limit(1);
+        let dao = match try!(self.execute_with_one_return(&matched)) {
+            Some(dao) => dao,
+            None => return Ok(None),
+        };
+        let columns = query.get_enumerated_columns();
+        let mut filters = vec![];
+        for key in &keys {
+            let value = match columns.iter().position(|c| c.column == *key) {
+                Some(index) => {
+                    match query.values[index] {
+                        Operand::Value(_) => query.values[index].clone(),
+                        _ => {
+                            return Err(DbError::new(&format!("Can not select back the updated record, \
+                                                              the primary column {} is set to an expression",
+                                                             key)))
+                        }
+                    }
+                }
+                None => {
+                    match dao.values.get(key) {
+                        Some(value) => Operand::Value(value.clone()),
+                        None => return Err(DbError::new(&format!("The primary column {} is not selected", key))),
+                    }
+                }
+            };
+            filters.push(Filter::bare_new(Operand::ColumnName(ColumnName::from_str(key)), Equality::EQ, value));
+        }
+        Ok(Some(filters))
+    }
+
+    /// whether the keys generated for the rows of a multi-row insert are consecutive,
+    /// so the inserted records can be selected back with `inserted_range_filters`
+    fn generates_consecutive_keys(&self, _query: &Query) -> Result<bool, DbError> {
+        Ok(false)
+    }
+
+    /// the column ordering the records just inserted by the multi-row insert on this connection,
+    /// and the filters that match them,
+    /// used for selecting back the records whose primary key is generated by the database
+    fn inserted_range_filters(&self, _query: &Query, _rows: usize) -> Result<(String, Vec<Filter>), DbError> {
+        Err(DbError::new("This database can not select back the inserted records"))
+    }
+
+    /// whether every row of the insert gives a value to all the primary columns of the table,
+    /// a NULL key is generated by the database
+    fn is_key_given(&self, query: &Query) -> Result<bool, DbError> {
+        let table = match query.get_from_table() {
+            Some(table) => table.clone(),
+            None => {
+                return Err(DbError::from(QueryError::NoTableSpecified("There should be a table to select back from".to_owned())));
+            }
+        };
+        let keys = try!(self.primary_columns(&table));
+        let columns = query.get_enumerated_columns();
+        let rows = query.get_rows();
+        let is_given = |key: &String| {
+            match columns.iter().position(|c| c.column == *key) {
+                Some(index) => {
+                    rows.iter().all(|row| {
+                        match row[index] {
+                            Operand::Value(Value::None) => false,
+                            _ => true,
+                        }
+                    })
+                }
+                None => false,
+            }
+        };
+        Ok(!keys.is_empty() && keys.iter().all(is_given))
+    }
+
+    /// select back the records just inserted by the multi-row insert, in the order of its rows.
+    /// Records with the primary key given are matched with their key,
+    /// otherwise with the range of keys generated for the insert
+    fn select_inserted_rows(&self, query: &Query) -> Result<Vec<Dao>, DbError> {
+        let table = match query.get_from_table() {
+            Some(table) => table.clone(),
+            None => {
+                return Err(DbError::from(QueryError::NoTableSpecified("There should be a table to select back from".to_owned())));
+            }
+        };
+        let rows = query.get_rows();
+        let keys = try!(self.primary_columns(&table));
+        let columns = query.get_enumerated_columns();
+        let key_indexes: Vec<Option<usize>> = keys.iter()
+                                                  .map(|key| columns.iter().position(|c| c.column == *key))
+                                                  .collect();
+        let mut select = Query::select();
+        select.from_table(&table.complete_name());
+        for field in &query.enumerated_returns {
+            select.add_field(field.clone());
+        }
+        if !try!(self.is_key_given(query)) {
+            let (column, filters) = try!(self.inserted_range_filters(query, rows.len()));
+            select.add_filters(filters).asc(&column);
+            let inserted = try!(self.execute_with_return(&select)).dao;
+            if inserted.len() != rows.len() {
+                return Err(DbError::new("The inserted records are not found"));
+            }
+            return Ok(inserted);
+        }
+        // the first key narrows down the records, the rest of the key is matched below
+        let first = key_indexes[0].unwrap();
+        let values = rows.iter().map(|row| row[first].clone()).collect();
+        select.add_filter(Filter::bare_new(Operand::ColumnName(ColumnName::from_str(&keys[0])),
+                                           Equality::IN,
+                                           Operand::Vec(values)));
+        let found = try!(self.execute_with_return(&select)).dao;
+        let mut inserted = vec![];
+        for row in rows {
+            // the values are compared in their sql form, since the database may return a wider type
+            let matched = found.iter().find(|dao| {
+                keys.iter().zip(&key_indexes).all(|(key, index)| {
+                    match (dao.values.get(key), &row[index.unwrap()]) {
+                        (Some(found), &Operand::Value(ref given)) => found.to_string() == given.to_string(),
+                        _ => false,
+                    }
+                })
+            });
+            match matched {
+                Some(dao) => inserted.push(dao.clone()),
+                None => return Err(DbError::new("The inserted record is not found")),
+            }
+        }
+        Ok(inserted)
+    }
+
+    /// select the returning fields of the insert/update query, from the records that match the filters
+    fn reselect(&self, query: &Query, filters: Vec<Filter>) -> Result<Option<Dao>, DbError> {
+        let table = match query.get_from_table() {
+            Some(table) => table.complete_name(),
+            None => {
+                return Err(DbError::from(QueryError::NoTableSpecified("There should be a table to select back from".to_owned())));
+            }
+        };
+        let mut select = Query::select();
+        select.from_table(&table);
+        for field in &query.enumerated_returns {
+            select.add_field(field.clone());
+        }
+        select.add_filters(filters);
+        select.finalize();
+        self.execute_with_one_return(&select)
+    }
+
+    /// insert the rows of the query, split into as many statements as the parameter limit requires,
+    /// returns the inserted records when the database supports the returning clause
+    fn insert_batch(&self, query: &Query) -> Result<Vec<Dao>, DbError> {
+        if !query.enumerated_returns.is_empty() && query.source.is_none() &&
+           !self.sql_options().contains(&SqlOption::SupportsReturningClause) {
+            let mut inserted = vec![];
+            if !try!(self.is_key_given(query)) && !try!(self.generates_consecutive_keys(query)) {
+                // one record at a time, each selected back with the key generated for it
+                for row in query.split_rows(1) {
+                    inserted.push(try!(self.insert(&row)));
+                }
+                return Ok(inserted);
+            }
+            for chunk in self.insert_chunks(query) {
+                let frag = try!(self.build_insert(&chunk));
+                try!(self.execute_sql(&frag.sql, &frag.params));
+                inserted.extend(try!(self.select_inserted_rows(&chunk)));
+            }
+            return Ok(inserted);
+        }
+        let returns = !query.enumerated_returns.is_empty() &&
+                      self.sql_options().contains(&SqlOption::SupportsReturningClause);
+        let mut inserted = vec![];
+        for frag in try!(self.build_insert_batch(query)) {
+            if returns {
+                let dao = try!(self.execute_sql_with_return(&frag.sql, &frag.params));
+                inserted.extend(dao);
+            } else {
+                try!(self.execute_sql(&frag.sql, &frag.params));
+            }
+        }
+        Ok(inserted)
+    }
+
+    /// insert the records into the table in as few round trips as possible,
+    /// returns the number of inserted records.
+    /// The records are inserted in batches under the parameter limit of the database,
+    /// platforms with a bulk loading facility such as COPY override this
+    fn bulk_insert(&self,
+                   table: &str,
+                   columns: &[&str],
+                   rows: &mut Iterator<Item = Dao>)
+                   -> Result<usize, DbError> {
+        let rows_per_query = cmp::max(1, self.max_params() / cmp::max(1, columns.len()));
+        let mut count = 0;
+        loop {
+            let mut query = Query::insert();
+            query.into_table(table);
+            query.columns(columns.to_vec());
+            let mut batch = 0;
+            while batch < rows_per_query {
+                match rows.next() {
+                    Some(dao) => {
+                        let row = columns.iter()
+                                         .map(|c| {
+                                             match dao.values.get(*c) {
+                                                 Some(value) => Operand::Value(value.clone()),
+                                                 None => Operand::Value(Value::None),
+                                             }
+                                         })
+                                         .collect();
+                        query.add_row(row);
+                        batch += 1;
+                    }
+                    None => break,
+                }
+            }
+            if batch == 0 {
+                break;
+            }
+            query.finalize();
+            let frag = try!(self.build_insert(&query));
+            count += try!(self.execute_sql(&frag.sql, &frag.params));
+            if batch < rows_per_query {
+                break;
+            }
+        }
+        Ok(count)
+    }
+
     /// update
-    /// returns the updated Dao
-    fn update(&self, query: &Query) -> Dao;
+    /// returns the updated Dao,
+    /// when the returning clause is not supported the first matched record is selected back by its primary key
+    fn update(&self, query: &Query) -> Result<Dao, DbError> {
+        let sql_frag = try!(self.build_update(query));
+        let updated = if self.sql_options().contains(&SqlOption::SupportsReturningClause) {
+            try!(self.execute_sql_with_one_return(&sql_frag.sql, &sql_frag.params))
+        } else {
+            // the filters may no longer match once the record is updated,
+            // so the primary key of the record is looked up first
+            match try!(self.updated_filters(query)) {
+                Some(filters) => {
+                    try!(self.execute_sql(&sql_frag.sql, &sql_frag.params));
+                    try!(self.reselect(query, filters))
+                }
+                None => None,
+            }
+        };
+        match updated {
+            Some(result) => Ok(result),
+            None => Err(DbError::new("No record has been updated")),
+        }
+    }
 
     /// delete records
     /// returns the number of deleted records
@@ -192,7 +706,7 @@ pub trait Database {
     /// execute query with return dao,
     /// use the enumerated column for data extraction when db doesn't support returning the records column names
     fn execute_with_return(&self, query: &Query) -> Result<DaoResult, DbError> {
-        let sql_frag = &self.build_query(query);
+        let sql_frag = &try!(self.build_query(query));
         let result = try!(self.execute_sql_with_return(&sql_frag.sql, &sql_frag.params));
         let dao_result = DaoResult {
             dao: result,
@@ -200,19 +714,21 @@ pub trait Database {
             total: None,
             page: None,
             page_size: None,
+            next_cursor: None,
+            prev_cursor: None,
         };
         Ok(dao_result)
     }
 
     /// execute query with 1 return dao
     fn execute_with_one_return(&self, query: &Query) -> Result<Option<Dao>, DbError> {
-        let sql_frag = &self.build_query(query);
+        let sql_frag = &try!(self.build_query(query));
         self.execute_sql_with_one_return(&sql_frag.sql, &sql_frag.params)
     }
 
     /// execute query with no return dao
     fn execute(&self, query: &Query) -> Result<usize, DbError> {
-        let sql_frag = &self.build_query(query);
+        let sql_frag = &try!(self.build_query(query));
         self.execute_sql(&sql_frag.sql, &sql_frag.params)
     }
 
@@ -234,11 +750,22 @@ pub trait Database {
     /// everything else, no required return other than error or affected number of records
     fn execute_sql(&self, sql: &str, param: &[Value]) -> Result<usize, DbError>;
 
+    /// execute several sql statements separated by `;`, without parameters
+    /// ie: the content of a migration file.
+    /// See `migration::split_statements` for how the statements are split,
+    /// platforms which can execute a batch natively override this
+    fn execute_batch(&self, sql: &str) -> Result<(), DbError> {
+        for statement in split_statements(sql) {
+            try!(self.execute_sql(&statement, &[]));
+        }
+        Ok(())
+    }
+
     /// build a query, return the sql string and the parameters.
     /// use by select to build the select query
     /// build all types of query
     /// TODO: need to supply the number of parameters where to start the numbering of the number parameters
-    fn build_query(&self, query: &Query) -> SqlFrag {
+    fn build_query(&self, query: &Query) -> Result<SqlFrag, DbError> {
         match query.sql_type {
             SqlType::SELECT => self.build_select(query),
             SqlType::INSERT => self.build_insert(query),
@@ -248,10 +775,14 @@ pub trait Database {
     }
 
     /// build operand, i.e: columns, query, function, values
-    fn build_operand(&self, w: &mut SqlFrag, parent_query: &Query, operand: &Operand) {
+    fn build_operand(&self,
+                     w: &mut SqlFrag,
+                     parent_query: &Query,
+                     operand: &Operand)
+                     -> Result<(), DbError> {
         match *operand {
             Operand::ColumnName(ref column_name) => {
-                if parent_query.joins.is_empty() {
+                if parent_query.joins.is_empty() && !is_outer_column(parent_query, column_name) {
                     w.append(&column_name.column);
                 } else {
                     w.append(&column_name.complete_name());
@@ -265,6 +796,7 @@ pub trait Database {
                 }
             }
             Operand::Function(ref function) => {
+                w.append(&function.function);
                 w.append("(");
                 let mut do_comma = false;
                 for param in &function.params {
@@ -273,15 +805,22 @@ pub trait Database {
                     } else {
                         do_comma = true;
                     }
-                    self.build_operand(w, parent_query, param);
+                    try!(self.build_operand(w, parent_query, param));
                 }
                 w.append(")");
+                if let Some(ref window) = function.over {
+                    try!(self.build_window(w, parent_query, window));
+                }
             }
-            Operand::Query(ref _q) => {
-                panic!("TODO: causes error Attributes 'readnone and readonly' are incompatible! \
-                        LLVM ERROR: Broken function found, compilation aborted!")
-                //let sql_frag = &self.build_query(&q);
-                //w.append(&sql_frag.sql);
+            Operand::Query(ref query) => {
+                match query.sql_type {
+                    SqlType::SELECT => (),
+                    _ => return Err(DbError::from(QueryError::SqlError("Only SELECT queries can be used as operand".to_owned()))),
+                }
+                w.append("(");
+                try!(self.write_select(w, query));
+                w.ln();
+                w.append(")");
             }
             Operand::Value(ref value) => {
                 w.parameter(value.clone());
@@ -297,57 +836,184 @@ pub trait Database {
                         } else {
                             do_comma = true;
                         }
-                        self.build_operand(w, parent_query, op);
+                        try!(self.build_operand(w, parent_query, op));
                     }
                     w.append(")");
                 }
             }
+            Operand::Case(ref case) => {
+                if case.when.is_empty() {
+                    return Err(DbError::from(QueryError::SqlError("CASE should have at least one WHEN".to_owned())));
+                }
+                w.append("CASE");
+                for &(ref filter, ref then) in &case.when {
+                    w.append(" WHEN ");
+                    try!(self.build_filter(w, parent_query, filter));
+                    space_after(w);
+                    w.append("THEN ");
+                    try!(self.build_operand(w, parent_query, then));
+                }
+                if let Some(ref else_) = case.else_ {
+                    space_after(w);
+                    w.append("ELSE ");
+                    try!(self.build_operand(w, parent_query, else_));
+                }
+                space_after(w);
+                w.append("END");
+            }
+            Operand::Sql(ref sql, ref params) => {
+                let parts = split_placeholders(sql);
+                if parts.len() != params.len() + 1 {
+                    return Err(DbError::from(QueryError::SqlError(format!("The sql `{}` has {} placeholders but {} parameters",
+                                                                          sql,
+                                                                          parts.len() - 1,
+                                                                          params.len()))));
+                }
+                for (i, part) in parts.iter().enumerate() {
+                    w.append(part);
+                    if i < params.len() {
+                        w.parameter(params[i].clone());
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// the OVER clause of a window function
+    fn build_window(&self,
+                    w: &mut SqlFrag,
+                    parent_query: &Query,
+                    window: &Window)
+                    -> Result<(), DbError> {
+        if !self.sql_options().contains(&SqlOption::SupportsWindowFunction) {
+            return Err(DbError::from(QueryError::SqlError("This database does not support window functions".to_owned())));
+        }
+        w.append(" OVER (");
+        if !window.partition_by.is_empty() {
+            w.append("PARTITION BY ");
+            let mut do_comma = false;
+            for operand in &window.partition_by {
+                if do_comma {
+                    w.commasp();
+                } else {
+                    do_comma = true;
+                }
+                try!(self.build_operand(w, parent_query, operand));
+            }
+        }
+        if !window.order_by.is_empty() {
+            if !window.partition_by.is_empty() {
+                w.sp();
+            }
+            w.append("ORDER BY ");
+            let mut do_comma = false;
+            for &(ref column, ref direction) in &window.order_by {
+                if do_comma {
+                    w.commasp();
+                } else {
+                    do_comma = true;
+                }
+                w.append(column);
+                match *direction {
+                    Direction::ASC => w.append(" ASC"),
+                    Direction::DESC => w.append(" DESC"),
+                };
+            }
         }
+        if let Some(ref frame) = window.frame {
+            if !window.partition_by.is_empty() || !window.order_by.is_empty() {
+                w.sp();
+            }
+            match frame.unit {
+                FrameUnit::ROWS => w.append("ROWS "),
+                FrameUnit::RANGE => w.append("RANGE "),
+            };
+            match frame.end {
+                Some(ref end) => {
+                    w.append("BETWEEN ");
+                    w.append(&frame_bound(&frame.start));
+                    w.append(" AND ");
+                    w.append(&frame_bound(end));
+                }
+                None => {
+                    w.append(&frame_bound(&frame.start));
+                }
+            }
+        }
+        w.append(")");
+        Ok(())
     }
 
-    fn build_condition(&self, w: &mut SqlFrag, parent_query: &Query, cond: &Condition) {
-        self.build_operand(w, parent_query, &cond.left);
+    fn build_condition(&self,
+                       w: &mut SqlFrag,
+                       parent_query: &Query,
+                       cond: &Condition)
+                       -> Result<(), DbError> {
+        match cond.equality {
+            Equality::EXISTS | Equality::NOT_EXISTS => {
+                match cond.equality {
+                    Equality::EXISTS => w.append("EXISTS "),
+                    _ => w.append("NOT EXISTS "),
+                };
+                match cond.right {
+                    Operand::Query(_) => try!(self.build_operand(w, parent_query, &cond.right)),
+                    _ => return Err(DbError::from(QueryError::SqlError("EXISTS expects a query".to_owned()))),
+                }
+                return Ok(());
+            }
+            Equality::ILIKE if !self.sql_options().contains(&SqlOption::SupportsILike) => {
+                w.append("LOWER(");
+                try!(self.build_operand(w, parent_query, &cond.left));
+                w.append(") LIKE LOWER(");
+                try!(self.build_operand(w, parent_query, &cond.right));
+                w.append(")");
+                return Ok(());
+            }
+            _ => (),
+        }
+        try!(self.build_operand(w, parent_query, &cond.left));
         w.append(" ");
         match cond.equality {
             Equality::EQ => {
                 w.append("= ");
-                self.build_operand(w, parent_query, &cond.right);
+                try!(self.build_operand(w, parent_query, &cond.right));
             }
             Equality::NEQ => {
                 w.append("!= ");
-                self.build_operand(w, parent_query, &cond.right);
+                try!(self.build_operand(w, parent_query, &cond.right));
             }
             Equality::LT => {
                 w.append("< ");
-                self.build_operand(w, parent_query, &cond.right);
+                try!(self.build_operand(w, parent_query, &cond.right));
             }
             Equality::LTE => {
                 w.append("<= ");
-                self.build_operand(w, parent_query, &cond.right);
+                try!(self.build_operand(w, parent_query, &cond.right));
             }
             Equality::GT => {
                 w.append("> ");
-                self.build_operand(w, parent_query, &cond.right);
+                try!(self.build_operand(w, parent_query, &cond.right));
             }
             Equality::GTE => {
                 w.append(">= ");
-                self.build_operand(w, parent_query, &cond.right);
+                try!(self.build_operand(w, parent_query, &cond.right));
             }
             Equality::IN => {
                 w.append("IN ");
-                self.build_operand(w, parent_query, &cond.right);
+                try!(self.build_operand(w, parent_query, &cond.right));
             }
             Equality::NOT_IN => {
                 w.append("NOT IN ");
-                self.build_operand(w, parent_query, &cond.right);
+                try!(self.build_operand(w, parent_query, &cond.right));
             }
             Equality::LIKE => {
                 w.append("LIKE ");
-                self.build_operand(w, parent_query, &cond.right);
+                try!(self.build_operand(w, parent_query, &cond.right));
             }
             Equality::ILIKE => {
                 w.append("ILIKE ");
-                self.build_operand(w, parent_query, &cond.right);
+                try!(self.build_operand(w, parent_query, &cond.right));
             }
             Equality::IS_NOT_NULL => {
                 w.append("IS NOT NULL");
@@ -356,11 +1022,32 @@ pub trait Database {
             Equality::IS_NULL => {
                 w.append("IS NULL");
             }
+            Equality::BETWEEN | Equality::NOT_BETWEEN => {
+                match cond.equality {
+                    Equality::BETWEEN => w.append("BETWEEN "),
+                    _ => w.append("NOT BETWEEN "),
+                };
+                match cond.right {
+                    Operand::Vec(ref range) if range.len() == 2 => {
+                        try!(self.build_operand(w, parent_query, &range[0]));
+                        space_after(w);
+                        w.append("AND ");
+                        try!(self.build_operand(w, parent_query, &range[1]));
+                    }
+                    _ => return Err(DbError::from(QueryError::SqlError("BETWEEN expects the lower and upper bound".to_owned()))),
+                }
+            }
+            Equality::EXISTS | Equality::NOT_EXISTS => unreachable!(),
         }
+        Ok(())
     }
 
-    fn build_field(&self, w: &mut SqlFrag, parent_query: &Query, field: &Field) {
-        self.build_operand(w, parent_query, &field.operand);
+    fn build_field(&self,
+                   w: &mut SqlFrag,
+                   parent_query: &Query,
+                   field: &Field)
+                   -> Result<(), DbError> {
+        try!(self.build_operand(w, parent_query, &field.operand));
         match field.name {
             Some(ref name) => {
                 w.append(" AS ");
@@ -368,15 +1055,23 @@ pub trait Database {
             }
             None => (),
         }
+        Ok(())
     }
 
 
-    fn build_filter(&self, w: &mut SqlFrag, parent_query: &Query, filter: &Filter) {
-        if !filter.sub_filters.is_empty() {
+    fn build_filter(&self,
+                    w: &mut SqlFrag,
+                    parent_query: &Query,
+                    filter: &Filter)
+                    -> Result<(), DbError> {
+        if filter.negated {
+            w.append("NOT ( ");
+        } else if !filter.sub_filters.is_empty() {
             w.append("( ");
         }
-        self.build_condition(w, parent_query, &filter.condition);
+        try!(self.build_condition(w, parent_query, &filter.condition));
         for filt in &filter.sub_filters {
+            space_after(w);
             match filt.connector {
                 Connector::And => {
                     w.append("AND ");
@@ -385,16 +1080,22 @@ pub trait Database {
                     w.append("OR ");
                 }
             }
-            self.build_filter(w, parent_query, filt);// build sub filters as well
+            try!(self.build_filter(w, parent_query, filt));// build sub filters as well
         }
-        if !filter.sub_filters.is_empty() {
-            w.append(" )");
+        if filter.negated || !filter.sub_filters.is_empty() {
+            space_after(w);
+            w.append(")");
         }
+        Ok(())
     }
 
     /// build the filter clause or the where clause of the query
     /// TODO: add the sub filters
-    fn build_filters(&self, w: &mut SqlFrag, parent_query: &Query, filters: &[Filter]) {
+    fn build_filters(&self,
+                     w: &mut SqlFrag,
+                     parent_query: &Query,
+                     filters: &[Filter])
+                     -> Result<(), DbError> {
         let mut do_and = false;
         for filter in filters {
             if do_and {
@@ -402,15 +1103,17 @@ pub trait Database {
             } else {
                 do_and = true;
             }
-            self.build_filter(w, parent_query, filter);
+            try!(self.build_filter(w, parent_query, filter));
         }
+        Ok(())
     }
 
     /// build the enumerated, distinct, *, columns
     fn build_enumerated_fields(&self,
                                w: &mut SqlFrag,
                                parent_query: &Query,
-                               enumerated_fields: &[Field]) {
+                               enumerated_fields: &[Field])
+                               -> Result<(), DbError> {
         let mut do_comma = false;
         let mut cnt = 0;
         for field in enumerated_fields {
@@ -423,75 +1126,107 @@ pub trait Database {
             if cnt % 4 == 0 {//break at every 4 columns to encourage sql tuning/revising
                 w.left_river("");
             }
-            self.build_field(w, parent_query, field);
+            try!(self.build_field(w, parent_query, field));
         }
+        Ok(())
     }
 
     /// build the select statment from the query object
-    fn build_select(&self, query: &Query) -> SqlFrag {
+    fn build_select(&self, query: &Query) -> Result<SqlFrag, DbError> {
         let mut w = SqlFrag::new(self.sql_options());
+        try!(self.write_select(&mut w, query));
+        Ok(w)
+    }
+
+    /// write the select query into the writer,
+    /// used when the select is part of another query so the parameters are numbered continuously
+    fn write_select(&self, w: &mut SqlFrag, query: &Query) -> Result<(), DbError> {
+        if !query.declared_query.is_empty() {
+            try!(self.write_declared_queries(w, query));
+        }
+        try!(self.write_select_core(w, query));
+        for &(ref operation, ref combined) in &query.combined {
+            try!(self.write_combined(w, operation, combined));
+        }
+        try!(self.write_order_limit(w, query));
+        if let Some(ref lock) = query.lock {
+            if !query.combined.is_empty() {
+                return Err(DbError::from(QueryError::SqlError("Locking is not allowed on combined queries".to_owned())));
+            }
+            try!(self.write_lock(w, lock));
+        }
+        Ok(())
+    }
+
+    /// the locking of the selected rows, ie: FOR UPDATE OF product SKIP LOCKED
+    fn write_lock(&self, w: &mut SqlFrag, lock: &Lock) -> Result<(), DbError> {
+        if !self.sql_options().contains(&SqlOption::SupportsRowLocking) {
+            return Err(DbError::from(QueryError::SqlError("This database does not support locking the selected rows, ie: FOR UPDATE".to_owned())));
+        }
+        if self.sql_options().contains(&SqlOption::UsesLockInShareMode) {
+            if !lock.tables.is_empty() || lock.wait.is_some() {
+                return Err(DbError::from(QueryError::SqlError("This database does not support OF, NOWAIT or SKIP LOCKED when locking the selected rows".to_owned())));
+            }
+            match lock.strength {
+                LockStrength::UPDATE => w.left_river("FOR").append("UPDATE"),
+                LockStrength::SHARE => w.left_river("LOCK").append("IN SHARE MODE"),
+            };
+            return Ok(());
+        }
+        w.left_river("FOR");
+        match lock.strength {
+            LockStrength::UPDATE => w.append("UPDATE"),
+            LockStrength::SHARE => w.append("SHARE"),
+        };
+        if !lock.tables.is_empty() {
+            w.append(" OF ");
+            w.append(&lock.tables.join(", "));
+        }
+        match lock.wait {
+            Some(LockWait::NOWAIT) => {
+                w.append(" NOWAIT");
+            }
+            Some(LockWait::SKIP_LOCKED) => {
+                w.append(" SKIP LOCKED");
+            }
+            None => (),
+        }
+        Ok(())
+    }
+
+    /// write the select query without the ORDER BY and LIMIT,
+    /// which are applied to the combined result when there are set operations
+    fn write_select_core(&self, w: &mut SqlFrag, query: &Query) -> Result<(), DbError> {
         w.left_river("SELECT");
-        self.build_enumerated_fields(&mut w, query, &query.enumerated_fields); //TODO: add support for column_sql, fields, functions
+        try!(self.build_enumerated_fields(w, query, &query.enumerated_fields)); //TODO: add support for column_sql, fields, functions
         w.left_river("FROM");
 
-        assert!(query.from.is_some(),
-                "There should be table, query, function to select from");
-
         match query.from {
             Some(ref field) => {
-                self.build_field(&mut w, query, field);
+                try!(self.build_field(w, query, field));
             }
-            None => println!("Warning: No from in this query"),
-        }
-        if !query.joins.is_empty() {
-            for join in &query.joins {
-                match join.modifier {
-                    Some(ref modifier) => {
-                        match *modifier {
-                            Modifier::LEFT => w.right_river("LEFT "),
-                            Modifier::RIGHT => w.right_river("RIGHT "),
-                            Modifier::FULL => w.right_river("FULL "),
-                        };
-                    }
-                    None => (),
-                }
-                match join.join_type {
-                    Some(ref join_type) => {
-                        match *join_type {
-                            JoinType::CROSS => w.right_river("CROSS "),
-                            JoinType::INNER => w.right_river("INNER "),
-                            JoinType::OUTER => w.right_river("OUTER "),
-                            JoinType::NATURAL => w.right_river("NATURAL "),
-                        };
-                    }
-                    None => (),
-                }
-                w.append("JOIN ");
-                w.append(&join.table_name.complete_name());
-                w.append(" ");
-                assert!(join.column1.len() == join.column2.len(),
-                        "There should be equal number of corresponding columns to join");
-                let mut cnt = 0;
-                let mut do_and = false;
-                for jc in &join.column1 {
-                    if do_and {
-                        w.right_river("AND ");
-                    } else {
-                        w.right_river("ON ");
-                        do_and = true;
-                    }
-                    w.append(jc);
-                    w.append(" = ");
-                    w.append(&join.column2[cnt]);
-                    w.append(" ");
-                    cnt += 1;
-                }
+            None => {
+                return Err(DbError::from(QueryError::NoTableSpecified("There should be table, query, function to select from".to_owned())));
             }
         }
+        for join in &query.joins {
+            try!(self.build_join(w, query, join));
+        }
 
         if !query.filters.is_empty() {
             w.left_river("WHERE ");
-            self.build_filters(&mut w, query, &query.filters);
+            try!(self.build_filters(w, query, &query.filters));
+        }
+
+        if let Some(Range::Keyset(ref keyset)) = query.range {
+            if !keyset.values.is_empty() {
+                if query.filters.is_empty() {
+                    w.left_river("WHERE ");
+                } else {
+                    w.left_river("AND ");
+                }
+                try!(self.build_keyset(w, query, keyset));
+            }
         }
 
         if !query.group_by.is_empty() {
@@ -503,7 +1238,7 @@ pub trait Database {
                 } else {
                     do_comma = true;
                 }
-                self.build_operand(&mut w, query, operand);
+                try!(self.build_operand(w, query, operand));
                 w.append(" ");
             }
         }
@@ -517,10 +1252,228 @@ pub trait Database {
                 } else {
                     do_comma = true;
                 }
-                self.build_filter(&mut w, query, hav);
+                try!(self.build_filter(w, query, hav));
             }
         }
 
+        Ok(())
+    }
+
+    /// the condition seeking past the last seen values of the ORDER BY columns,
+    /// a row comparison when the columns are ordered the same way, ie: (name, product_id) > ($1, $2),
+    /// otherwise expanded, ie: (name > $1 OR (name = $2 AND product_id < $3))
+    fn build_keyset(&self, w: &mut SqlFrag, query: &Query, keyset: &Keyset) -> Result<(), DbError> {
+        if query.order_by.is_empty() {
+            return Err(DbError::from(QueryError::SqlError("Keyset paging needs the ORDER BY columns to seek from".to_owned())));
+        }
+        if !query.combined.is_empty() {
+            return Err(DbError::from(QueryError::SqlError("Keyset paging is not supported on combined queries".to_owned())));
+        }
+        // aggregates can not be compared in WHERE, they would need HAVING
+        if !query.group_by.is_empty() || query.order_by.iter().any(|&(ref column, _)| is_aggregate(column)) {
+            return Err(DbError::from(QueryError::SqlError("Keyset paging is not supported on grouped queries".to_owned())));
+        }
+        if keyset.values.len() != query.order_by.len() {
+            return Err(DbError::from(QueryError::SqlError("There should be a keyset value for each ORDER BY column".to_owned())));
+        }
+        // nothing compares greater or less than NULL
+        if keyset.values.iter().any(|value| *value == Value::None) {
+            return Err(DbError::from(QueryError::SqlError("Keyset paging can not seek from a NULL value".to_owned())));
+        }
+        let operators: Vec<&str> = query.order_by
+                                        .iter()
+                                        .map(|&(_, ref direction)| {
+                                            let ascending = match *direction {
+                                                Direction::ASC => true,
+                                                Direction::DESC => false,
+                                            };
+                                            if ascending != keyset.backward { ">" } else { "<" }
+                                        })
+                                        .collect();
+        if operators.len() == 1 {
+            w.append(&query.order_by[0].0);
+            w.append(&format!(" {} ", operators[0]));
+            w.parameter(keyset.values[0].clone());
+        } else if operators.iter().all(|op| *op == operators[0]) {
+            w.append("(");
+            let columns: Vec<&str> = query.order_by.iter().map(|&(ref column, _)| column as &str).collect();
+            w.append(&columns.join(", "));
+            w.append(&format!(") {} (", operators[0]));
+            let mut do_comma = false;
+            for value in &keyset.values {
+                if do_comma {
+                    w.commasp();
+                } else {
+                    do_comma = true;
+                }
+                w.parameter(value.clone());
+            }
+            w.append(")");
+        } else {
+            w.append("(");
+            for i in 0..operators.len() {
+                if i > 0 {
+                    w.append("OR (");
+                }
+                for j in 0..i {
+                    w.append(&query.order_by[j].0);
+                    w.append(" = ");
+                    w.parameter(keyset.values[j].clone());
+                    w.append("AND ");
+                }
+                w.append(&query.order_by[i].0);
+                w.append(&format!(" {} ", operators[i]));
+                w.parameter(keyset.values[i].clone());
+                if i > 0 {
+                    w.append(")");
+                }
+            }
+            w.append(")");
+        }
+        Ok(())
+    }
+
+    /// build the join of a table, ie: LEFT JOIN table alias ON column1 = column2,
+    /// CROSS and NATURAL joins have no ON condition
+    fn build_join(&self, w: &mut SqlFrag, parent_query: &Query, join: &Join) -> Result<(), DbError> {
+        w.right_river("");
+        match join.modifier {
+            Some(ref modifier) => {
+                match *modifier {
+                    Modifier::LEFT => w.append("LEFT "),
+                    Modifier::RIGHT => w.append("RIGHT "),
+                    Modifier::FULL => w.append("FULL "),
+                };
+            }
+            None => (),
+        }
+        let mut has_no_condition = false;
+        match join.join_type {
+            Some(ref join_type) => {
+                match *join_type {
+                    JoinType::CROSS => {
+                        w.append("CROSS ");
+                        has_no_condition = true;
+                    }
+                    JoinType::INNER => {
+                        w.append("INNER ");
+                    }
+                    JoinType::OUTER => {
+                        w.append("OUTER ");
+                    }
+                    JoinType::NATURAL => {
+                        w.append("NATURAL ");
+                        has_no_condition = true;
+                    }
+                }
+            }
+            None => (),
+        }
+        w.append("JOIN ");
+        w.append(&join.table_name.complete_name());
+        w.append(" ");
+        if let Some(ref alias) = join.alias {
+            w.append(alias);
+            w.append(" ");
+        }
+        if join.column1.len() != join.column2.len() {
+            return Err(DbError::from(QueryError::NoColumnSpecified("There should be equal number of corresponding columns to join".to_owned())));
+        }
+        let has_condition = !join.column1.is_empty() || !join.on.is_empty();
+        if has_no_condition {
+            if has_condition {
+                return Err(DbError::from(QueryError::SqlError("CROSS and NATURAL joins can not have ON conditions".to_owned())));
+            }
+            return Ok(());
+        }
+        if !has_condition {
+            return Err(DbError::from(QueryError::NoColumnSpecified("There should be a condition to join on".to_owned())));
+        }
+        let mut do_and = false;
+        for (column1, column2) in join.column1.iter().zip(join.column2.iter()) {
+            if do_and {
+                w.right_river("AND ");
+            } else {
+                w.right_river("ON ");
+                do_and = true;
+            }
+            w.append(column1);
+            w.append(" = ");
+            w.append(column2);
+            w.append(" ");
+        }
+        for filter in &join.on {
+            if do_and {
+                w.right_river("AND ");
+            } else {
+                w.right_river("ON ");
+                do_and = true;
+            }
+            try!(self.build_filter(w, parent_query, filter));
+        }
+        Ok(())
+    }
+
+    /// write the query combined with UNION, UNION ALL, INTERSECT or EXCEPT
+    fn write_combined(&self,
+                      w: &mut SqlFrag,
+                      operation: &SetOperation,
+                      combined: &Query)
+                      -> Result<(), DbError> {
+        match combined.sql_type {
+            SqlType::SELECT => (),
+            _ => return Err(DbError::from(QueryError::SqlError("Only SELECT queries can be combined".to_owned()))),
+        }
+        if !combined.order_by.is_empty() || combined.range.is_some() {
+            return Err(DbError::from(QueryError::SqlError("ORDER BY and LIMIT of a combined query should be set on the first query".to_owned())));
+        }
+        if !combined.declared_query.is_empty() {
+            return Err(DbError::from(QueryError::SqlError("WITH queries should be declared on the first query".to_owned())));
+        }
+        match *operation {
+            SetOperation::INTERSECT | SetOperation::EXCEPT => {
+                if !self.sql_options().contains(&SqlOption::SupportsIntersect) {
+                    return Err(DbError::from(QueryError::SqlError("This database does not support INTERSECT and EXCEPT".to_owned())));
+                }
+            }
+            _ => (),
+        }
+        // the combinations of the combined query are evaluated first,
+        // flattening them would combine them with everything before instead
+        let nested = !combined.combined.is_empty();
+        if nested && !self.sql_options().contains(&SqlOption::SupportsNestedCombine) {
+            return Err(DbError::from(QueryError::SqlError("This database does not support combining a combined query, use it in from_query instead".to_owned())));
+        }
+        match *operation {
+            SetOperation::UNION => w.left_river("UNION"),
+            SetOperation::UNION_ALL => w.left_river("UNION ALL"),
+            SetOperation::INTERSECT => w.left_river("INTERSECT"),
+            SetOperation::EXCEPT => w.left_river("EXCEPT"),
+        };
+        if nested {
+            w.append("(");
+        }
+        try!(self.write_select_core(w, combined));
+        for &(ref operation, ref combined) in &combined.combined {
+            try!(self.write_combined(w, operation, combined));
+        }
+        if nested {
+            w.ln();
+            w.append(")");
+        }
+        Ok(())
+    }
+
+    /// the ORDER BY and LIMIT of the select
+    fn write_order_limit(&self, w: &mut SqlFrag, query: &Query) -> Result<(), DbError> {
+        if let Some(ref error) = query.range_error {
+            return Err(DbError::from(QueryError::SqlError(error.to_owned())));
+        }
+        // seeking backward reads the records in reverse, they are put back in order when retrieved
+        let reversed = match query.range {
+            Some(Range::Keyset(ref keyset)) => keyset.backward,
+            _ => false,
+        };
         if !query.order_by.is_empty() {
             w.left_river("ORDER BY ");
             let mut do_comma = false;
@@ -531,9 +1484,9 @@ pub trait Database {
                     do_comma = true;
                 }
                 w.append(&column);
-                match *direction {
-                    Direction::ASC => w.append(" ASC"),
-                    Direction::DESC => w.append(" DESC"),
+                match (direction, reversed) {
+                    (&Direction::ASC, false) | (&Direction::DESC, true) => w.append(" ASC"),
+                    (&Direction::DESC, false) | (&Direction::ASC, true) => w.append(" DESC"),
                 };
             }
         }
@@ -552,44 +1505,155 @@ pub trait Database {
             },
             None => (),
         }
-        w
+        Ok(())
+    }
+
+    /// write the declared queries ahead of the select, ie: WITH alias AS (SELECT ..)
+    fn write_declared_queries(&self, w: &mut SqlFrag, query: &Query) -> Result<(), DbError> {
+        if !self.sql_options().contains(&SqlOption::SupportsCTE) {
+            return Err(DbError::from(QueryError::SqlError("This database does not support WITH queries".to_owned())));
+        }
+        if query.recursive {
+            w.left_river("WITH RECURSIVE");
+        } else {
+            w.left_river("WITH");
+        }
+        let mut do_comma = false;
+        for &(ref alias, ref declared) in &query.declared_query {
+            if do_comma {
+                w.comma();
+                w.right_river("");
+            } else {
+                do_comma = true;
+            }
+            match declared.sql_type {
+                SqlType::SELECT => (),
+                _ => {
+                    return Err(DbError::from(QueryError::SqlError("Only SELECT queries can be declared in WITH".to_owned())));
+                }
+            }
+            w.append(alias);
+            w.append(" AS (");
+            try!(self.write_select(w, declared));
+            w.ln();
+            w.append(")");
+        }
+        Ok(())
     }
 
     /// TODO complete this
-    fn build_insert(&self, query: &Query) -> SqlFrag {
+    fn build_insert(&self, query: &Query) -> Result<SqlFrag, DbError> {
         println!("building insert query");
         let mut w = SqlFrag::new(self.sql_options());
+        let is_upsert = !query.on_conflict.is_empty();
+        let uses_on_conflict = self.sql_options().contains(&SqlOption::SupportsOnConflict);
+        let uses_on_duplicate_key = self.sql_options().contains(&SqlOption::SupportsOnDuplicateKey);
+        if is_upsert && !uses_on_conflict && !uses_on_duplicate_key {
+            return Err(DbError::from(QueryError::SqlError("This database does not support upsert".to_owned())));
+        }
         w.left_river("INSERT");
         w.append("INTO ");
-        let into_table = query.get_from_table();
-        assert!(into_table.is_some(), "There should be table to insert to");
-        if let Some(table_name) = into_table {
-            if self.sql_options().contains(&SqlOption::UsesSchema) {
-                w.append(&table_name.complete_name());
-            } else {
-                w.append(&table_name.name);
+        match query.get_from_table() {
+            Some(table_name) => {
+                if self.sql_options().contains(&SqlOption::UsesSchema) {
+                    w.append(&table_name.complete_name());
+                } else {
+                    w.append(&table_name.name);
+                }
+            }
+            None => {
+                return Err(DbError::from(QueryError::NoTableSpecified("There should be table to insert to".to_owned())));
             }
         }
 
 
         w.append("( ");
-        self.build_enumerated_fields(&mut w, query, &query.enumerated_fields); //TODO: add support for column_sql, fields, functions
+        try!(self.build_enumerated_fields(&mut w, query, &query.enumerated_fields)); //TODO: add support for column_sql, fields, functions
         w.append(" ) ");
-        assert!(!query.values.is_empty(),
-                "values should not be empty, when inserting records");
-        if !query.values.is_empty() {
-            w.left_river("VALUES");
-            w.append("(");
-            let mut do_comma = false;
-            for vo in &query.values {
-                if do_comma {
-                    w.commasp();
+        let rows = query.get_rows();
+        match query.source {
+            Some(ref source) => {
+                if !rows.is_empty() {
+                    return Err(DbError::from(QueryError::SqlError("Insert should either have values or a query, not both".to_owned())));
+                }
+                match source.sql_type {
+                    SqlType::SELECT => try!(self.write_select(&mut w, source)),
+                    _ => return Err(DbError::from(QueryError::SqlError("Only SELECT queries can be inserted".to_owned()))),
+                }
+            }
+            None => {
+                if rows.is_empty() {
+                    return Err(DbError::from(QueryError::NoColumnSpecified("values should not be empty, when inserting records".to_owned())));
+                }
+                w.left_river("VALUES");
+                let mut do_comma_row = false;
+                for row in rows {
+                    if do_comma_row {
+                        w.append(",");
+                        w.right_river("");
+                    } else {
+                        do_comma_row = true;
+                    }
+                    w.append("(");
+                    let mut do_comma = false;
+                    for vo in row {
+                        if do_comma {
+                            w.commasp();
+                        } else {
+                            do_comma = true;
+                        }
+                        try!(self.build_operand(&mut w, query, vo));
+                    }
+                    w.append(")");
+                }
+                w.sp();
+            }
+        }
+        if is_upsert {
+            // the columns to be updated when the record already exists
+            let mut update_columns = vec![];
+            for ec in query.get_enumerated_columns() {
+                if !query.on_conflict.contains(&ec.column) {
+                    update_columns.push(ec.column.to_owned());
+                }
+            }
+            w.left_river("ON");
+            if uses_on_conflict {
+                w.append("CONFLICT (");
+                w.append(&query.on_conflict.join(", "));
+                w.append(") ");
+                if update_columns.is_empty() {
+                    w.append("DO NOTHING ");
                 } else {
-                    do_comma = true;
+                    w.append("DO UPDATE SET ");
+                    let mut do_comma = false;
+                    for column in &update_columns {
+                        if do_comma {
+                            w.commasp();
+                        } else {
+                            do_comma = true;
+                        }
+                        w.append(&format!("{} = EXCLUDED.{}", column, column));
+                    }
+                    w.append(" ");
                 }
-                self.build_operand(&mut w, query, vo);
+            } else {
+                w.append("DUPLICATE KEY UPDATE ");
+                if update_columns.is_empty() {
+                    // mysql needs at least 1 assignment, so make it a no-op
+                    update_columns.push(query.on_conflict[0].to_owned());
+                }
+                let mut do_comma = false;
+                for column in &update_columns {
+                    if do_comma {
+                        w.commasp();
+                    } else {
+                        do_comma = true;
+                    }
+                    w.append(&format!("{} = VALUES({})", column, column));
+                }
+                w.append(" ");
             }
-            w.append(") ");
         }
         if !query.enumerated_returns.is_empty() {
             if self.sql_options().contains(&SqlOption::SupportsReturningClause) {
@@ -601,22 +1665,130 @@ pub trait Database {
                     } else {
                         do_comma = true;
                     }
-                    self.build_field(&mut w, query, field);
+                    try!(self.build_field(&mut w, query, field));
                 }
             }
         }
         w.ln();
-        w
+        Ok(w)
     }
 
 
-    fn build_update(&self, query: &Query) -> SqlFrag {
+    /// the insert split into as many statements as needed
+    /// to keep the parameters of each under the limit of the database
+    fn build_insert_batch(&self, query: &Query) -> Result<Vec<SqlFrag>, DbError> {
+        if query.source.is_some() {
+            let frag = try!(self.build_insert(query));
+            return Ok(vec![frag]);
+        }
+        let mut frags = vec![];
+        for chunk in self.insert_chunks(query) {
+            let frag = try!(self.build_insert(&chunk));
+            frags.push(frag);
+        }
+        Ok(frags)
+    }
+
+    /// split the rows of the insert into queries under the parameter limit of the database
+    fn insert_chunks(&self, query: &Query) -> Vec<Query> {
+        let params_per_row = match query.get_rows().iter().map(|r| r.len()).max() {
+            Some(len) if len > 0 => len,
+            _ => 1,
+        };
+        let rows_per_query = cmp::max(1, self.max_params() / params_per_row);
+        query.split_rows(rows_per_query)
+    }
+
+    /// the table being updated or deleted from
+    fn target_table(&self, query: &Query) -> Result<String, DbError> {
+        match query.get_from_table() {
+            Some(ref from) => {
+                if self.sql_options().contains(&SqlOption::UsesSchema) {
+                    Ok(from.complete_name())
+                } else {
+                    Ok(from.name.to_owned())
+                }
+            }
+            None => {
+                Err(DbError::from(QueryError::NoTableSpecified("There should be table to update or delete from".to_owned())))
+            }
+        }
+    }
+
+    /// how the columns of the table being updated or deleted from are qualified,
+    /// the alias when it is renamed, ie: `UPDATE product AS p`
+    fn target_reference(&self, query: &Query) -> Result<String, DbError> {
+        match query.from {
+            Some(ref from) if from.name.is_some() => Ok(from.name.clone().unwrap()),
+            _ => self.target_table(query),
+        }
+    }
+
+    /// write the table being updated or deleted from together with its alias
+    fn write_target_table(&self, w: &mut SqlFrag, query: &Query) -> Result<(), DbError> {
+        w.append(&try!(self.target_table(query)));
+        if let Some(ref from) = query.from {
+            if let Some(ref alias) = from.name {
+                w.append(" AS ");
+                w.append(alias);
+            }
+        }
+        Ok(())
+    }
+
+    /// the filters of an update or delete,
+    /// the joined tables are written in the FROM or USING of the statement when supported,
+    /// otherwise the joins are moved into an EXISTS subquery,
+    /// which needs the columns of the updated table to be prefixed with the table name,
+    /// the unqualified columns of the filters are then taken to be of the updated table
+    fn joined_filters(&self, w: &mut SqlFrag, query: &Query, keyword: &str) -> Result<Vec<Filter>, DbError> {
+        if query.joins.is_empty() || self.sql_options().contains(&SqlOption::SupportsMultiTableUpdate) {
+            return Ok(query.filters.clone());
+        }
+        let first = &query.joins[0];
+        let mut filters = try!(first_join_filters(first));
+        if self.sql_options().contains(&SqlOption::SupportsUpdateFrom) {
+            filters.extend(query.filters.iter().cloned());
+            w.left_river(keyword);
+            w.append(&first.table_name.complete_name());
+            if let Some(ref alias) = first.alias {
+                w.append(" ");
+                w.append(alias);
+            }
+            for join in &query.joins[1..] {
+                try!(self.build_join(w, query, join));
+            }
+            Ok(filters)
+        } else {
+            let target = try!(self.target_reference(query));
+            filters.extend(query.filters.iter().map(|filter| qualify_filter(filter, &target)));
+            let mut exists = Query::select();
+            exists.add_field(Field {
+                      operand: Operand::Sql("1".to_owned(), vec![]),
+                      name: None,
+                  })
+                  .from_field(Field {
+                      operand: Operand::TableName(first.table_name.clone()),
+                      name: first.alias.clone(),
+                  })
+                  .add_filters(filters);
+            exists.joins = query.joins[1..].to_vec();
+            Ok(vec![Filter::exists(exists)])
+        }
+    }
+
+    fn build_update(&self, query: &Query) -> Result<SqlFrag, DbError> {
         let mut w = SqlFrag::new(self.sql_options());
         w.left_river("UPDATE ");
-        let from_table = query.get_from_table();
-        assert!(from_table.is_some(), "There should be table to update from");
-        if let Some(ref from) = from_table {
-            w.append(&from.complete_name());
+        try!(self.write_target_table(&mut w, query));
+        let target_table = try!(self.target_reference(query));
+        // the set columns are qualified, since the joined tables may have the same column
+        let is_multi_table = self.sql_options().contains(&SqlOption::SupportsMultiTableUpdate) &&
+                             !query.joins.is_empty();
+        if is_multi_table {
+            for join in &query.joins {
+                try!(self.build_join(&mut w, query, join));
+            }
         }
         let enumerated_columns = query.get_enumerated_columns();
         let mut do_comma = false;
@@ -630,18 +1802,20 @@ pub trait Database {
             } else {
                 do_comma = true;
             }
+            if is_multi_table {
+                w.append(ec.table.as_ref().unwrap_or(&target_table));
+                w.append(".");
+            }
             w.append(&ec.column);
             w.append(" = ");
-            let value = &query.values[column_index];
-            if let &Operand::Value(ref value) = value {
-                w.parameter(value.clone());
-            }
+            try!(self.build_operand(&mut w, query, &query.values[column_index]));
             column_index += 1;
         }
 
-        if !query.filters.is_empty() {
+        let filters = try!(self.joined_filters(&mut w, query, "FROM"));
+        if !filters.is_empty() {
             w.left_river("WHERE ");
-            self.build_filters(&mut w, query, &query.filters);
+            try!(self.build_filters(&mut w, query, &filters));
         }
         if !query.enumerated_returns.is_empty() {
            if self.sql_options().contains(&SqlOption::SupportsReturningClause) {
@@ -653,30 +1827,169 @@ pub trait Database {
                    } else {
                        do_comma = true;
                    }
-                   self.build_field(&mut w, query, field);
+                   try!(self.build_field(&mut w, query, field));
                }
            }
         }
-        w
+        Ok(w)
     }
 
-    fn build_delete(&self, query: &Query) -> SqlFrag {
+    fn build_delete(&self, query: &Query) -> Result<SqlFrag, DbError> {
         let mut w = SqlFrag::new(self.sql_options());
-        w.left_river("DELETE FROM ");
-        let from_table = query.get_from_table();
-        assert!(from_table.is_some(), "There should be table to delete from");
-        if let Some(ref from) = from_table {
-            w.append(&from.complete_name());
+        if !query.joins.is_empty() && self.sql_options().contains(&SqlOption::SupportsMultiTableUpdate) {
+            w.left_river("DELETE");
+            w.append(&try!(self.target_reference(query)));
+            w.left_river("FROM");
+            try!(self.write_target_table(&mut w, query));
+            for join in &query.joins {
+                try!(self.build_join(&mut w, query, join));
+            }
+        } else {
+            w.left_river("DELETE FROM ");
+            try!(self.write_target_table(&mut w, query));
         }
-        if !query.filters.is_empty() {
+        let filters = try!(self.joined_filters(&mut w, query, "USING"));
+        if !filters.is_empty() {
             w.left_river("WHERE ");
-            self.build_filters(&mut w, query, &query.filters);
+            try!(self.build_filters(&mut w, query, &filters));
         }
-        w
+        Ok(w)
     }
 
     fn sql_options(&self) -> Vec<SqlOption>;
 
+    /// the maximum number of parameters in a single statement,
+    /// defaults to the limit of sqlite
+    fn max_params(&self) -> usize {
+        999
+    }
+
+}
+
+/// the conditions of the first table joined to an update or delete,
+/// which are added to the WHERE since there is no ON to join it with
+fn first_join_filters(join: &Join) -> Result<Vec<Filter>, DbError> {
+    let is_natural = match join.join_type {
+        Some(JoinType::NATURAL) => true,
+        _ => false,
+    };
+    if join.modifier.is_some() || is_natural {
+        return Err(DbError::from(QueryError::SqlError("The first table joined to an update or delete should be an INNER or CROSS join".to_owned())));
+    }
+    if join.column1.len() != join.column2.len() {
+        return Err(DbError::from(QueryError::NoColumnSpecified("There should be equal number of corresponding columns to join".to_owned())));
+    }
+    let mut filters = vec![];
+    for (column1, column2) in join.column1.iter().zip(join.column2.iter()) {
+        filters.push(Filter::bare_new(Operand::Sql(column1.to_owned(), vec![]),
+                                      Equality::EQ,
+                                      Operand::Sql(column2.to_owned(), vec![])));
+    }
+    filters.extend(join.on.iter().cloned());
+    Ok(filters)
+}
+
+/// prefix the unqualified columns of the filter with the table,
+/// the subqueries are left as is since their columns resolve on their own table
+fn qualify_filter(filter: &Filter, table: &str) -> Filter {
+    let mut filter = filter.clone();
+    qualify_operand(&mut filter.condition.left, table);
+    qualify_operand(&mut filter.condition.right, table);
+    filter.sub_filters = filter.sub_filters.iter().map(|sub| qualify_filter(sub, table)).collect();
+    filter
+}
+
+fn qualify_operand(operand: &mut Operand, table: &str) {
+    match *operand {
+        Operand::ColumnName(ref mut column_name) => {
+            if column_name.table.is_none() {
+                column_name.table = Some(table.to_owned());
+            }
+        }
+        Operand::Function(ref mut function) => {
+            for param in &mut function.params {
+                qualify_operand(param, table);
+            }
+        }
+        Operand::Vec(ref mut operands) => {
+            for operand in operands {
+                qualify_operand(operand, table);
+            }
+        }
+        _ => (),
+    }
+}
+
+/// the column refers to a table other than the one the query selects from,
+/// ie: the outer query of a correlated subquery
+fn is_outer_column(query: &Query, column: &ColumnName) -> bool {
+    match (query.get_from_table(), &column.table) {
+        (Some(from), &Some(ref table)) => &from.name != table,
+        _ => false,
+    }
+}
+
+/// separate the next keyword, parameters are already followed by a space
+fn space_after(w: &mut SqlFrag) {
+    if !w.sql.ends_with(" ") {
+        w.sp();
+    }
+}
+
+/// whether the ORDER BY expression is an aggregate, ie: COUNT(*)
+fn is_aggregate(column: &str) -> bool {
+    let column = column.trim_left().to_uppercase();
+    let aggregates = ["COUNT", "SUM", "AVG", "MIN", "MAX", "ARRAY_AGG", "STRING_AGG", "GROUP_CONCAT"];
+    aggregates.iter().any(|aggregate| {
+        column.starts_with(aggregate) && column[aggregate.len()..].trim_left().starts_with("(")
+    })
+}
+
+/// the sql of the start or end of a window frame
+fn frame_bound(bound: &FrameBound) -> String {
+    match *bound {
+        FrameBound::UNBOUNDED_PRECEDING => "UNBOUNDED PRECEDING".to_owned(),
+        FrameBound::PRECEDING(n) => format!("{} PRECEDING", n),
+        FrameBound::CURRENT_ROW => "CURRENT ROW".to_owned(),
+        FrameBound::FOLLOWING(n) => format!("{} FOLLOWING", n),
+        FrameBound::UNBOUNDED_FOLLOWING => "UNBOUNDED FOLLOWING".to_owned(),
+    }
+}
+
+impl<'a> Database + 'a {
+    /// execute the closure inside a transaction,
+    /// commits when the closure returns Ok, rolls back when it returns an Err or panics.
+    /// Nested calls are mapped to savepoints, so only the inner work is undone on failure.
+    /// When the rollback fails as well, both errors are returned in `DbError::RollbackFailed`
+    pub fn transaction<T, F>(&self, f: F) -> Result<T, DbError>
+        where F: FnOnce(&Database) -> Result<T, DbError>
+    {
+        try!(self.begin());
+        let mut guard = TransactionGuard {
+            db: self,
+            finished: false,
+        };
+        // a failed commit is rolled back as well, so the transaction is not left open
+        let result = match f(self) {
+            Ok(value) => self.commit().map(|_| value),
+            Err(e) => Err(e),
+        };
+        guard.finished = true;
+        match result {
+            Ok(value) => Ok(value),
+            Err(e) => {
+                match self.rollback() {
+                    Ok(()) => Err(e),
+                    Err(rollback) => {
+                        Err(DbError::RollbackFailed {
+                            cause: Box::new(e),
+                            rollback: Box::new(rollback),
+                        })
+                    }
+                }
+            }
+        }
+    }
 }
 
 
@@ -688,28 +2001,32 @@ pub trait DatabaseDDL{
     /// The following methods involves DDL(Data definition language) operation
     // //////////////////////////////////////
     /// create a database schema
-    fn create_schema(&self, schema: &str);
+    fn create_schema(&self, schema: &str) -> Result<(), DbError>;
 
     /// drop the database schema
-    fn drop_schema(&self, schema: &str);
+    fn drop_schema(&self, schema: &str) -> Result<(), DbError>;
 
     /// create a database table based on the Model Definition
-    fn create_table(&self, model: &Table);
+    fn create_table(&self, model: &Table) -> Result<(), DbError>;
 
     /// build sql for create table
     fn build_create_table(&self, table: &Table) -> SqlFrag;
 
     /// rename table, in the same schema
-    fn rename_table(&self, table: &Table, new_tablename: String);
+    fn rename_table(&self, table: &Table, new_tablename: String) -> Result<(), DbError>;
 
     /// drop table
-    fn drop_table(&self, table: &Table);
+    fn drop_table(&self, table: &Table) -> Result<(), DbError>;
 
     /// set the foreign key constraint of a table
-    fn set_foreign_constraint(&self, model: &Table);
+    fn set_foreign_constraint(&self, model: &Table) -> Result<(), DbError>;
 
     /// set the primary key constraint of a table
-    fn set_primary_constraint(&self, model: &Table);
+    fn set_primary_constraint(&self, model: &Table) -> Result<(), DbError>;
+
+    /// build the sql which applies the schema change,
+    /// errors when the change can not be done in this database
+    fn build_change(&self, change: &Change) -> Result<SqlFrag, DbError>;
 }
 
 
@@ -729,10 +2046,10 @@ pub trait DatabaseDev {
     /// Build the Table object based on the extracted meta data info from database
     /// This is queries directly from the database, so this will be costly. Only used this on initialization processes
     ///
-    fn get_table_metadata(&self, schema: &str, table: &str, is_view: bool) -> Table;
+    fn get_table_metadata(&self, schema: &str, table: &str, is_view: bool) -> Result<Table, DbError>;
 
     /// get all the tables in this database (schema, table, is_view)
-    fn get_all_tables(&self) -> Vec<(String, String, bool)>;
+    fn get_all_tables(&self) -> Result<Vec<(String, String, bool)>, DbError>;
 
     /// get the inherited columns of this table
     fn get_inherited_columns(&self, schema: &str, table: &str) -> Vec<String>;
@@ -0,0 +1,320 @@
+//! Compare two set of table definitions,
+//! ie: the live database and the definitions of the structs,
+//! and list the changes needed to turn one into the other.
+//! The changes are rendered into sql by each of the database platform through `DatabaseDDL::build_change`
+use table::{Table, Column, Foreign};
+use database::{DatabaseDDL, DbError};
+use writer::SqlFrag;
+
+/// a single difference between two schemas,
+/// the table is included so the platform can render the qualified name
+#[derive(Debug)]
+#[derive(Clone)]
+pub enum Change {
+    /// create the table, including its foreign keys
+    AddTable(Table),
+    DropTable(Table),
+    AddColumn(Table, Column),
+    DropColumn(Table, Column),
+    /// the data type of the column has changed, the column has the new definition
+    AlterColumnType(Table, Column),
+    /// the default value has changed, a None default means the default is dropped
+    AlterColumnDefault(Table, Column),
+    /// the column is now nullable or not nullable
+    AlterColumnNullable(Table, Column),
+    AddForeignKey(Table, Column),
+    /// the column has the foreign key that is to be dropped
+    DropForeignKey(Table, Column),
+}
+
+/// list the changes needed to turn the `from` tables into the `to` tables,
+/// ordered so they can be applied one after the other:
+/// foreign keys are dropped first, then the tables are added, altered and dropped,
+/// and the foreign keys are added last when all the referred tables exist
+pub fn diff(from: &[Table], to: &[Table]) -> Vec<Change> {
+    let mut drop_foreign_keys = vec![];
+    let mut add_tables = vec![];
+    let mut alter_columns = vec![];
+    let mut drop_columns = vec![];
+    let mut drop_tables = vec![];
+    let mut add_foreign_keys = vec![];
+
+    for new_table in to {
+        match from.iter().find(|t| *t == new_table) {
+            Some(old_table) => {
+                for new_column in &new_table.columns {
+                    match find_column(old_table, &new_column.name) {
+                        Some(old_column) => {
+                            if !same_type(old_column, new_column) {
+                                alter_columns.push(Change::AlterColumnType(new_table.clone(),
+                                                                           new_column.clone()));
+                            }
+                            if old_column.default != new_column.default {
+                                alter_columns.push(Change::AlterColumnDefault(new_table.clone(),
+                                                                              new_column.clone()));
+                            }
+                            if old_column.not_null != new_column.not_null {
+                                alter_columns.push(Change::AlterColumnNullable(new_table.clone(),
+                                                                               new_column.clone()));
+                            }
+                            if !same_foreign(&old_column.foreign, &new_column.foreign) {
+                                if old_column.foreign.is_some() {
+                                    drop_foreign_keys.push(Change::DropForeignKey(old_table.clone(),
+                                                                                  old_column.clone()));
+                                }
+                                if new_column.foreign.is_some() {
+                                    add_foreign_keys.push(Change::AddForeignKey(new_table.clone(),
+                                                                                new_column.clone()));
+                                }
+                            }
+                        }
+                        None => {
+                            let mut added = new_column.clone();
+                            added.foreign = None;
+                            alter_columns.push(Change::AddColumn(new_table.clone(), added));
+                            if new_column.foreign.is_some() {
+                                add_foreign_keys.push(Change::AddForeignKey(new_table.clone(),
+                                                                            new_column.clone()));
+                            }
+                        }
+                    }
+                }
+                for old_column in &old_table.columns {
+                    if find_column(new_table, &old_column.name).is_none() {
+                        if old_column.foreign.is_some() {
+                            drop_foreign_keys.push(Change::DropForeignKey(old_table.clone(),
+                                                                          old_column.clone()));
+                        }
+                        drop_columns.push(Change::DropColumn(old_table.clone(), old_column.clone()));
+                    }
+                }
+            }
+            None => add_tables.push(new_table.clone()),
+        }
+    }
+    for old_table in from {
+        if !to.contains(old_table) {
+            drop_tables.push(old_table.clone());
+        }
+    }
+
+    let mut changes = vec![];
+    changes.extend(drop_foreign_keys);
+    for table in sort_by_dependency(add_tables) {
+        changes.push(Change::AddTable(table));
+    }
+    changes.extend(alter_columns);
+    changes.extend(drop_columns);
+    let mut drop_tables = sort_by_dependency(drop_tables);
+    drop_tables.reverse();
+    for table in drop_tables {
+        changes.push(Change::DropTable(table));
+    }
+    changes.extend(add_foreign_keys);
+    changes
+}
+
+/// render the changes into sql statements of the database platform
+pub fn to_sql(db: &DatabaseDDL, changes: &[Change]) -> Result<Vec<SqlFrag>, DbError> {
+    let mut frags = vec![];
+    for change in changes {
+        let frag = try!(db.build_change(change));
+        frags.push(frag);
+    }
+    Ok(frags)
+}
+
+fn find_column<'a>(table: &'a Table, name: &str) -> Option<&'a Column> {
+    table.columns.iter().find(|c| c.name == name)
+}
+
+/// compare the database data type when both are known, otherwise the rust type
+fn same_type(old: &Column, new: &Column) -> bool {
+    if !old.db_data_type.is_empty() && !new.db_data_type.is_empty() {
+        normalize_type(&old.db_data_type) == normalize_type(&new.db_data_type)
+    } else {
+        old.data_type == new.data_type
+    }
+}
+
+/// the canonical name of the database data type,
+/// so aliases such as `int4` and `integer`, or `int(11)` and `int` are the same type.
+/// The length of character and numeric types is significant and kept
+fn normalize_type(db_type: &str) -> String {
+    let db_type = db_type.trim().to_lowercase();
+    let db_type = db_type.split_whitespace().collect::<Vec<&str>>().join(" ");
+    let (base, modifier) = match db_type.find('(') {
+        Some(pos) => {
+            match db_type[pos..].find(')') {
+                Some(end) => {
+                    let modifier = db_type[pos..pos + end + 1].replace(" ", "");
+                    let base = format!("{}{}", db_type[..pos].trim(), &db_type[pos + end + 1..]);
+                    (base, modifier)
+                }
+                None => (db_type.clone(), String::new()),
+            }
+        }
+        None => (db_type.clone(), String::new()),
+    };
+    let base = base.trim();
+    // the display width of integers does not change the type
+    let (base, modifier) = match base {
+        "int2" | "smallint" => ("smallint", ""),
+        "int" | "int4" | "integer" => ("integer", ""),
+        "int8" | "bigint" => ("bigint", ""),
+        "tinyint" => ("tinyint", ""),
+        "mediumint" => ("mediumint", ""),
+        "int unsigned" | "integer unsigned" => ("integer unsigned", ""),
+        "smallint unsigned" => ("smallint unsigned", ""),
+        "bigint unsigned" => ("bigint unsigned", ""),
+        "tinyint unsigned" => ("tinyint unsigned", ""),
+        "mediumint unsigned" => ("mediumint unsigned", ""),
+        "float4" | "real" => ("real", &modifier as &str),
+        "float8" | "double" | "double precision" => ("double precision", &modifier as &str),
+        "bool" | "boolean" => ("boolean", ""),
+        "decimal" | "numeric" => ("numeric", &modifier as &str),
+        "varchar" | "character varying" => ("character varying", &modifier as &str),
+        "char" | "bpchar" | "character" => ("character", &modifier as &str),
+        "timestamp" | "timestamp without time zone" => ("timestamp", &modifier as &str),
+        "timestamptz" | "timestamp with time zone" => ("timestamptz", &modifier as &str),
+        "time" | "time without time zone" => ("time", &modifier as &str),
+        "timetz" | "time with time zone" => ("timetz", &modifier as &str),
+        other => (other, &modifier as &str),
+    };
+    format!("{}{}", base, modifier)
+}
+
+/// an unspecified schema is treated the same as any schema
+fn same_foreign(old: &Option<Foreign>, new: &Option<Foreign>) -> bool {
+    match (old, new) {
+        (&Some(ref old), &Some(ref new)) => {
+            old.table == new.table && old.column == new.column &&
+            (old.schema.is_empty() || new.schema.is_empty() || old.schema == new.schema)
+        }
+        (&None, &None) => true,
+        _ => false,
+    }
+}
+
+/// the referred tables come before the tables referring to them,
+/// tables in a reference cycle are kept in their original order
+fn sort_by_dependency(tables: Vec<Table>) -> Vec<Table> {
+    let mut sorted: Vec<Table> = vec![];
+    let mut remaining = tables;
+    while !remaining.is_empty() {
+        let position = remaining.iter().position(|table| {
+            table.columns.iter().all(|c| {
+                match c.foreign {
+                    Some(ref foreign) => {
+                        foreign.table == table.name ||
+                        !remaining.iter().any(|t| t.name == foreign.table)
+                    }
+                    None => true,
+                }
+            })
+        });
+        let table = remaining.remove(position.unwrap_or(0));
+        sorted.push(table);
+    }
+    sorted
+}
+
+
+#[cfg(test)]
+fn column(name: &str, db_data_type: &str) -> Column {
+    Column {
+        name: name.to_owned(),
+        data_type: "".to_owned(),
+        db_data_type: db_data_type.to_owned(),
+        is_primary: false,
+        is_unique: false,
+        default: None,
+        comment: None,
+        not_null: false,
+        foreign: None,
+        is_inherited: false,
+    }
+}
+
+#[cfg(test)]
+fn table(name: &str, columns: Vec<Column>) -> Table {
+    Table {
+        schema: "bazaar".to_owned(),
+        name: name.to_owned(),
+        parent_table: None,
+        sub_table: vec![],
+        comment: None,
+        columns: columns,
+        is_view: false,
+    }
+}
+
+#[test]
+fn test_diff_columns() {
+    let old = table("product", vec![column("product_id", "uuid"), column("price", "integer")]);
+    let mut price = column("price", "double precision");
+    price.not_null = true;
+    let new = table("product", vec![column("product_id", "uuid"), price, column("name", "text")]);
+    let changes = diff(&[old], &[new]);
+    println!("changes: {:#?}", changes);
+    assert_eq!(changes.len(), 3);
+    match changes[0] {
+        Change::AlterColumnType(_, ref c) => assert_eq!(c.name, "price"),
+        _ => panic!("expecting the price to change type"),
+    }
+    match changes[1] {
+        Change::AlterColumnNullable(_, ref c) => assert!(c.not_null),
+        _ => panic!("expecting the price to be not null"),
+    }
+    match changes[2] {
+        Change::AddColumn(_, ref c) => assert_eq!(c.name, "name"),
+        _ => panic!("expecting the name to be added"),
+    }
+}
+
+#[test]
+fn test_diff_type_aliases() {
+    let old = table("product", vec![column("product_id", "int4"),
+                                    column("price", "numeric(10,2)"),
+                                    column("name", "character varying(255)"),
+                                    column("created", "timestamp with time zone"),
+                                    column("stock", "int(11) unsigned")]);
+    let new = table("product", vec![column("product_id", "integer"),
+                                    column("price", "NUMERIC(10, 2)"),
+                                    column("name", "varchar(255)"),
+                                    column("created", "timestamptz"),
+                                    column("stock", "int unsigned")]);
+    let changes = diff(&[old], &[new]);
+    println!("changes: {:#?}", changes);
+    assert!(changes.is_empty());
+
+    let old = table("product", vec![column("name", "varchar(100)")]);
+    let new = table("product", vec![column("name", "varchar(255)")]);
+    assert_eq!(diff(&[old], &[new]).len(), 1);
+}
+
+#[test]
+fn test_diff_tables_order() {
+    let users = table("users", vec![column("user_id", "uuid")]);
+    let mut owner_id = column("owner_id", "uuid");
+    owner_id.foreign = Some(Foreign {
+        schema: "bazaar".to_owned(),
+        table: "users".to_owned(),
+        column: "user_id".to_owned(),
+    });
+    let product = table("product", vec![column("product_id", "uuid"), owner_id]);
+    let category = table("category", vec![column("category_id", "uuid")]);
+
+    let changes = diff(&[category], &[product, users]);
+    println!("changes: {:#?}", changes);
+    let names: Vec<String> = changes.iter()
+                                    .map(|c| {
+                                        match *c {
+                                            Change::AddTable(ref t) => format!("add {}", t.name),
+                                            Change::DropTable(ref t) => format!("drop {}", t.name),
+                                            _ => "other".to_owned(),
+                                        }
+                                    })
+                                    .collect();
+    assert_eq!(names, vec!["add users", "add product", "drop category"]);
+}
@@ -1,8 +1,8 @@
 use query::{Filter, Operand};
 use query::Query;
 use table::Table;
-use dao::Dao;
-use database::{Database, DbError};
+use dao::{Dao, Value};
+use database::{Database, DbError, SqlOption};
 use table::IsTable;
 use dao::IsDao;
 use dao::ToValue;
@@ -144,7 +144,72 @@ impl <'a>EntityManager<'a> {
                 None => (),
             }
         }
-        q.collect_one(self.db)
+        q.finalize();
+        let inserted = try!(self.db.insert(&q));
+        Ok(T::from_dao(&inserted))
+    }
+
+    /// insert the records in as few statements as possible,
+    /// returns the inserted records including the values generated via the defaults.
+    /// All the records should have the same columns, a column missing in only some of the records
+    /// would otherwise be inserted as NULL instead of its default.
+    /// When the database has no returning clause the records are selected back after each statement,
+    /// matched with their primary key or the range of keys generated for them
+    pub fn insert_many<T>(&self, records: &[T]) -> Result<Vec<T>, DbError>
+        where T: IsTable + IsDao
+    {
+        if records.is_empty() {
+            return Ok(vec![]);
+        }
+        let table = T::table();
+        let daos: Vec<Dao> = records.iter().map(|r| r.to_dao()).collect();
+        let columns: Vec<String> = daos[0].values.keys().cloned().collect();
+        for dao in &daos {
+            if dao.values.len() != columns.len() ||
+               !columns.iter().all(|c| dao.values.contains_key(c)) {
+                return Err(DbError::new("The records to insert should all have the same columns"));
+            }
+        }
+        let mut q = Query::insert();
+        q.into_table(&table.complete_name());
+        for column in &columns {
+            q.column(column);
+        }
+        q.return_all();
+        for dao in &daos {
+            let row = columns.iter()
+                             .map(|column| Operand::Value(dao.values[column].clone()))
+                             .collect();
+            q.add_row(row);
+        }
+        let inserted = try!(self.db.transaction(|db| q.insert_batch(db)));
+        Ok(inserted.iter().map(|dao| T::from_dao(dao)).collect())
+    }
+
+    /// load the records into their table using the bulk loading facility of the database,
+    /// returns the number of inserted records.
+    /// Only the columns present in the records are sent, so the others get their default,
+    /// all the records should have the same columns
+    pub fn bulk_insert<T>(&self, records: &[T]) -> Result<usize, DbError>
+        where T: IsTable + IsDao
+    {
+        if records.is_empty() {
+            return Ok(0);
+        }
+        let table = T::table();
+        let daos: Vec<Dao> = records.iter().map(|r| r.to_dao()).collect();
+        let columns: Vec<&str> = table.columns
+                                      .iter()
+                                      .filter(|c| daos[0].values.contains_key(&c.name))
+                                      .map(|c| &c.name as &str)
+                                      .collect();
+        for dao in &daos {
+            if !columns.iter().all(|c| dao.values.contains_key(*c)) {
+                return Err(DbError::new("The records to insert should all have the same columns"));
+            }
+        }
+        let mut rows = daos.into_iter();
+        self.db.bulk_insert(&table.complete_name(), &columns, &mut rows)
     }
 
     /// insert this record on the database, ignoring some columns
@@ -173,7 +238,9 @@ impl <'a>EntityManager<'a> {
                 None => (),
             }
         }
-        q.collect_one(self.db)
+        q.finalize();
+        let inserted = try!(self.db.insert(&q));
+        Ok(T::from_dao(&inserted))
     }
 
     /// insert this record on the database, explicitly setting the defaults of the columns
@@ -185,56 +252,170 @@ impl <'a>EntityManager<'a> {
         unimplemented!()
     }
 
-    /// this is called when there is a problem with the transaction
-    pub fn reset(&self) {
-        unimplemented!()
+    /// this is called when there is a problem with the transaction,
+    /// rolls back all the ongoing transactions, including the nested ones
+    pub fn reset(&self) -> Result<(), DbError> {
+        while self.db.is_transacted() {
+            try!(self.db.rollback());
+        }
+        Ok(())
     }
 
     /// when there is a problem with the transaction process, this can be called
-    pub fn rollback(&self) {
-        unimplemented!()
+    pub fn rollback(&self) -> Result<(), DbError> {
+        self.db.rollback()
     }
 
     /// update the Dao, return the updated Dao
-    pub fn update<T>(&self, _dao: &Dao) -> Result<T, DbError>
+    pub fn update<T>(&self, dao: &Dao) -> Result<T, DbError>
         where T: IsTable + IsDao
     {
-        unimplemented!()
+        let table = T::table();
+        let filters = try!(Self::primary_filters(&table, dao));
+        let sets = Self::values_of(dao, Self::non_primary_columns(&table, dao));
+        self.update_columns(&table, sets, filters)
     }
 
     /// update the Dao, return the updated Dao
     /// ignored columns will remain unchanged
     pub fn update_ignore_columns<T>(&self,
-                                    _dao: &Dao,
-                                    _ignore_columns: Vec<&str>)
+                                    dao: &Dao,
+                                    ignore_columns: Vec<&str>)
                                     -> Result<T, DbError>
         where T: IsTable + IsDao
     {
-        unimplemented!()
+        let table = T::table();
+        let filters = try!(Self::primary_filters(&table, dao));
+        let columns = Self::non_primary_columns(&table, dao)
+                          .into_iter()
+                          .filter(|c| !ignore_columns.contains(&&c[..]))
+                          .collect();
+        let sets = Self::values_of(dao, columns);
+        self.update_columns(&table, sets, filters)
     }
 
     /// update the Dao, return the updated Dao
     /// only the columns specified, the rest is unchanged
-    pub fn update_only_columns<T>(&self, _dao: &Dao, _columns: Vec<&str>) -> Result<T, DbError>
+    pub fn update_only_columns<T>(&self, dao: &Dao, columns: Vec<&str>) -> Result<T, DbError>
         where T: IsTable + IsDao
     {
-        unimplemented!()
+        let table = T::table();
+        let filters = try!(Self::primary_filters(&table, dao));
+        let columns = Self::non_primary_columns(&table, dao)
+                          .into_iter()
+                          .filter(|c| columns.contains(&&c[..]))
+                          .collect();
+        let sets = Self::values_of(dao, columns);
+        self.update_columns(&table, sets, filters)
     }
 
     /// update the Dao, return the updated Dao
     /// the default columns will be reset to whatever the db's default function will come up.
     /// ie. updated column will be defaulted everytime a record is updated.
-    pub fn update_ignore_defaulted_columns<T>(&self, _dao: &Dao) -> Result<T, DbError>
+    /// SQLite has no `DEFAULT` in an update, the defaulted columns are left unchanged there
+    pub fn update_ignore_defaulted_columns<T>(&self, dao: &Dao) -> Result<T, DbError>
         where T: IsTable + IsDao
     {
-        unimplemented!()
+        let table = T::table();
+        let filters = try!(Self::primary_filters(&table, dao));
+        let set_default = self.db.sql_options().contains(&SqlOption::SupportsSetDefault);
+        let mut sets = vec![];
+        for column in &table.columns {
+            if column.is_primary {
+                continue;
+            }
+            if column.default.is_some() {
+                if set_default {
+                    sets.push((column.name.to_owned(), Operand::Sql("DEFAULT".to_owned(), vec![])));
+                }
+            } else if let Some(value) = dao.values.get(&column.name) {
+                sets.push((column.name.to_owned(), Operand::Value(value.clone())));
+            }
+        }
+        self.update_columns(&table, sets, filters)
     }
 
     /// update the Dao with filter, return the updated Dao
-    pub fn update_with_filter<T>(&self, _dao: &Dao, _filter: Vec<Filter>) -> Result<T, DbError>
+    /// the records that don't pass the filter are not updated,
+    /// when no record passed, the error "No record has been updated" is returned
+    pub fn update_with_filter<T>(&self, dao: &Dao, filter: Vec<Filter>) -> Result<T, DbError>
         where T: IsTable + IsDao
     {
-        unimplemented!()
+        let table = T::table();
+        let sets = Self::values_of(dao, Self::non_primary_columns(&table, dao));
+        self.update_columns(&table, sets, filter)
+    }
+
+    /// the filters that matches the record of this dao using the primary columns of the table
+    fn primary_filters(table: &Table, dao: &Dao) -> Result<Vec<Filter>, DbError> {
+        let primary = table.primary_columns();
+        if primary.is_empty() {
+            return Err(DbError::new(&format!("Table {} has no primary column",
+                                             table.complete_name())));
+        }
+        let mut filters = vec![];
+        for pk in primary {
+            match dao.values.get(&pk.name) {
+                Some(value) => filters.push(Filter::with_value(&pk.name, Equality::EQ, value.clone())),
+                None => {
+                    return Err(DbError::new(&format!("No value for primary column {}", pk.name)))
+                }
+            }
+        }
+        Ok(filters)
+    }
+
+    /// the columns of the dao that will be set when updating, the primary columns are not changed
+    fn non_primary_columns(table: &Table, dao: &Dao) -> Vec<String> {
+        let mut columns = vec![];
+        for key in dao.values.keys() {
+            if !table.is_primary(key) {
+                columns.push(key.to_owned());
+            }
+        }
+        columns
+    }
+
+    /// the values of the dao to set to the columns
+    fn values_of(dao: &Dao, columns: Vec<String>) -> Vec<(String, Operand)> {
+        columns.into_iter()
+               .map(|column| {
+                   let value = Operand::Value(dao.get_value(&column));
+                   (column, value)
+               })
+               .collect()
+    }
+
+    /// update the columns of the records that passed the filters,
+    /// when the database doesn't support returning clause, the first record that passed the filters
+    /// is looked up before the update and selected back with its primary key afterwards
+    fn update_columns<T>(&self,
+                         table: &Table,
+                         sets: Vec<(String, Operand)>,
+                         filters: Vec<Filter>)
+                         -> Result<T, DbError>
+        where T: IsTable + IsDao
+    {
+        if sets.is_empty() {
+            return Err(DbError::new("There are no columns to update"));
+        }
+        let mut q = Query::update();
+        q.from_table(&table.complete_name());
+        for (column, value) in sets {
+            q.column(&column);
+            q.add_value(value);
+        }
+        q.add_filters(filters);
+        if self.db.sql_options().contains(&SqlOption::SupportsReturningClause) {
+            q.return_all();
+            match try!(q.retrieve_one(self.db)) {
+                Some(updated) => Ok(T::from_dao(&updated)),
+                None => Err(DbError::new("No record has been updated")),
+            }
+        } else {
+            let updated = try!(self.db.update(&q));
+            Ok(T::from_dao(&updated))
+        }
     }
 
     /// whether to use insert or update
@@ -242,10 +423,62 @@ impl <'a>EntityManager<'a> {
     /// update when it is an existing recor
     /// may use UPSERT in newer versions of postgres
     /// may use MERGE in oracle, mssql
-    pub fn save<T>(&self, _dao: T) -> Result<T, DbError>
+    /// records which has no value for its primary columns are always inserted
+    pub fn save<T>(&self, record: T) -> Result<T, DbError>
         where T: IsTable + IsDao
     {
-        unimplemented!()
+        let table = T::table();
+        let dao = record.to_dao();
+        let filters = match Self::primary_filters(&table, &dao) {
+            Ok(filters) => filters,
+            Err(_) => return self.insert(&record),
+        };
+        let has_primary_values = filters.iter().all(|f| {
+            match f.condition.right {
+                Operand::Value(Value::None) => false,
+                _ => true,
+            }
+        });
+        if !has_primary_values {
+            return self.insert(&record);
+        }
+        let options = self.db.sql_options();
+        if options.contains(&SqlOption::SupportsOnConflict) ||
+           options.contains(&SqlOption::SupportsOnDuplicateKey) {
+            let mut q = Query::insert();
+            q.into_table(&table.complete_name());
+            for (column, value) in &dao.values {
+                q.column(column);
+                q.add_value(Operand::Value(value.clone()));
+            }
+            let primary: Vec<&str> = table.primary_columns()
+                                          .into_iter()
+                                          .map(|c| &c.name as &str)
+                                          .collect();
+            q.on_conflict_update(primary);
+            if options.contains(&SqlOption::SupportsReturningClause) {
+                q.return_all();
+                match try!(q.retrieve_one(self.db)) {
+                    Some(saved) => Ok(T::from_dao(&saved)),
+                    None => Err(DbError::new("No record has been saved")),
+                }
+            } else {
+                try!(q.execute(self.db));
+                Query::select_all()
+                    .from_table(&table.complete_name())
+                    .add_filters(filters)
+                    .collect_one(self.db)
+            }
+        } else {
+            let existing = try!(Query::select_all()
+                                    .from_table(&table.complete_name())
+                                    .add_filters(filters)
+                                    .retrieve_one(self.db));
+            match existing {
+                Some(_) => self.update(&dao),
+                None => self.insert(&record),
+            }
+        }
     }
 
     ///
@@ -102,3 +102,6 @@ pub mod table;
 pub mod writer;
 pub mod config;
 pub mod pool;
+pub mod diff;
+pub mod migration;
+pub mod codegen;
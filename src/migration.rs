@@ -0,0 +1,554 @@
+//! Versioned schema migrations.
+//!
+//! Each migration has a version, an up step and optionally a down step.
+//! The steps are either sql, ie: read from `{version}_{name}.up.sql` and `{version}_{name}.down.sql` files,
+//! or rust closures over the `Database`.
+//! Applied migrations are recorded in the `rustorm_migrations` table together with a checksum,
+//! so a migration file that was modified after it was applied is detected.
+//!
+//! Each migration is applied in a transaction, so a failing migration leaves no trace,
+//! except on mysql: DDL statements such as CREATE TABLE and ALTER TABLE commit implicitly there,
+//! so the statements before the failing one stay applied while the migration is not recorded.
+//! Keep a single DDL statement per migration on mysql, so a failed migration can be fixed and run again.
+//!
+//! ```ignore
+//! let migrator = try!(Migrator::from_dir(Path::new("migrations")));
+//! let applied = try!(migrator.migrate(db.as_ref()));
+//! ```
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use database::{Database, DbError};
+use query::{Query, Equality};
+
+/// the table where the applied migrations are recorded
+pub const HISTORY_TABLE: &'static str = "rustorm_migrations";
+
+/// what is executed when a migration is applied or reverted
+pub enum Step {
+    /// sql statements separated by `;`, see `split_statements`
+    Sql(String),
+    /// a closure for changes that can not be expressed in plain sql
+    Function(Box<Fn(&Database) -> Result<(), DbError>>),
+}
+
+impl Step {
+    fn run(&self, db: &Database) -> Result<(), DbError> {
+        match *self {
+            Step::Sql(ref sql) => db.execute_batch(sql),
+            Step::Function(ref f) => f(db),
+        }
+    }
+}
+
+pub struct Migration {
+    pub version: u64,
+    pub name: String,
+    up: Step,
+    down: Option<Step>,
+}
+
+impl Migration {
+    pub fn sql(version: u64, name: &str, up: &str, down: Option<&str>) -> Self {
+        Migration {
+            version: version,
+            name: name.to_owned(),
+            up: Step::Sql(up.to_owned()),
+            down: down.map(|d| Step::Sql(d.to_owned())),
+        }
+    }
+
+    pub fn function<U, D>(version: u64, name: &str, up: U, down: Option<D>) -> Self
+        where U: Fn(&Database) -> Result<(), DbError> + 'static,
+              D: Fn(&Database) -> Result<(), DbError> + 'static
+    {
+        Migration {
+            version: version,
+            name: name.to_owned(),
+            up: Step::Function(Box::new(up)),
+            down: match down {
+                Some(down) => Some(Step::Function(Box::new(down))),
+                None => None,
+            },
+        }
+    }
+
+    /// the checksum of the up sql of the migration, which is what has been applied,
+    /// so fixing the down sql afterwards is not treated as a modification.
+    /// Closures can not be inspected, so only their name is taken into account
+    pub fn checksum(&self) -> String {
+        match self.up {
+            Step::Sql(ref sql) => checksum(sql),
+            Step::Function(_) => checksum(&self.name),
+        }
+    }
+}
+
+/// the state of a known migration in the database
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct MigrationStatus {
+    pub version: u64,
+    pub name: String,
+    pub applied: bool,
+    /// the migration was changed after it was applied
+    pub modified: bool,
+}
+
+/// a record of the history table
+struct Applied {
+    version: u64,
+    checksum: String,
+}
+
+pub struct Migrator {
+    migrations: Vec<Migration>,
+}
+
+impl Migrator {
+    pub fn new(migrations: Vec<Migration>) -> Self {
+        let mut migrations = migrations;
+        migrations.sort_by(|a, b| a.version.cmp(&b.version));
+        Migrator { migrations: migrations }
+    }
+
+    /// read the migration files in the directory,
+    /// files are named `{version}_{name}.up.sql` and `{version}_{name}.down.sql`,
+    /// the down file is optional
+    pub fn from_dir(dir: &Path) -> Result<Self, DbError> {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) => return Err(DbError::new(&format!("Unable to read {}: {}", dir.display(), e))),
+        };
+        let mut migrations = vec![];
+        for entry in entries {
+            let path = match entry {
+                Ok(entry) => entry.path(),
+                Err(e) => return Err(DbError::new(&format!("{}", e))),
+            };
+            let file_name = match path.file_name().and_then(|f| f.to_str()) {
+                Some(file_name) => file_name.to_owned(),
+                None => continue,
+            };
+            if let Some((version, name)) = parse_file_name(&file_name, ".up.sql") {
+                let up = try!(read_file(&path));
+                let down_path = dir.join(format!("{}_{}.down.sql", version, name));
+                let down = if down_path.exists() {
+                    Some(try!(read_file(&down_path)))
+                } else {
+                    None
+                };
+                migrations.push(Migration::sql(version, &name, &up, down.as_ref().map(|d| d as &str)));
+            }
+        }
+        Ok(Migrator::new(migrations))
+    }
+
+    /// apply the pending migrations in order, each in its own transaction,
+    /// returns the versions applied.
+    /// Fails without applying anything when an applied migration has been modified
+    pub fn migrate(&self, db: &Database) -> Result<Vec<u64>, DbError> {
+        let applied = try!(self.applied(db));
+        for migration in &self.migrations {
+            if let Some(record) = applied.iter().find(|a| a.version == migration.version) {
+                if record.checksum != migration.checksum() {
+                    return Err(DbError::new(&format!("Migration {}_{} has been modified after it was applied",
+                                                     migration.version,
+                                                     migration.name)));
+                }
+            }
+        }
+        let mut versions = vec![];
+        for migration in &self.migrations {
+            if !applied.iter().any(|a| a.version == migration.version) {
+                try!(self.apply(db, migration));
+                versions.push(migration.version);
+            }
+        }
+        Ok(versions)
+    }
+
+    /// revert the last applied migration, returns its version,
+    /// None if there is nothing to revert
+    pub fn rollback(&self, db: &Database) -> Result<Option<u64>, DbError> {
+        let applied = try!(self.applied(db));
+        let last = match applied.last() {
+            Some(last) => last.version,
+            None => return Ok(None),
+        };
+        let migration = match self.migrations.iter().find(|m| m.version == last) {
+            Some(migration) => migration,
+            None => return Err(DbError::new(&format!("Applied migration {} is not found", last))),
+        };
+        try!(self.revert(db, migration));
+        Ok(Some(last))
+    }
+
+    /// revert then apply again the last applied migration
+    pub fn redo(&self, db: &Database) -> Result<Option<u64>, DbError> {
+        match try!(self.rollback(db)) {
+            Some(version) => {
+                let migration = self.migrations.iter().find(|m| m.version == version).unwrap();
+                try!(self.apply(db, migration));
+                Ok(Some(version))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// list each of the migrations and whether it has been applied
+    pub fn status(&self, db: &Database) -> Result<Vec<MigrationStatus>, DbError> {
+        let applied = try!(self.applied(db));
+        let mut status = vec![];
+        for migration in &self.migrations {
+            let record = applied.iter().find(|a| a.version == migration.version);
+            status.push(MigrationStatus {
+                version: migration.version,
+                name: migration.name.clone(),
+                applied: record.is_some(),
+                modified: match record {
+                    Some(record) => record.checksum != migration.checksum(),
+                    None => false,
+                },
+            });
+        }
+        Ok(status)
+    }
+
+    fn apply(&self, db: &Database, migration: &Migration) -> Result<(), DbError> {
+        db.transaction(|tx| {
+            try!(migration.up.run(tx));
+            try!(Query::insert()
+                     .into_table(HISTORY_TABLE)
+                     .set("version", &(migration.version as i64))
+                     .set("name", &migration.name)
+                     .set("checksum", &migration.checksum())
+                     .execute(tx));
+            Ok(())
+        })
+    }
+
+    fn revert(&self, db: &Database, migration: &Migration) -> Result<(), DbError> {
+        let down = match migration.down {
+            Some(ref down) => down,
+            None => {
+                return Err(DbError::new(&format!("Migration {}_{} can not be reverted",
+                                                 migration.version,
+                                                 migration.name)))
+            }
+        };
+        db.transaction(|tx| {
+            try!(down.run(tx));
+            try!(Query::delete()
+                     .from_table(HISTORY_TABLE)
+                     .filter("version", Equality::EQ, &(migration.version as i64))
+                     .execute(tx));
+            Ok(())
+        })
+    }
+
+    /// the applied migrations ordered by version, the history table is created when missing
+    fn applied(&self, db: &Database) -> Result<Vec<Applied>, DbError> {
+        try!(db.execute_sql(&format!("CREATE TABLE IF NOT EXISTS {} (
+    version bigint NOT NULL PRIMARY KEY,
+    name varchar(255) NOT NULL,
+    checksum varchar(32) NOT NULL,
+    applied_at timestamp DEFAULT CURRENT_TIMESTAMP
+)",
+                                     HISTORY_TABLE),
+                            &[]));
+        let result = try!(Query::select_all()
+                              .from_table(HISTORY_TABLE)
+                              .asc("version")
+                              .retrieve(db));
+        let mut applied = vec![];
+        for dao in result.dao {
+            let version: i64 = try!(dao.try_get("version"));
+            applied.push(Applied {
+                version: version as u64,
+                checksum: try!(dao.try_get("checksum")),
+            });
+        }
+        Ok(applied)
+    }
+}
+
+/// split the sql into its statements at each `;`,
+/// except when the `;` is inside a quoted string or identifier, a comment,
+/// a dollar quoted body (`$$ ... $$` or `$tag$ ... $tag$`)
+/// or a `BEGIN ... END` block such as the body of a trigger.
+/// Empty statements are left out
+pub fn split_statements(sql: &str) -> Vec<String> {
+    let chars: Vec<char> = sql.chars().collect();
+    let mut statements = vec![];
+    let mut current = String::new();
+    let mut blocks = Blocks {
+        depth: 0,
+        last: String::new(),
+        closed: false,
+    };
+    let mut word = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_alphanumeric() || c == '_' {
+            word.push(c);
+            current.push(c);
+            i += 1;
+            continue;
+        }
+        if !word.is_empty() {
+            blocks.word(&word);
+            word.clear();
+        }
+        if c == ';' {
+            blocks.semicolon();
+        }
+        if c == '\'' || c == '"' || c == '`' {
+            // quoted until the same closing quote, a doubled quote is an escaped one
+            current.push(c);
+            i += 1;
+            while i < chars.len() {
+                current.push(chars[i]);
+                if chars[i] == c {
+                    if i + 1 < chars.len() && chars[i + 1] == c {
+                        current.push(c);
+                        i += 1;
+                    } else {
+                        break;
+                    }
+                } else if chars[i] == '\\' && c != '`' && i + 1 < chars.len() {
+                    current.push(chars[i + 1]);
+                    i += 1;
+                }
+                i += 1;
+            }
+            i += 1;
+        } else if c == '-' && i + 1 < chars.len() && chars[i + 1] == '-' {
+            while i < chars.len() && chars[i] != '\n' {
+                current.push(chars[i]);
+                i += 1;
+            }
+        } else if c == '/' && i + 1 < chars.len() && chars[i + 1] == '*' {
+            current.push_str("/*");
+            i += 2;
+            while i < chars.len() && !(chars[i] == '*' && i + 1 < chars.len() && chars[i + 1] == '/') {
+                current.push(chars[i]);
+                i += 1;
+            }
+            if i < chars.len() {
+                current.push_str("*/");
+            }
+            i += 2;
+        } else if c == '$' && dollar_tag(&chars, i).is_some() {
+            let tag = dollar_tag(&chars, i).unwrap();
+            current.push_str(&tag);
+            i += tag.len();
+            while i < chars.len() && !starts_with(&chars, i, &tag) {
+                current.push(chars[i]);
+                i += 1;
+            }
+            if i < chars.len() {
+                current.push_str(&tag);
+            }
+            i += tag.len();
+        } else if c == ';' && blocks.depth == 0 {
+            if !current.trim().is_empty() {
+                statements.push(current.trim().to_owned());
+            }
+            current = String::new();
+            i += 1;
+        } else {
+            current.push(c);
+            i += 1;
+        }
+    }
+    if !current.trim().is_empty() {
+        statements.push(current.trim().to_owned());
+    }
+    statements
+}
+
+/// the nesting of the `BEGIN ... END` blocks seen so far while splitting statements
+struct Blocks {
+    depth: usize,
+    /// the previous word, uppercased
+    last: String,
+    /// the previous word was an `END` which closed a block
+    closed: bool,
+}
+
+impl Blocks {
+    /// `CASE` also ends with `END` so it is counted as a block too,
+    /// a `BEGIN` which starts a statement outside of any block starts a transaction and not a block,
+    /// ie: `BEGIN;`, `BEGIN IMMEDIATE;`, `BEGIN ISOLATION LEVEL SERIALIZABLE;`,
+    /// the `END` of `END IF`, `END LOOP`, `END WHILE` and `END REPEAT` closes no `BEGIN`
+    fn word(&mut self, word: &str) {
+        let word = word.to_uppercase();
+        match &word as &str {
+            "BEGIN" if self.depth == 0 && (self.last.is_empty() || self.last == ";") => (),
+            "BEGIN" => self.depth += 1,
+            "CASE" if self.last != "END" => self.depth += 1,
+            "END" if self.depth > 0 => {
+                self.depth -= 1;
+                self.closed = true;
+                self.last = word;
+                return;
+            }
+            "IF" | "LOOP" | "WHILE" | "REPEAT" if self.last == "END" && self.closed => {
+                self.depth += 1
+            }
+            _ => (),
+        }
+        self.closed = false;
+        self.last = word;
+    }
+
+    fn semicolon(&mut self) {
+        self.closed = false;
+        self.last = ";".to_owned();
+    }
+}
+
+/// the `$tag$` starting at this position
+fn dollar_tag(chars: &[char], start: usize) -> Option<String> {
+    let mut tag = "$".to_owned();
+    let mut i = start + 1;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '$' {
+            tag.push(c);
+            return Some(tag);
+        }
+        if !(c.is_alphanumeric() || c == '_') || (i == start + 1 && c.is_numeric()) {
+            return None;
+        }
+        tag.push(c);
+        i += 1;
+    }
+    None
+}
+
+fn starts_with(chars: &[char], start: usize, s: &str) -> bool {
+    let mut i = start;
+    for c in s.chars() {
+        if i >= chars.len() || chars[i] != c {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+/// extract the version and name out of `{version}_{name}{suffix}`
+fn parse_file_name(file_name: &str, suffix: &str) -> Option<(u64, String)> {
+    if !file_name.ends_with(suffix) {
+        return None;
+    }
+    let stem = &file_name[..file_name.len() - suffix.len()];
+    let mut split = stem.splitn(2, '_');
+    let version = match split.next().map(|v| v.parse::<u64>()) {
+        Some(Ok(version)) => version,
+        _ => return None,
+    };
+    let name = split.next().unwrap_or("");
+    Some((version, name.to_owned()))
+}
+
+fn read_file(path: &Path) -> Result<String, DbError> {
+    let mut content = String::new();
+    match fs::File::open(path).and_then(|mut f| f.read_to_string(&mut content)) {
+        Ok(_) => Ok(content),
+        Err(e) => Err(DbError::new(&format!("Unable to read {}: {}", path.display(), e))),
+    }
+}
+
+/// 64 bit FNV-1a hash in hex, stable across rust versions unlike the std hasher
+fn checksum(content: &str) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in content.bytes() {
+        hash = hash ^ (byte as u64);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", hash)
+}
+
+#[test]
+fn test_parse_file_name() {
+    assert_eq!(parse_file_name("20151201_create_product.up.sql", ".up.sql"),
+               Some((20151201, "create_product".to_owned())));
+    assert_eq!(parse_file_name("20151201_create_product.down.sql", ".up.sql"), None);
+    assert_eq!(parse_file_name("readme.up.sql", ".up.sql"), None);
+}
+
+#[test]
+fn test_checksum() {
+    assert_eq!(checksum(""), "cbf29ce484222325");
+    assert_eq!(checksum("a"), "af63dc4c8601ec8c");
+    let up = Migration::sql(1, "create", "CREATE TABLE a(id int)", None);
+    let changed = Migration::sql(1, "create", "CREATE TABLE a(id bigint)", None);
+    assert!(up.checksum() != changed.checksum());
+    let with_down = Migration::sql(1, "create", "CREATE TABLE a(id int)", Some("DROP TABLE a"));
+    assert_eq!(up.checksum(), with_down.checksum());
+}
+
+#[test]
+fn test_split_statements() {
+    let sql = "CREATE TABLE a(name text DEFAULT 'x;y');
+               -- a comment; with a semicolon
+               INSERT INTO a VALUES ('it''s; quoted', \"b;c\");
+               /* block; comment */
+               CREATE FUNCTION f() RETURNS trigger AS $body$ BEGIN RETURN NEW; END; $body$ LANGUAGE plpgsql;
+               CREATE TRIGGER t BEFORE INSERT ON a FOR EACH ROW
+               BEGIN
+                   IF NEW.name IS NULL THEN SET NEW.name = 'none'; END IF;
+                   SET NEW.name = CASE WHEN NEW.name = '' THEN 'empty' ELSE NEW.name END;
+               END;
+               BEGIN;
+               COMMIT;;";
+    let statements = split_statements(sql);
+    println!("statements: {:#?}", statements);
+    assert_eq!(statements.len(), 6);
+    assert_eq!(statements[0], "CREATE TABLE a(name text DEFAULT 'x;y')");
+    assert!(statements[1].ends_with("INSERT INTO a VALUES ('it''s; quoted', \"b;c\")"));
+    assert!(statements[2].ends_with("$body$ LANGUAGE plpgsql"));
+    assert!(statements[3].starts_with("CREATE TRIGGER"));
+    assert!(statements[3].ends_with("END"));
+    assert!(statements[4].ends_with("BEGIN"));
+    assert_eq!(statements[5], "COMMIT");
+}
+
+#[test]
+fn test_split_statements_transactions() {
+    let sql = "BEGIN IMMEDIATE;
+               CREATE TABLE a(id integer);
+               COMMIT;
+               BEGIN DEFERRED TRANSACTION;
+               INSERT INTO a VALUES (1);
+               END;
+               BEGIN EXCLUSIVE;
+               INSERT INTO a VALUES (2);
+               COMMIT;
+               BEGIN ISOLATION LEVEL SERIALIZABLE;
+               INSERT INTO a VALUES (3);
+               COMMIT;
+               BEGIN WORK;
+               COMMIT WORK;";
+    let statements = split_statements(sql);
+    println!("statements: {:#?}", statements);
+    assert_eq!(statements,
+               vec!["BEGIN IMMEDIATE",
+                    "CREATE TABLE a(id integer)",
+                    "COMMIT",
+                    "BEGIN DEFERRED TRANSACTION",
+                    "INSERT INTO a VALUES (1)",
+                    "END",
+                    "BEGIN EXCLUSIVE",
+                    "INSERT INTO a VALUES (2)",
+                    "COMMIT",
+                    "BEGIN ISOLATION LEVEL SERIALIZABLE",
+                    "INSERT INTO a VALUES (3)",
+                    "COMMIT",
+                    "BEGIN WORK",
+                    "COMMIT WORK"]);
+}
@@ -0,0 +1,358 @@
+//! Encoding and decoding of the data streamed by the PostgreSQL `COPY` statement.
+//!
+//! `CopyReader` turns an iterator of `Dao` into the `COPY ... FROM STDIN` stream
+//! one row at a time, so the records don't need to be held in memory.
+//! http://www.postgresql.org/docs/9.4/static/sql-copy.html
+use std::io::{self, Read};
+use std::collections::BTreeMap;
+use chrono::Timelike;
+use chrono::naive::date::NaiveDate;
+use chrono::naive::time::NaiveTime;
+use rustc_serialize::json::ToJson;
+use dao::{Dao, Value};
+
+/// the header of the binary format: signature, flags and the header extension length
+const BINARY_HEADER: &'static [u8] = b"PGCOPY\n\xff\r\n\0\0\0\0\0\0\0\0\0";
+
+/// seconds from the unix epoch to the postgres epoch 2000-01-01
+const POSTGRES_EPOCH: i64 = 946684800;
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub enum CopyFormat {
+    /// tab separated, `\N` for NULL
+    TEXT,
+    CSV,
+    /// the values are sent in their binary representation, the fastest but the strictest on types
+    BINARY,
+}
+
+impl CopyFormat {
+    /// the options of the COPY statement for this format
+    pub fn options(&self) -> &'static str {
+        match *self {
+            CopyFormat::TEXT => "",
+            CopyFormat::CSV => " (FORMAT csv)",
+            CopyFormat::BINARY => " (FORMAT binary)",
+        }
+    }
+}
+
+/// reads the records as a COPY stream of the specified columns,
+/// a column that is missing in the record is sent as NULL
+pub struct CopyReader<'a> {
+    rows: &'a mut Iterator<Item = Dao>,
+    columns: Vec<String>,
+    format: CopyFormat,
+    buf: Vec<u8>,
+    pos: usize,
+    started: bool,
+    finished: bool,
+}
+
+impl<'a> CopyReader<'a> {
+    pub fn new(rows: &'a mut Iterator<Item = Dao>,
+               columns: &[&str],
+               format: CopyFormat)
+               -> Self {
+        CopyReader {
+            rows: rows,
+            columns: columns.iter().map(|c| c.to_string()).collect(),
+            format: format,
+            buf: vec![],
+            pos: 0,
+            started: false,
+            finished: false,
+        }
+    }
+
+    /// fill the buffer with the next row, the header and the trailer
+    fn fill(&mut self) -> Result<(), String> {
+        self.buf.clear();
+        self.pos = 0;
+        if !self.started {
+            self.started = true;
+            if self.format == CopyFormat::BINARY {
+                self.buf.extend(BINARY_HEADER.iter().cloned());
+            }
+        }
+        match self.rows.next() {
+            Some(dao) => {
+                let values: Vec<&Value> = self.columns
+                                              .iter()
+                                              .map(|c| dao.values.get(c).unwrap_or(&Value::None))
+                                              .collect();
+                match self.format {
+                    CopyFormat::TEXT => self.buf.extend(text_row(&values).into_bytes()),
+                    CopyFormat::CSV => self.buf.extend(csv_row(&values).into_bytes()),
+                    CopyFormat::BINARY => {
+                        let row = try!(binary_row(&values));
+                        self.buf.extend(row);
+                    }
+                }
+            }
+            None => {
+                self.finished = true;
+                if self.format == CopyFormat::BINARY {
+                    push_i16(&mut self.buf, -1);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Read for CopyReader<'a> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.buf.len() {
+            if self.finished {
+                return Ok(0);
+            }
+            if let Err(e) = self.fill() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, e));
+            }
+        }
+        let n = try!((&self.buf[self.pos..]).read(out));
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// a line of the text format, terminated by a newline
+pub fn text_row(values: &[&Value]) -> String {
+    let fields: Vec<String> = values.iter()
+                                    .map(|v| {
+                                        match text_value(v) {
+                                            Some(text) => escape_text(&text),
+                                            None => "\\N".to_owned(),
+                                        }
+                                    })
+                                    .collect();
+    format!("{}\n", fields.join("\t"))
+}
+
+/// a line of the csv format, NULL is an unquoted empty field
+pub fn csv_row(values: &[&Value]) -> String {
+    let fields: Vec<String> = values.iter()
+                                    .map(|v| {
+                                        match text_value(v) {
+                                            Some(text) => quote_csv(&text),
+                                            None => "".to_owned(),
+                                        }
+                                    })
+                                    .collect();
+    format!("{}\n", fields.join(","))
+}
+
+/// a tuple of the binary format: the field count then each field prefixed with its length
+pub fn binary_row(values: &[&Value]) -> Result<Vec<u8>, String> {
+    let mut buf = vec![];
+    push_i16(&mut buf, values.len() as i16);
+    for value in values {
+        match try!(binary_value(value)) {
+            Some(bytes) => {
+                push_i32(&mut buf, bytes.len() as i32);
+                buf.extend(bytes);
+            }
+            None => push_i32(&mut buf, -1),
+        }
+    }
+    Ok(buf)
+}
+
+/// parse the output of `COPY ... TO STDOUT` in text format,
+/// COPY doesn't carry the data types, so the values are left as strings
+pub fn parse_text(data: &str, columns: &[&str]) -> Result<Vec<Dao>, String> {
+    let mut records = vec![];
+    for line in data.lines() {
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != columns.len() {
+            return Err(format!("Expecting {} fields, found {} in: {}",
+                               columns.len(),
+                               fields.len(),
+                               line));
+        }
+        let mut values = BTreeMap::new();
+        for (column, field) in columns.iter().zip(fields) {
+            let value = if field == "\\N" {
+                Value::None
+            } else {
+                Value::String(unescape_text(field))
+            };
+            values.insert(column.to_string(), value);
+        }
+        records.push(Dao { values: values });
+    }
+    Ok(records)
+}
+
+/// the textual representation of the value as accepted by postgres, None for NULL
+fn text_value(value: &Value) -> Option<String> {
+    match *value {
+        Value::Bool(x) => Some(if x { "t".to_owned() } else { "f".to_owned() }),
+        Value::I8(x) => Some(x.to_string()),
+        Value::I16(x) => Some(x.to_string()),
+        Value::I32(x) => Some(x.to_string()),
+        Value::I64(x) => Some(x.to_string()),
+        Value::U8(x) => Some(x.to_string()),
+        Value::U16(x) => Some(x.to_string()),
+        Value::U32(x) => Some(x.to_string()),
+        Value::U64(x) => Some(x.to_string()),
+        Value::F32(x) => Some(x.to_string()),
+        Value::F64(x) => Some(x.to_string()),
+        Value::String(ref x) => Some(x.to_owned()),
+        Value::VecU8(ref x) => {
+            let hex: Vec<String> = x.iter().map(|b| format!("{:02x}", b)).collect();
+            Some(format!("\\x{}", hex.concat()))
+        }
+        Value::Object(_) => Some(value.to_json().to_string()),
+        Value::Json(ref x) => Some(x.to_string()),
+        Value::Uuid(ref x) => Some(x.to_string()),
+        Value::DateTime(ref x) => Some(x.to_rfc3339()),
+        Value::NaiveDate(ref x) => Some(x.to_string()),
+        Value::NaiveTime(ref x) => Some(x.to_string()),
+        Value::NaiveDateTime(ref x) => Some(x.to_string()),
+        Value::None => None,
+    }
+}
+
+/// the binary representation of the value in network byte order, None for NULL.
+/// postgres has no 1 byte nor unsigned integers, so those values are rejected
+/// rather than sent in a representation the column would misread
+fn binary_value(value: &Value) -> Result<Option<Vec<u8>>, String> {
+    let mut buf = vec![];
+    match *value {
+        Value::Bool(x) => buf.push(if x { 1 } else { 0 }),
+        Value::I16(x) => push_i16(&mut buf, x),
+        Value::I32(x) => push_i32(&mut buf, x),
+        Value::I64(x) => push_i64(&mut buf, x),
+        Value::F32(x) => push_i32(&mut buf, x.to_bits() as i32),
+        Value::F64(x) => push_i64(&mut buf, x.to_bits() as i64),
+        Value::String(ref x) => buf.extend(x.as_bytes().iter().cloned()),
+        Value::VecU8(ref x) => buf.extend(x.iter().cloned()),
+        Value::Uuid(ref x) => buf.extend(x.as_bytes().iter().cloned()),
+        Value::DateTime(ref x) => {
+            let micros = (x.timestamp() - POSTGRES_EPOCH) * 1_000_000 + (x.nanosecond() / 1_000) as i64;
+            push_i64(&mut buf, micros);
+        }
+        Value::NaiveDateTime(ref x) => {
+            let micros = (x.timestamp() - POSTGRES_EPOCH) * 1_000_000 + (x.nanosecond() / 1_000) as i64;
+            push_i64(&mut buf, micros);
+        }
+        Value::NaiveDate(ref x) => {
+            let days = (*x - NaiveDate::from_ymd(2000, 1, 1)).num_days();
+            push_i32(&mut buf, days as i32);
+        }
+        Value::NaiveTime(ref x) => {
+            let micros = (*x - NaiveTime::from_hms(0, 0, 0)).num_microseconds().unwrap_or(0);
+            push_i64(&mut buf, micros);
+        }
+        Value::None => return Ok(None),
+        _ => {
+            return Err(format!("{:?} is not supported in the binary COPY format, use TEXT or CSV",
+                               value))
+        }
+    }
+    Ok(Some(buf))
+}
+
+/// backslash, newline, carriage return and tab are escaped with a backslash
+fn escape_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape_text(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') => unescaped.push('\n'),
+                Some('r') => unescaped.push('\r'),
+                Some('t') => unescaped.push('\t'),
+                Some('b') => unescaped.push('\x08'),
+                Some('f') => unescaped.push('\x0c'),
+                Some('v') => unescaped.push('\x0b'),
+                Some(other) => unescaped.push(other),
+                None => unescaped.push('\\'),
+            }
+        } else {
+            unescaped.push(c);
+        }
+    }
+    unescaped
+}
+
+/// an empty string is quoted so it is not read as NULL
+fn quote_csv(text: &str) -> String {
+    if text.is_empty() || text.contains(|c: char| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", text.replace("\"", "\"\""))
+    } else {
+        text.to_owned()
+    }
+}
+
+fn push_i16(buf: &mut Vec<u8>, value: i16) {
+    buf.push((value >> 8) as u8);
+    buf.push(value as u8);
+}
+
+fn push_i32(buf: &mut Vec<u8>, value: i32) {
+    push_i16(buf, (value >> 16) as i16);
+    push_i16(buf, value as i16);
+}
+
+fn push_i64(buf: &mut Vec<u8>, value: i64) {
+    push_i32(buf, (value >> 32) as i32);
+    push_i32(buf, value as i32);
+}
+
+#[test]
+fn test_text_and_csv_row() {
+    let name = Value::String("tab\there, \"quoted\"".to_owned());
+    let values = vec![&Value::I32(1), &name, &Value::None, &Value::Bool(true)];
+    assert_eq!(text_row(&values), "1\ttab\\there, \"quoted\"\t\\N\tt\n");
+    assert_eq!(csv_row(&values), "1,\"tab\there, \"\"quoted\"\"\",,t\n");
+
+    let mut object = BTreeMap::new();
+    object.insert("color".to_owned(), Value::String("red".to_owned()));
+    assert_eq!(text_row(&[&Value::Object(object)]), "{\"color\":\"red\"}\n");
+}
+
+#[test]
+fn test_binary_row() {
+    let values = vec![&Value::I32(258), &Value::None];
+    assert_eq!(binary_row(&values).unwrap(),
+               vec![0, 2, 0, 0, 0, 4, 0, 0, 1, 2, 255, 255, 255, 255]);
+    assert!(binary_row(&[&Value::U64(1)]).is_err());
+    assert!(binary_row(&[&Value::U32(1)]).is_err());
+    assert!(binary_row(&[&Value::I8(1)]).is_err());
+}
+
+#[test]
+fn test_copy_reader() {
+    let mut dao = Dao::new();
+    dao.set("product_id", &1);
+    dao.set("name", &"line\nbreak");
+    let mut rows = vec![dao].into_iter();
+    let mut reader = CopyReader::new(&mut rows, &["product_id", "name", "price"], CopyFormat::TEXT);
+    let mut data = String::new();
+    reader.read_to_string(&mut data).unwrap();
+    assert_eq!(data, "1\tline\\nbreak\t\\N\n");
+
+    let records = parse_text(&data, &["product_id", "name", "price"]).unwrap();
+    assert_eq!(records[0].values.get("name"),
+               Some(&Value::String("line\nbreak".to_owned())));
+    assert_eq!(records[0].values.get("price"), Some(&Value::None));
+}
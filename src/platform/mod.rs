@@ -1,10 +1,12 @@
 pub mod postgres;
+pub mod copy;
 #[cfg(feature = "sqlite")]
 pub mod sqlite;
 #[cfg(feature = "mysql")]
 pub mod mysql;
 
 pub use self::postgres::Postgres;
+pub use self::copy::CopyFormat;
 #[cfg(feature = "sqlite")]
 pub use self::sqlite::Sqlite;
 #[cfg(feature = "mysql")]
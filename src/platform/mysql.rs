@@ -1,4 +1,5 @@
 use query::Query;
+use query::{Filter, Equality, Operand, ColumnName, TableName};
 use dao::Dao;
 
 use dao::Value;
@@ -10,26 +11,158 @@ use mysql::value::Value as MyValue;
 use mysql::consts::ColumnType;
 use mysql::value::FromValue;
 use mysql::value::IntoValue;
-use mysql::error::MyResult;
-use mysql::conn::Stmt;
-use mysql::conn::pool::MyPool;
+use mysql::conn::pool::MyPooledConn;
 use chrono::naive::datetime::NaiveDateTime;
 
-use table::Table;
-use database::DatabaseDDL;
+use table::{Table, Column, Foreign};
+use database::{DatabaseDDL, DatabaseDev};
 use database::DbError;
 use time::Timespec;
+use std::cell::{Cell, RefCell, RefMut};
+use std::collections::BTreeMap;
+use mysql::error::MyError;
+use platform::PlatformError;
+use regex::Regex;
+#[cfg(test)]
+use std::io;
+use diff::Change;
 
 pub struct Mysql {
-    pool: Option<MyPool>,
+    /// a single connection is held from the pool,
+    /// so statements of the same transaction run in the same session
+    conn: Option<RefCell<MyPooledConn>>,
+    /// the number of nested transactions currently open
+    depth: Cell<usize>,
+    /// the database selected on this connection, forgotten when another one is used
+    database: RefCell<Option<String>>,
+    /// the primary columns of each table already looked up, flagged when AUTO_INCREMENT,
+    /// forgotten when the schema is changed on this connection
+    primary_columns: RefCell<BTreeMap<String, Vec<(String, bool)>>>,
+    /// the innodb_autoinc_lock_mode of the server, looked up once
+    autoinc_lock_mode: Cell<Option<i64>>,
+    /// the server is older than mysql 8.0, which has no FOR SHARE, OF, NOWAIT and SKIP LOCKED
+    lock_in_share_mode: Cell<bool>,
 }
 impl Mysql{
     pub fn new() -> Self {
-        Mysql { pool: None }
+        Mysql {
+            conn: None,
+            depth: Cell::new(0),
+            database: RefCell::new(None),
+            primary_columns: RefCell::new(BTreeMap::new()),
+            autoinc_lock_mode: Cell::new(None),
+            lock_in_share_mode: Cell::new(false),
+        }
+    }
+
+    pub fn with_pooled_connection(conn: MyPooledConn) -> Self {
+        Mysql {
+            conn: Some(RefCell::new(conn)),
+            depth: Cell::new(0),
+            database: RefCell::new(None),
+            primary_columns: RefCell::new(BTreeMap::new()),
+            autoinc_lock_mode: Cell::new(None),
+            lock_in_share_mode: Cell::new(false),
+        }
+    }
+
+    /// build the sql for a server of this version, ie: "5.7.30", without a connection
+    pub fn with_version(version: &str) -> Self {
+        let my = Mysql::new();
+        my.lock_in_share_mode.set(is_before_8_0(version));
+        my
+    }
+
+    /// look up the version of the connected server, the features used in the sql depend on it
+    pub fn detect_version(&self) -> Result<(), DbError> {
+        let version = try!(self.version());
+        self.lock_in_share_mode.set(is_before_8_0(&version));
+        Ok(())
+    }
+
+    /// the database selected on this connection, looked up once
+    fn current_database(&self) -> Result<String, DbError> {
+        if let Some(ref database) = *self.database.borrow() {
+            return Ok(database.to_owned());
+        }
+        let database: String = match try!(self.execute_sql_with_one_return("SELECT DATABASE() AS db", &[])) {
+            Some(dao) => {
+                match dao.get_opt("db") {
+                    Some(database) => database,
+                    None => return Err(DbError::new("No database is selected")),
+                }
+            }
+            None => return Err(DbError::new("No database is selected")),
+        };
+        *self.database.borrow_mut() = Some(database.to_owned());
+        Ok(database)
+    }
+
+    /// the primary columns of the table, and whether each is AUTO_INCREMENT, looked up once per table
+    fn get_primary_columns(&self, table: &TableName) -> Result<Vec<(String, bool)>, DbError> {
+        let schema = match table.schema {
+            Some(ref schema) => schema.to_owned(),
+            None => try!(self.current_database()),
+        };
+        let key = format!("{}.{}", schema, table.name);
+        if let Some(columns) = self.primary_columns.borrow().get(&key) {
+            return Ok(columns.clone());
+        }
+        let sql = "
+            SELECT column_name AS column_name,
+                   extra AS extra
+              FROM information_schema.columns
+             WHERE table_schema = ?
+               AND table_name = ?
+               AND column_key = 'PRI'
+          ORDER BY ordinal_position";
+        let params = vec![Value::String(schema.to_owned()), Value::String(table.name.to_owned())];
+        let result = try!(self.execute_sql_with_return(sql, &params));
+        let mut columns = vec![];
+        for r in result {
+            let column_name: String = r.get("column_name");
+            let extra: String = r.get_opt("extra").unwrap_or(String::new());
+            columns.push((column_name, extra.to_lowercase().contains("auto_increment")));
+        }
+        self.primary_columns.borrow_mut().insert(key, columns.clone());
+        Ok(columns)
     }
 
-    pub fn with_pooled_connection(pool: MyPool) -> Self {
-        Mysql { pool: Some(pool) }
+    /// how the AUTO_INCREMENT values are generated for inserts,
+    /// 2 (interleaved) does not keep the values of a multi-row insert consecutive
+    fn get_autoinc_lock_mode(&self) -> Result<i64, DbError> {
+        if let Some(mode) = self.autoinc_lock_mode.get() {
+            return Ok(mode);
+        }
+        let mode = match try!(self.execute_sql_with_one_return("SELECT @@innodb_autoinc_lock_mode AS mode", &[])) {
+            Some(dao) => dao.get_opt("mode").unwrap_or(2),
+            None => 2,
+        };
+        self.autoinc_lock_mode.set(Some(mode));
+        Ok(mode)
+    }
+
+    /// the AUTO_INCREMENT primary column of the table being inserted into
+    fn auto_increment_column(&self, query: &Query) -> Result<Option<String>, DbError> {
+        let table = match query.get_from_table() {
+            Some(table) => table.clone(),
+            None => return Err(DbError::new("There should be a table to select back from")),
+        };
+        let columns = try!(self.get_primary_columns(&table));
+        Ok(columns.into_iter().find(|&(_, auto_increment)| auto_increment).map(|(column, _)| column))
+    }
+
+    /// forget the looked up primary columns when the statement changes the schema,
+    /// and the current database when another one is used
+    fn forget_schema(&self, sql: &str) {
+        let sql = sql.trim_left().to_uppercase();
+        if sql.starts_with("CREATE") || sql.starts_with("ALTER") || sql.starts_with("DROP") ||
+           sql.starts_with("RENAME") {
+            self.primary_columns.borrow_mut().clear();
+        }
+        if sql.starts_with("USE") {
+            *self.database.borrow_mut() = None;
+        }
     }
 
     fn from_rust_type_tosql(types: &[Value]) -> Vec<MyValue> {
@@ -63,6 +196,10 @@ impl Mysql{
                     let v = x.into_value();
                     params.push(v);
                 },
+                Value::U16(ref x) => {
+                    let v = x.into_value();
+                    params.push(v);
+                },
                 Value::U32(ref x) => {
                     let v = x.into_value();
                     params.push(v);
@@ -79,6 +216,34 @@ impl Mysql{
                     let v = x.into_value();
                     params.push(v);
                 },
+                Value::VecU8(ref x) => {
+                    params.push(MyValue::Bytes(x.clone()));
+                },
+                Value::Uuid(ref x) => {
+                    params.push(MyValue::Bytes(x.to_hyphenated_string().into_bytes()));
+                },
+                Value::Json(ref x) => {
+                    params.push(MyValue::Bytes(x.to_string().into_bytes()));
+                },
+                Value::DateTime(ref x) => {
+                    let v = x.format("%Y-%m-%d %H:%M:%S%.6f").to_string();
+                    params.push(MyValue::Bytes(v.into_bytes()));
+                },
+                Value::NaiveDateTime(ref x) => {
+                    let v = x.format("%Y-%m-%d %H:%M:%S%.6f").to_string();
+                    params.push(MyValue::Bytes(v.into_bytes()));
+                },
+                Value::NaiveDate(ref x) => {
+                    let v = x.format("%Y-%m-%d").to_string();
+                    params.push(MyValue::Bytes(v.into_bytes()));
+                },
+                Value::NaiveTime(ref x) => {
+                    let v = x.format("%H:%M:%S%.6f").to_string();
+                    params.push(MyValue::Bytes(v.into_bytes()));
+                },
+                Value::None => {
+                    params.push(MyValue::NULL);
+                },
                 _ => panic!("not yet here {:?}", t),
             }
         }
@@ -93,7 +258,7 @@ impl Mysql{
                     println!("sql to rust {:?} type: {:?}", value, column_type);
                     match *value{
                         MyValue::NULL => {
-                            Value::Null
+                            Value::None
                         },
                         
                         _ => {
@@ -122,7 +287,7 @@ impl Mysql{
                                     let v: f64 = FromValue::from_value(value.clone());
                                     Value::F64(v)
                                 },
-                                ColumnType::MYSQL_TYPE_NULL => Value::Null,
+                                ColumnType::MYSQL_TYPE_NULL => Value::None,
                                 ColumnType::MYSQL_TYPE_TIMESTAMP => {
                                     let v: Timespec = FromValue::from_value(value.clone());
                                     let t = NaiveDateTime::from_timestamp(v.sec, v.nsec as u32);
@@ -207,80 +372,178 @@ impl Mysql{
                     }
                     
                 },
-            None => Value::Null,
+            None => Value::None,
         }
     }
 
-    ///
-    /// convert rust data type names to database data type names
-    /// will be used in generating SQL for table creation
-    /// FIXME, need to restore the exact data type as before
-    fn rust_type_to_dbtype(&self, rust_type: &str) -> String {
-        match rust_type {
-            "bool" => {
-                "boolean".to_owned()
-            }
-            "i8" => {
-                "integer".to_owned()
-            }
-            "i16" => {
-                "integer".to_owned()
-            }
-            "i32" => {
-                "integer".to_owned()
-            }
-            "u32" => {
-                "integer".to_owned()
-            }
-            "i64" => {
-                "integer".to_owned()
-            }
-            "f32" => {
-                "real".to_owned()
-            }
-            "f64" => {
-                "real".to_owned()
-            }
-            "String" => {
-                "text".to_owned()
-            }
-            "Vec<u8>" => {
-                "blob".to_owned()
-            }
-            "Json" => {
-                "text".to_owned()
-            }
-            "Uuid" => {
-                "varchar(36)".to_owned()
-            }
-            "NaiveDateTime" => {
-                "timestamp".to_owned()
-            }
-            "DateTime<UTC>" => {
-                "timestamp".to_owned()
-            }
-            "NaiveDate" => {
-                "date".to_owned()
+    /// the schema of the table, defaults to the current database
+    fn schema_or_current(&self, schema: &str) -> Result<String, DbError> {
+        if !schema.is_empty() {
+            return Ok(schema.to_owned());
+        }
+        self.current_database()
+    }
+
+    /// get the columns of the table, together with their key constraints
+    fn get_table_columns(&self, schema: &str, table: &str) -> Result<Vec<Column>, DbError> {
+        let sql = "
+            SELECT column_name AS column_name, column_type AS column_type,
+                   is_nullable AS is_nullable, column_default AS column_default,
+                   column_comment AS column_comment
+              FROM information_schema.columns
+             WHERE table_schema = ?
+               AND table_name = ?
+             ORDER BY ordinal_position";
+        let params = vec![Value::String(schema.to_owned()), Value::String(table.to_owned())];
+        let result = try!(self.execute_sql_with_return(sql, &params));
+        let keys = try!(self.get_table_keys(schema, table));
+        let mut columns = vec![];
+        for r in result {
+            let name: String = r.get("column_name");
+            let db_data_type: String = r.get("column_type");
+            let is_nullable: String = r.get("is_nullable");
+            let default: Option<String> = r.get_opt("column_default");
+            let comment: Option<String> = r.get_opt("column_comment");
+            let (_, data_type) = self.dbtype_to_rust_type(&db_data_type);
+            let mut column = Column {
+                name: name,
+                data_type: data_type,
+                db_data_type: db_data_type,
+                is_primary: false,
+                is_unique: false,
+                default: default,
+                comment: comment.and_then(|c| if c.is_empty() { None } else { Some(c) }),
+                not_null: is_nullable == "NO",
+                foreign: None,
+                is_inherited: false,
+            };
+            for &(ref column_name, ref constraint_type, ref foreign) in &keys {
+                if *column_name == column.name {
+                    match &constraint_type[..] {
+                        "PRIMARY KEY" => column.is_primary = true,
+                        "UNIQUE" => column.is_unique = true,
+                        "FOREIGN KEY" => column.foreign = foreign.clone(),
+                        _ => (),
+                    }
+                }
             }
-            "NaiveTime" => {
-                "time".to_owned()
+            columns.push(column);
+        }
+        Ok(columns)
+    }
+
+    /// get the (column, constraint type, referred column) of the key constraints of the table
+    fn get_table_keys(&self,
+                      schema: &str,
+                      table: &str)
+                      -> Result<Vec<(String, String, Option<Foreign>)>, DbError> {
+        let sql = "
+            SELECT kcu.column_name AS column_name,
+                   tc.constraint_type AS constraint_type,
+                   kcu.referenced_table_schema AS referenced_table_schema,
+                   kcu.referenced_table_name AS referenced_table_name,
+                   kcu.referenced_column_name AS referenced_column_name
+              FROM information_schema.key_column_usage kcu
+              JOIN information_schema.table_constraints tc
+                ON tc.constraint_schema = kcu.constraint_schema
+               AND tc.table_name = kcu.table_name
+               AND tc.constraint_name = kcu.constraint_name
+             WHERE kcu.table_schema = ?
+               AND kcu.table_name = ?";
+        let params = vec![Value::String(schema.to_owned()), Value::String(table.to_owned())];
+        let result = try!(self.execute_sql_with_return(sql, &params));
+        let mut keys = vec![];
+        for r in result {
+            let column_name: String = r.get("column_name");
+            let constraint_type: String = r.get("constraint_type");
+            let foreign_table: Option<String> = r.get_opt("referenced_table_name");
+            let foreign = match foreign_table {
+                Some(foreign_table) => {
+                    Some(Foreign {
+                        schema: r.get_opt("referenced_table_schema").unwrap_or(String::new()),
+                        table: foreign_table,
+                        column: r.get_opt("referenced_column_name").unwrap_or(String::new()),
+                    })
+                }
+                None => None,
+            };
+            keys.push((column_name, constraint_type, foreign));
+        }
+        Ok(keys)
+    }
+
+    /// the name of the foreign key constraint on the column, as it is named in the database
+    fn get_foreign_key_name(&self,
+                            schema: &str,
+                            table: &str,
+                            column: &str)
+                            -> Result<String, DbError> {
+        let schema = try!(self.schema_or_current(schema));
+        let sql = "
+            SELECT constraint_name AS constraint_name
+              FROM information_schema.key_column_usage
+             WHERE table_schema = ?
+               AND table_name = ?
+               AND column_name = ?
+               AND referenced_table_name IS NOT NULL";
+        let params = vec![Value::String(schema),
+                          Value::String(table.to_owned()),
+                          Value::String(column.to_owned())];
+        let dao = try!(self.execute_sql_with_one_return(sql, &params));
+        match dao.and_then(|dao| dao.get_opt("constraint_name")) {
+            Some(constraint) => Ok(constraint),
+            None => {
+                Err(DbError::new(&format!("No foreign key found on {}.{}", table, column)))
             }
-            "HashMap<String, Option<String>>" => {
-                "text".to_owned()
+        }
+    }
+
+    fn get_table_comment(&self, schema: &str, table: &str) -> Result<Option<String>, DbError> {
+        let sql = "
+            SELECT table_comment AS table_comment
+              FROM information_schema.tables
+             WHERE table_schema = ?
+               AND table_name = ?";
+        let params = vec![Value::String(schema.to_owned()), Value::String(table.to_owned())];
+        let dao = try!(self.execute_sql_with_one_return(sql, &params));
+        match dao {
+            Some(dao) => {
+                let comment: Option<String> = dao.get_opt("table_comment");
+                Ok(comment.and_then(|c| if c.is_empty() { None } else { Some(c) }))
             }
-            _ => panic!("Unable to get the equivalent database data type for {}",
-                        rust_type),
+            None => Ok(None),
         }
     }
 
-    fn get_prepared_statement<'a>(&'a self, sql: &'a str) -> MyResult<Stmt> {
-        self.pool.as_ref().unwrap().prepare(sql)
+    fn get_connection(&self) -> RefMut<MyPooledConn> {
+        match self.conn {
+            Some(ref conn) => conn.borrow_mut(),
+            None => panic!("No connection for this database"),
+        }
+    }
+
+    /// the column as it is declared in ADD COLUMN and MODIFY COLUMN
+    fn column_definition(&self, column: &Column) -> String {
+        let data_type = if column.db_data_type.is_empty() {
+            self.rust_type_to_dbtype(&column.data_type)
+        } else {
+            column.db_data_type.to_owned()
+        };
+        let mut definition = format!("{} {}", column.name, data_type);
+        if column.not_null {
+            definition.push_str(" NOT NULL");
+        }
+        if let Some(ref default) = column.default {
+            definition.push_str(" DEFAULT ");
+            definition.push_str(default);
+        }
+        definition
     }
 }
 
 impl Database for Mysql {
     fn version(&self) -> Result<String, DbError> {
-        let sql = "SELECT version()";
+        let sql = "SELECT VERSION() AS version";
         let dao = try!(self.execute_sql_with_one_return(sql, &vec![]));
         match dao {
             Some(dao) => Ok(dao.get("version")),
@@ -288,17 +551,8 @@ impl Database for Mysql {
         }
     }
 
-    fn begin(&self) {
-        unimplemented!()
-    }
-    fn commit(&self) {
-        unimplemented!()
-    }
-    fn rollback(&self) {
-        unimplemented!()
-    }
-    fn is_transacted(&self) -> bool {
-        false
+    fn transaction_depth(&self) -> &Cell<usize> {
+        &self.depth
     }
     fn is_closed(&self) -> bool {
         false
@@ -317,14 +571,114 @@ impl Database for Mysql {
 
     /// return this list of options, supported features in the database
     fn sql_options(&self) -> Vec<SqlOption> {
-        vec![
+        let mut options = vec![
             SqlOption::UsesQuestionMark,//mysql uses question mark instead of the numbered params
-        ]
+            SqlOption::SupportsOnDuplicateKey,
+            SqlOption::SupportsMultiTableUpdate,
+            SqlOption::SupportsSetDefault,
+            SqlOption::SupportsRowLocking,
+        ];
+        if self.lock_in_share_mode.get() {
+            options.push(SqlOption::UsesLockInShareMode);
+        }
+        options
     }
 
-    fn update(&self, _query: &Query) -> Dao {
-        unimplemented!()
+    fn max_params(&self) -> usize {
+        65535
+    }
+
+    fn primary_columns(&self, table: &TableName) -> Result<Vec<String>, DbError> {
+        let columns = try!(self.get_primary_columns(table));
+        Ok(columns.into_iter().map(|(column, _)| column).collect())
     }
+
+    fn generates_consecutive_keys(&self, query: &Query) -> Result<bool, DbError> {
+        match try!(self.auto_increment_column(query)) {
+            Some(_) => Ok(try!(self.get_autoinc_lock_mode()) != 2),
+            None => Ok(false),
+        }
+    }
+
+    /// the rows of a multi-row insert get consecutive AUTO_INCREMENT values,
+    /// starting at LAST_INSERT_ID()
+    fn inserted_range_filters(&self, query: &Query, rows: usize) -> Result<(String, Vec<Filter>), DbError> {
+        let column = match try!(self.auto_increment_column(query)) {
+            Some(column) => column,
+            None => return Err(DbError::new("There is no AUTO_INCREMENT column to select back the inserted records")),
+        };
+        let first = Filter::bare_new(Operand::ColumnName(ColumnName::from_str(&column)),
+                                     Equality::GTE,
+                                     Operand::Sql("LAST_INSERT_ID()".to_owned(), vec![]));
+        let last = Filter::bare_new(Operand::ColumnName(ColumnName::from_str(&column)),
+                                    Equality::LT,
+                                    Operand::Sql("LAST_INSERT_ID() + ?".to_owned(), vec![Value::I64(rows as i64)]));
+        Ok((column, vec![first, last]))
+    }
+
+    /// the primary columns given in the insert are matched with their values,
+    /// the one that is not given must be the AUTO_INCREMENT column, which is matched with LAST_INSERT_ID().
+    /// An upsert may have updated an existing record instead, which is matched with its unique columns
+    fn inserted_filters(&self, query: &Query) -> Result<Vec<Filter>, DbError> {
+        let table = match query.get_from_table() {
+            Some(table) => table.clone(),
+            None => return Err(DbError::new("There should be a table to select back from")),
+        };
+        let columns = query.get_enumerated_columns();
+        // a NULL given to the AUTO_INCREMENT column has the value generated as well
+        let given = |column: &str| {
+            match columns.iter().position(|c| c.column == column) {
+                Some(index) => {
+                    match query.values[index] {
+                        Operand::Value(Value::None) => None,
+                        ref value => Some(value.clone()),
+                    }
+                }
+                None => None,
+            }
+        };
+        let mut filters = vec![];
+        let primary_columns = try!(self.get_primary_columns(&table));
+        if primary_columns.is_empty() {
+            return Err(DbError::new(&format!("Table {} has no primary key to select back the inserted record",
+                                             table.name)));
+        }
+        for (column, auto_increment) in primary_columns {
+            let value = match given(&column) {
+                Some(value) => value,
+                None if !query.on_conflict.is_empty() => {
+                    // LAST_INSERT_ID() is not the id of the record updated by ON DUPLICATE KEY UPDATE
+                    filters.clear();
+                    for unique in &query.on_conflict {
+                        match given(unique) {
+                            Some(value) => {
+                                filters.push(Filter::bare_new(Operand::ColumnName(ColumnName::from_str(unique)),
+                                                              Equality::EQ,
+                                                              value))
+                            }
+                            None => {
+                                return Err(DbError::new(&format!("The unique column {} is not given, \
+                                                                  the upserted record can not be selected back",
+                                                                 unique)))
+                            }
+                        }
+                    }
+                    return Ok(filters);
+                }
+                None if auto_increment => Operand::Sql("LAST_INSERT_ID()".to_owned(), vec![]),
+                None => {
+                    return Err(DbError::new(&format!("The primary column {} is not given and is not AUTO_INCREMENT, \
+                                                      the inserted record can not be selected back",
+                                                     column)))
+                }
+            };
+            filters.push(Filter::bare_new(Operand::ColumnName(ColumnName::from_str(&column)),
+                                          Equality::EQ,
+                                          value));
+        }
+        Ok(filters)
+    }
+
     fn delete(&self, _query: &Query) -> Result<usize, String> {
         unimplemented!()
     }
@@ -332,8 +686,8 @@ impl Database for Mysql {
     fn execute_sql_with_return(&self, sql: &str, params: &[Value]) -> Result<Vec<Dao>, DbError> {
         println!("SQL: \n{}", sql);
         println!("param: {:?}", params);
-        assert!(self.pool.is_some());
-        let mut stmt = try!(self.get_prepared_statement(sql));
+        let mut conn = self.get_connection();
+        let mut stmt = try!(conn.prepare(sql));
         let mut columns = vec![];
         for col in stmt.columns_ref().unwrap() {
             let column_name = String::from_utf8(col.name.clone()).unwrap();
@@ -372,23 +726,31 @@ impl Database for Mysql {
     /// generic execute sql which returns not much information,
     /// returns only the number of affected records or errors
     /// can be used with DDL operations (CREATE, DELETE, ALTER, DROP)
+    /// statements without parameters are sent as plain text,
+    /// since transaction statements (BEGIN, SAVEPOINT, ..) can not be prepared in mysql
     fn execute_sql(&self, sql: &str, params: &[Value]) -> Result<usize, DbError> {
         println!("SQL: \n{}", sql);
         println!("param: {:?}", params);
-        let to_sql_types = Mysql::from_rust_type_tosql(params);
-        assert!(self.pool.is_some());
-        let result = try!(self.pool.as_ref().unwrap().prep_exec(sql, &to_sql_types));
-        Ok(result.affected_rows() as usize)
+        self.forget_schema(sql);
+        let mut conn = self.get_connection();
+        if params.is_empty() {
+            let result = try!(conn.query(sql));
+            Ok(result.affected_rows() as usize)
+        } else {
+            let to_sql_types = Mysql::from_rust_type_tosql(params);
+            let result = try!(conn.prep_exec(sql, &to_sql_types));
+            Ok(result.affected_rows() as usize)
+        }
     }
 
 }
 
 impl DatabaseDDL for Mysql{
-    fn create_schema(&self, _schema: &str) {
+    fn create_schema(&self, _schema: &str) -> Result<(), DbError> {
         unimplemented!()
     }
 
-    fn drop_schema(&self, _schema: &str) {
+    fn drop_schema(&self, _schema: &str) -> Result<(), DbError> {
         unimplemented!()
     }
 
@@ -416,31 +778,434 @@ impl DatabaseDDL for Mysql{
         w.append(")");
         w
     }
-    fn create_table(&self, table: &Table) {
+    fn create_table(&self, table: &Table) -> Result<(), DbError> {
         let frag = self.build_create_table(table);
-        match self.execute_sql(&frag.sql, &vec![]) {
-            Ok(_) => println!("created table.."),
-            Err(e) => panic!("table not created {}", e),
-        }
+        try!(self.execute_sql(&frag.sql, &vec![]));
+        println!("created table..");
+        Ok(())
     }
 
-    fn rename_table(&self, _table: &Table, _new_tablename: String) {
+    fn rename_table(&self, _table: &Table, _new_tablename: String) -> Result<(), DbError> {
         unimplemented!()
     }
 
-    fn drop_table(&self, _table: &Table) {
+    fn drop_table(&self, _table: &Table) -> Result<(), DbError> {
         unimplemented!()
     }
 
-    fn set_foreign_constraint(&self, _model: &Table) {
+    fn set_foreign_constraint(&self, _model: &Table) -> Result<(), DbError> {
         unimplemented!()
     }
 
-    fn set_primary_constraint(&self, _model: &Table) {
+    fn set_primary_constraint(&self, _model: &Table) -> Result<(), DbError> {
         unimplemented!()
     }
+
+    fn build_change(&self, change: &Change) -> Result<SqlFrag, DbError> {
+        let sql = match *change {
+            Change::AddTable(ref table) => return Ok(self.build_create_table(table)),
+            Change::DropTable(ref table) => format!("DROP TABLE {}", table.name),
+            Change::AddColumn(ref table, ref column) => {
+                format!("ALTER TABLE {} ADD COLUMN {}",
+                        table.name,
+                        self.column_definition(column))
+            }
+            Change::DropColumn(ref table, ref column) => {
+                format!("ALTER TABLE {} DROP COLUMN {}", table.name, column.name)
+            }
+            // mysql redefines the whole column when changing the type or nullability
+            Change::AlterColumnType(ref table, ref column) |
+            Change::AlterColumnNullable(ref table, ref column) => {
+                format!("ALTER TABLE {} MODIFY COLUMN {}",
+                        table.name,
+                        self.column_definition(column))
+            }
+            Change::AlterColumnDefault(ref table, ref column) => {
+                match column.default {
+                    Some(ref default) => {
+                        format!("ALTER TABLE {} ALTER COLUMN {} SET DEFAULT {}",
+                                table.name,
+                                column.name,
+                                default)
+                    }
+                    None => {
+                        format!("ALTER TABLE {} ALTER COLUMN {} DROP DEFAULT",
+                                table.name,
+                                column.name)
+                    }
+                }
+            }
+            // the constraint is named by mysql, it is looked up again when dropped
+            Change::AddForeignKey(ref table, ref column) => {
+                match column.foreign {
+                    Some(ref foreign) => {
+                        format!("ALTER TABLE {} ADD FOREIGN KEY ({}) REFERENCES {} ({})",
+                                table.name,
+                                column.name,
+                                foreign.table,
+                                foreign.column)
+                    }
+                    None => return Err(DbError::new(&format!("Column {} has no foreign key", column.name))),
+                }
+            }
+            Change::DropForeignKey(ref table, ref column) => {
+                let constraint = try!(self.get_foreign_key_name(&table.schema,
+                                                                &table.name,
+                                                                &column.name));
+                format!("ALTER TABLE {} DROP FOREIGN KEY {}", table.name, constraint)
+            }
+        };
+        let mut w = SqlFrag::new(self.sql_options());
+        w.append(&sql);
+        Ok(w)
+    }
 }
 
 
-// TODO: need to implement trait DatabaseDev for Mysql
-// Mysql can be used as development database
+/// mysql has no table inheritance, the schema is the database
+impl DatabaseDev for Mysql {
+    fn get_table_sub_class(&self, _schema: &str, _table: &str) -> Vec<String> {
+        vec![]
+    }
+
+    fn get_parent_table(&self, _schema: &str, _table: &str) -> Option<String> {
+        None
+    }
+
+    fn get_table_metadata(&self, schema: &str, table: &str, is_view: bool) -> Result<Table, DbError> {
+        let schema = try!(self.schema_or_current(schema));
+        let columns = try!(self.get_table_columns(&schema, table));
+        let comment = try!(self.get_table_comment(&schema, table));
+        Ok(Table {
+            schema: schema,
+            name: table.to_owned(),
+            parent_table: None,
+            sub_table: vec![],
+            comment: comment,
+            columns: columns,
+            is_view: is_view,
+        })
+    }
+
+    fn get_all_tables(&self) -> Result<Vec<(String, String, bool)>, DbError> {
+        let sql = "
+            SELECT table_schema AS table_schema, table_name AS table_name,
+                   table_type AS table_type
+              FROM information_schema.tables
+             WHERE table_schema = DATABASE()
+             ORDER BY table_name";
+        let result = try!(self.execute_sql_with_return(sql, &vec![]));
+        let mut tables: Vec<(String, String, bool)> = Vec::new();
+        for r in result {
+            let schema: String = r.get("table_schema");
+            let table: String = r.get("table_name");
+            let table_type: String = r.get("table_type");
+            tables.push((schema, table, table_type == "VIEW"))
+        }
+        Ok(tables)
+    }
+
+    fn get_inherited_columns(&self, _schema: &str, _table: &str) -> Vec<String> {
+        vec![]
+    }
+
+    /// the db_type is the column_type of information_schema, ie: `int(11) unsigned`,
+    /// types that has no equivalent rust type such as `geometry` are treated as String
+    fn dbtype_to_rust_type(&self, db_type: &str) -> (Vec<String>, String) {
+        let db_type = db_type.to_lowercase();
+        if db_type == "tinyint(1)" {
+            return (vec![], "bool".to_owned());
+        }
+        let unsigned = db_type.contains("unsigned");
+        let base_type = match db_type.find(|c: char| c == '(' || c == ' ') {
+            Some(pos) => &db_type[..pos],
+            None => &db_type[..],
+        };
+        match base_type {
+            "bool" | "boolean" => {
+                (vec![], "bool".to_owned())
+            }
+            "tinyint" => {
+                if unsigned {
+                    (vec![], "u8".to_owned())
+                } else {
+                    (vec![], "i8".to_owned())
+                }
+            }
+            "smallint" => {
+                if unsigned {
+                    (vec![], "u16".to_owned())
+                } else {
+                    (vec![], "i16".to_owned())
+                }
+            }
+            "mediumint" | "int" | "integer" | "year" => {
+                if unsigned {
+                    (vec![], "u32".to_owned())
+                } else {
+                    (vec![], "i32".to_owned())
+                }
+            }
+            "bigint" => {
+                if unsigned {
+                    (vec![], "u64".to_owned())
+                } else {
+                    (vec![], "i64".to_owned())
+                }
+            }
+            "float" => {
+                (vec![], "f32".to_owned())
+            }
+            "double" | "real" | "decimal" | "numeric" => {
+                (vec![], "f64".to_owned())
+            }
+            "char" | "varchar" | "tinytext" | "text" | "mediumtext" | "longtext" | "enum" | "set" => {
+                (vec![], "String".to_owned())
+            }
+            "binary" | "varbinary" | "bit" | "tinyblob" | "blob" | "mediumblob" | "longblob" => {
+                (vec![], "Vec<u8>".to_owned())
+            }
+            "json" => {
+                (vec!["rustc_serialize::json::Json".to_owned()], "Json".to_owned())
+            }
+            "datetime" | "timestamp" => {
+                (vec!["chrono::naive::datetime::NaiveDateTime".to_owned()],
+                 "NaiveDateTime".to_owned())
+            }
+            "date" => {
+                (vec!["chrono::naive::date::NaiveDate".to_owned()],
+                 "NaiveDate".to_owned())
+            }
+            "time" => {
+                (vec!["chrono::naive::time::NaiveTime".to_owned()],
+                 "NaiveTime".to_owned())
+            }
+            _ => (vec![], "String".to_owned()),
+        }
+    }
+
+    ///
+    /// convert rust data type names to database data type names
+    /// will be used in generating SQL for table creation
+    fn rust_type_to_dbtype(&self, rust_type: &str) -> String {
+        match rust_type {
+            "bool" => {
+                "tinyint(1)".to_owned()
+            }
+            "i8" => {
+                "tinyint".to_owned()
+            }
+            "i16" => {
+                "smallint".to_owned()
+            }
+            "i32" => {
+                "int".to_owned()
+            }
+            "i64" => {
+                "bigint".to_owned()
+            }
+            "u8" => {
+                "tinyint unsigned".to_owned()
+            }
+            "u16" => {
+                "smallint unsigned".to_owned()
+            }
+            "u32" => {
+                "int unsigned".to_owned()
+            }
+            "u64" => {
+                "bigint unsigned".to_owned()
+            }
+            "f32" => {
+                "float".to_owned()
+            }
+            "f64" => {
+                "double".to_owned()
+            }
+            "String" => {
+                "text".to_owned()
+            }
+            "Vec<u8>" => {
+                "blob".to_owned()
+            }
+            "Json" => {
+                "json".to_owned()
+            }
+            "Uuid" => {
+                "varchar(36)".to_owned()
+            }
+            "NaiveDateTime" => {
+                "datetime".to_owned()
+            }
+            "DateTime<UTC>" => {
+                "timestamp".to_owned()
+            }
+            "NaiveDate" => {
+                "date".to_owned()
+            }
+            "NaiveTime" => {
+                "time".to_owned()
+            }
+            "HashMap<String, Option<String>>" => {
+                "text".to_owned()
+            }
+            _ => panic!("Unable to get the equivalent database data type for {}",
+                        rust_type),
+        }
+    }
+}
+
+/// extract the first matching group of the pattern from the error message
+fn capture(pattern: &str, message: &str) -> Option<String> {
+    match Regex::new(pattern) {
+        Ok(re) => {
+            match re.captures(message) {
+                Some(cap) => cap.at(1).map(|c| c.to_owned()),
+                None => None,
+            }
+        }
+        Err(_) => None,
+    }
+}
+
+/// classify the mysql error into the platform agnostic DbError,
+/// based on the server error code
+pub fn to_db_error(err: MyError) -> DbError {
+    let info = match err {
+        MyError::MySqlError(ref e) => Some((e.code, e.message.clone())),
+        _ => None,
+    };
+    let is_io = match err {
+        MyError::MyIoError(_) => true,
+        _ => false,
+    };
+    classify_error(info, is_io, PlatformError::from(err))
+}
+
+/// the DbError of the server error code, the names involved are taken from the message
+fn classify_error(info: Option<(u16, String)>, is_io: bool, cause: PlatformError) -> DbError {
+    match info {
+        // ER_DUP_ENTRY: Duplicate entry '1' for key 'PRIMARY'
+        Some((1062, message)) => {
+            DbError::UniqueViolation {
+                constraint: capture(r"for key '([^']+)'", &message),
+                table: None,
+                cause: cause,
+            }
+        }
+        // ER_ROW_IS_REFERENCED_2, ER_NO_REFERENCED_ROW_2:
+        // ... a foreign key constraint fails (`db`.`table`, CONSTRAINT `fk` FOREIGN KEY ...
+        Some((1451, message)) | Some((1452, message)) => {
+            DbError::ForeignKeyViolation {
+                constraint: capture(r"CONSTRAINT `([^`]+)`", &message),
+                table: capture(r"\(`[^`]*`\.`([^`]+)`", &message),
+                cause: cause,
+            }
+        }
+        // ER_BAD_NULL_ERROR: Column 'name' cannot be null
+        // ER_NO_DEFAULT_FOR_FIELD: Field 'name' doesn't have a default value
+        Some((1048, message)) | Some((1364, message)) => {
+            DbError::NotNullViolation {
+                column: capture(r"'([^']+)'", &message),
+                table: None,
+                cause: cause,
+            }
+        }
+        // ER_LOCK_DEADLOCK
+        Some((1213, _)) => DbError::SerializationFailure(cause),
+        // ER_LOCK_WAIT_TIMEOUT, ER_QUERY_TIMEOUT
+        Some((1205, _)) | Some((3024, _)) => DbError::Timeout(cause),
+        Some(_) => DbError::PlatformError(cause),
+        None => {
+            if is_io {
+                DbError::ConnectionLost(cause)
+            } else {
+                DbError::PlatformError(cause)
+            }
+        }
+    }
+}
+
+/// whether the server version is older than mysql 8.0,
+/// mariadb is treated as such since it has no FOR SHARE
+fn is_before_8_0(version: &str) -> bool {
+    if version.to_lowercase().contains("mariadb") {
+        return true;
+    }
+    match version.split('.').next().and_then(|major| major.trim().parse::<u32>().ok()) {
+        Some(major) => major < 8,
+        None => true,
+    }
+}
+
+#[test]
+fn test_capture() {
+    assert_eq!(capture(r"for key '([^']+)'", "Duplicate entry '1' for key 'PRIMARY'"),
+               Some("PRIMARY".to_owned()));
+    let message = "Cannot add or update a child row: a foreign key constraint fails \
+                   (`bazaar`.`product`, CONSTRAINT `product_owner_fkey` FOREIGN KEY (`owner_id`))";
+    assert_eq!(capture(r"CONSTRAINT `([^`]+)`", message),
+               Some("product_owner_fkey".to_owned()));
+    assert_eq!(capture(r"\(`[^`]*`\.`([^`]+)`", message),
+               Some("product".to_owned()));
+}
+
+#[cfg(test)]
+fn io_cause() -> PlatformError {
+    PlatformError::from(MyError::MyIoError(io::Error::new(io::ErrorKind::Other, "test")))
+}
+
+#[test]
+fn test_classify_error() {
+    let info = Some((1062, "Duplicate entry '1' for key 'PRIMARY'".to_owned()));
+    match classify_error(info, false, io_cause()) {
+        DbError::UniqueViolation { constraint, .. } => assert_eq!(constraint, Some("PRIMARY".to_owned())),
+        other => panic!("expecting a unique violation, got {:?}", other),
+    }
+    let info = Some((1452,
+                     "Cannot add or update a child row: a foreign key constraint fails \
+                      (`bazaar`.`product`, CONSTRAINT `product_owner_fkey` FOREIGN KEY (`owner_id`))"
+                         .to_owned()));
+    match classify_error(info, false, io_cause()) {
+        DbError::ForeignKeyViolation { constraint, table, .. } => {
+            assert_eq!(constraint, Some("product_owner_fkey".to_owned()));
+            assert_eq!(table, Some("product".to_owned()));
+        }
+        other => panic!("expecting a foreign key violation, got {:?}", other),
+    }
+    let info = Some((1048, "Column 'name' cannot be null".to_owned()));
+    match classify_error(info, false, io_cause()) {
+        DbError::NotNullViolation { column, .. } => assert_eq!(column, Some("name".to_owned())),
+        other => panic!("expecting a not null violation, got {:?}", other),
+    }
+    match classify_error(Some((1213, "Deadlock found".to_owned())), false, io_cause()) {
+        DbError::SerializationFailure(_) => (),
+        other => panic!("expecting a serialization failure, got {:?}", other),
+    }
+    match classify_error(Some((1205, "Lock wait timeout exceeded".to_owned())), false, io_cause()) {
+        DbError::Timeout(_) => (),
+        other => panic!("expecting a timeout, got {:?}", other),
+    }
+    match classify_error(None, true, io_cause()) {
+        DbError::ConnectionLost(_) => (),
+        other => panic!("expecting a lost connection, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_dbtype_to_rust_type() {
+    let my = Mysql::new();
+    assert_eq!(my.dbtype_to_rust_type("tinyint(1)").1, "bool");
+    assert_eq!(my.dbtype_to_rust_type("int(11) unsigned").1, "u32");
+    assert_eq!(my.dbtype_to_rust_type("varchar(255)").1, "String");
+    assert_eq!(my.dbtype_to_rust_type("datetime").1, "NaiveDateTime");
+    assert_eq!(my.dbtype_to_rust_type("geometry").1, "String");
+}
+
+#[test]
+fn test_is_before_8_0() {
+    assert!(is_before_8_0("5.7.30-log"));
+    assert!(is_before_8_0("10.5.8-MariaDB"));
+    assert!(!is_before_8_0("8.0.21"));
+    assert!(!is_before_8_0("8.4.0"));
+}
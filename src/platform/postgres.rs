@@ -14,10 +14,23 @@ use database::SqlOption;
 use r2d2::PooledConnection;
 use r2d2_postgres::PostgresConnectionManager;
 use rustc_serialize::json::Json;
+use std::cell::Cell;
+use postgres::error::Error as PgError;
+use postgres::error::SqlState;
+use diff::Change;
+use platform::PlatformError;
+use platform::copy::{self, CopyFormat, CopyReader};
+use dao::IsDao;
+use table::IsTable;
+use std::io::Write;
+#[cfg(test)]
+use std::io;
 
 pub struct Postgres {
     /// a connection pool is provided
     pub pool: Option<PooledConnection<PostgresConnectionManager>>,
+    /// the number of nested transactions currently open
+    depth: Cell<usize>,
 }
 
 /// Build the Query into a SQL statements that is a valid
@@ -32,12 +45,18 @@ impl Postgres {
     /// useful when just building sql queries specific to this platform
     /// inexpensive operation, so can have multiple instances
     pub fn new() -> Self {
-        Postgres { pool: None }
+        Postgres {
+            pool: None,
+            depth: Cell::new(0),
+        }
     }
 
 
     pub fn with_pooled_connection(pool: PooledConnection<PostgresConnectionManager>) -> Self {
-        Postgres { pool: Some(pool) }
+        Postgres {
+            pool: Some(pool),
+            depth: Cell::new(0),
+        }
     }
 
 
@@ -49,6 +68,68 @@ impl Postgres {
         }
     }
 
+    /// stream the records into the table with `COPY ... FROM STDIN`,
+    /// returns the number of records copied
+    pub fn copy_in(&self,
+                   table: &str,
+                   columns: &[&str],
+                   rows: &mut Iterator<Item = Dao>,
+                   format: CopyFormat)
+                   -> Result<u64, DbError> {
+        let sql = format!("COPY {} ({}) FROM STDIN{}",
+                          table,
+                          columns.join(", "),
+                          format.options());
+        println!("SQL: \n{}", sql);
+        let stmt = try!(self.get_connection().prepare(&sql));
+        let mut reader = CopyReader::new(rows, columns, format);
+        let count = try!(stmt.copy_in(&[], &mut reader));
+        Ok(count)
+    }
+
+    /// copy the records into their table, all the columns of the table are copied
+    pub fn copy_in_records<T>(&self, records: &[T], format: CopyFormat) -> Result<u64, DbError>
+        where T: IsTable + IsDao
+    {
+        let table = T::table();
+        let columns: Vec<&str> = table.columns.iter().map(|c| &c.name as &str).collect();
+        let mut rows = records.iter().map(|r| r.to_dao());
+        self.copy_in(&table.complete_name(), &columns, &mut rows, format)
+    }
+
+    /// write the columns of the table into the writer with `COPY ... TO STDOUT`,
+    /// returns the number of records copied
+    pub fn copy_out<W: Write>(&self,
+                              table: &str,
+                              columns: &[&str],
+                              format: CopyFormat,
+                              writer: &mut W)
+                              -> Result<u64, DbError> {
+        let sql = format!("COPY {} ({}) TO STDOUT{}",
+                          table,
+                          columns.join(", "),
+                          format.options());
+        println!("SQL: \n{}", sql);
+        let stmt = try!(self.get_connection().prepare(&sql));
+        let count = try!(stmt.copy_out(&[], writer));
+        Ok(count)
+    }
+
+    /// export the columns of the table with `COPY ... TO STDOUT` into daos,
+    /// the values are strings since COPY doesn't carry the data types
+    pub fn copy_out_dao(&self, table: &str, columns: &[&str]) -> Result<Vec<Dao>, DbError> {
+        let mut data = vec![];
+        try!(self.copy_out(table, columns, CopyFormat::TEXT, &mut data));
+        let data = match String::from_utf8(data) {
+            Ok(data) => data,
+            Err(e) => return Err(DbError::new(&format!("{}", e))),
+        };
+        match copy::parse_text(&data, columns) {
+            Ok(records) => Ok(records),
+            Err(e) => Err(DbError::new(&e)),
+        }
+    }
+
     /// convert Type to ToSql (postgresql native types)
     /// This is used when inserting records to the database
     /// TODO: put this somewhere organized
@@ -405,6 +486,32 @@ impl Postgres {
 
     }
 
+    /// the database data type of the column,
+    /// derived from the rust type when it is not specified
+    fn column_type(&self, column: &Column) -> String {
+        if column.db_data_type.is_empty() {
+            self.rust_type_to_dbtype(&column.data_type)
+        } else {
+            column.db_data_type.to_owned()
+        }
+    }
+
+    /// the column as it is declared in CREATE TABLE and ADD COLUMN
+    fn column_definition(&self, column: &Column) -> String {
+        let mut definition = format!("{} {}", column.name, self.column_type(column));
+        if column.not_null {
+            definition.push_str(" NOT NULL");
+        }
+        if let Some(ref default) = column.default {
+            definition.push_str(" DEFAULT ");
+            definition.push_str(default);
+        }
+        if column.is_unique && !column.is_primary {
+            definition.push_str(" UNIQUE");
+        }
+        definition
+    }
+
 }
 
 
@@ -418,14 +525,14 @@ impl Database for Postgres {
         }
     }
 
-    fn begin(&self) {
+    fn transaction_depth(&self) -> &Cell<usize> {
+        &self.depth
     }
-    fn commit(&self) {
-    }
-    fn rollback(&self) {
-    }
-    fn is_transacted(&self) -> bool {
-        false
+
+    fn execute_batch(&self, sql: &str) -> Result<(), DbError> {
+        println!("SQL: \n{}", sql);
+        try!(self.get_connection().batch_execute(sql));
+        Ok(())
     }
     fn is_closed(&self) -> bool {
         false
@@ -449,21 +556,42 @@ impl Database for Postgres {
     /// JSON >= 9.2
     /// JSONB >= 9.4
     /// Returning >= 8.2
+    /// ON CONFLICT >= 9.5
     fn sql_options(&self) -> Vec<SqlOption> {
         vec![
             SqlOption::UsesNumberedParam,  // uses numbered parameters
             SqlOption::SupportsReturningClause, // supports returning clause, feature
             SqlOption::SupportsCTE,
+            SqlOption::SupportsIntersect,
+            SqlOption::SupportsNestedCombine,
+            SqlOption::SupportsWindowFunction,
+            SqlOption::SupportsILike,
             SqlOption::SupportsInheritance,
             SqlOption::UsesSchema,
             SqlOption::ReturnMetaColumns,// whether to use the column names returned in a statement
+            SqlOption::SupportsOnConflict,
+            SqlOption::SupportsUpdateFrom,
+            SqlOption::SupportsRowLocking,
+            SqlOption::SupportsSetDefault,
         ]
     }
 
+    /// the number of parameters is sent as a 16 bit integer
+    fn max_params(&self) -> usize {
+        32767
+    }
 
-    fn update(&self, _query: &Query) -> Dao {
-        unimplemented!()
+    /// streamed with COPY instead of inserts
+    fn bulk_insert(&self,
+                   table: &str,
+                   columns: &[&str],
+                   rows: &mut Iterator<Item = Dao>)
+                   -> Result<usize, DbError> {
+        let count = try!(self.copy_in(table, columns, rows, CopyFormat::TEXT));
+        Ok(count as usize)
     }
+
+
     fn delete(&self, _query: &Query) -> Result<usize, String> {
         unimplemented!()
     }
@@ -508,31 +636,234 @@ impl Database for Postgres {
 
 impl DatabaseDDL for Postgres {
 
-    fn create_schema(&self, _schema: &str) {
-        unimplemented!()
+    fn create_schema(&self, schema: &str) -> Result<(), DbError> {
+        let sql = format!("CREATE SCHEMA IF NOT EXISTS {}", schema);
+        try!(self.execute_sql(&sql, &vec![]));
+        Ok(())
     }
-    fn drop_schema(&self, _schema: &str) {
-        unimplemented!()
+
+    fn drop_schema(&self, schema: &str) -> Result<(), DbError> {
+        let sql = format!("DROP SCHEMA IF EXISTS {}", schema);
+        try!(self.execute_sql(&sql, &vec![]));
+        Ok(())
     }
-    fn create_table(&self, _model: &Table) {
-        unimplemented!()
+
+    /// the create table statement is followed by the COMMENT ON statements,
+    /// so it is executed as a batch
+    fn create_table(&self, table: &Table) -> Result<(), DbError> {
+        let frag = self.build_create_table(table);
+        println!("SQL: \n{}", frag.sql);
+        try!(self.get_connection().batch_execute(&frag.sql));
+        Ok(())
     }
-    fn build_create_table(&self, _table: &Table) -> SqlFrag {
-        unimplemented!()
+
+    /// build the CREATE TABLE statement of the table,
+    /// inherited columns are left to the parent table mentioned in INHERITS
+    fn build_create_table(&self, table: &Table) -> SqlFrag {
+        let table_name = qualified_name(&table.schema, &table.name);
+        let mut w = SqlFrag::new(self.sql_options());
+        w.append("CREATE TABLE ");
+        w.append(&table_name);
+        w.append(" (");
+        let mut do_comma = false;
+        for c in &table.columns {
+            if c.is_inherited {
+                continue;
+            }
+            if do_comma {
+                w.comma();
+            } else {
+                do_comma = true;
+            }
+            w.ln_tab();
+            w.append(&self.column_definition(c));
+        }
+        let primary_columns: Vec<&str> = table.columns
+                                              .iter()
+                                              .filter(|c| c.is_primary && !c.is_inherited)
+                                              .map(|c| &c.name as &str)
+                                              .collect();
+        if !primary_columns.is_empty() {
+            w.comma();
+            w.ln_tab();
+            w.append(&format!("PRIMARY KEY ({})", primary_columns.join(", ")));
+        }
+        for c in &table.columns {
+            if c.is_inherited {
+                continue;
+            }
+            if let Some(ref foreign) = c.foreign {
+                w.comma();
+                w.ln_tab();
+                w.append(&foreign_key_constraint(table, c, foreign));
+            }
+        }
+        w.ln();
+        w.append(")");
+        if let Some(ref parent_table) = table.parent_table {
+            w.append(" INHERITS (");
+            w.append(&qualified_name(&table.schema, parent_table));
+            w.append(")");
+        }
+        w.append(";");
+        if let Some(ref comment) = table.comment {
+            w.ln();
+            w.append(&format!("COMMENT ON TABLE {} IS {};", table_name, quote_literal(comment)));
+        }
+        for c in &table.columns {
+            if c.is_inherited {
+                continue;
+            }
+            if let Some(ref comment) = c.comment {
+                w.ln();
+                w.append(&format!("COMMENT ON COLUMN {}.{} IS {};",
+                                  table_name,
+                                  c.name,
+                                  quote_literal(comment)));
+            }
+        }
+        w
     }
-    fn rename_table(&self, _table: &Table, _new_tablename: String) {
-        unimplemented!()
+
+    fn rename_table(&self, table: &Table, new_tablename: String) -> Result<(), DbError> {
+        let sql = format!("ALTER TABLE {} RENAME TO {}",
+                          qualified_name(&table.schema, &table.name),
+                          new_tablename);
+        try!(self.execute_sql(&sql, &vec![]));
+        Ok(())
     }
-    fn drop_table(&self, _table: &Table) {
-        unimplemented!()
+
+    fn drop_table(&self, table: &Table) -> Result<(), DbError> {
+        let sql = format!("DROP TABLE {}", qualified_name(&table.schema, &table.name));
+        try!(self.execute_sql(&sql, &vec![]));
+        Ok(())
     }
-    fn set_foreign_constraint(&self, _model: &Table) {
-        unimplemented!()
+
+    /// add the foreign key constraints of the table,
+    /// useful when the referred tables are created after this table
+    fn set_foreign_constraint(&self, table: &Table) -> Result<(), DbError> {
+        for c in &table.columns {
+            if let Some(ref foreign) = c.foreign {
+                let sql = format!("ALTER TABLE {} ADD {}",
+                                  qualified_name(&table.schema, &table.name),
+                                  foreign_key_constraint(table, c, foreign));
+                try!(self.execute_sql(&sql, &vec![]));
+            }
+        }
+        Ok(())
     }
-    fn set_primary_constraint(&self, _model: &Table) {
-        unimplemented!()
+
+    fn set_primary_constraint(&self, table: &Table) -> Result<(), DbError> {
+        let primary_columns: Vec<&str> = table.columns
+                                              .iter()
+                                              .filter(|c| c.is_primary)
+                                              .map(|c| &c.name as &str)
+                                              .collect();
+        if primary_columns.is_empty() {
+            return Err(DbError::new(&format!("Table {} has no primary columns", table.name)));
+        }
+        let sql = format!("ALTER TABLE {} ADD PRIMARY KEY ({})",
+                          qualified_name(&table.schema, &table.name),
+                          primary_columns.join(", "));
+        try!(self.execute_sql(&sql, &vec![]));
+        Ok(())
+    }
+
+    fn build_change(&self, change: &Change) -> Result<SqlFrag, DbError> {
+        let sql = match *change {
+            Change::AddTable(ref table) => return Ok(self.build_create_table(table)),
+            Change::DropTable(ref table) => {
+                format!("DROP TABLE {};", qualified_name(&table.schema, &table.name))
+            }
+            Change::AddColumn(ref table, ref column) => {
+                format!("ALTER TABLE {} ADD COLUMN {};",
+                        qualified_name(&table.schema, &table.name),
+                        self.column_definition(column))
+            }
+            Change::DropColumn(ref table, ref column) => {
+                format!("ALTER TABLE {} DROP COLUMN {};",
+                        qualified_name(&table.schema, &table.name),
+                        column.name)
+            }
+            Change::AlterColumnType(ref table, ref column) => {
+                format!("ALTER TABLE {} ALTER COLUMN {} TYPE {};",
+                        qualified_name(&table.schema, &table.name),
+                        column.name,
+                        self.column_type(column))
+            }
+            Change::AlterColumnDefault(ref table, ref column) => {
+                let action = match column.default {
+                    Some(ref default) => format!("SET DEFAULT {}", default),
+                    None => "DROP DEFAULT".to_owned(),
+                };
+                format!("ALTER TABLE {} ALTER COLUMN {} {};",
+                        qualified_name(&table.schema, &table.name),
+                        column.name,
+                        action)
+            }
+            Change::AlterColumnNullable(ref table, ref column) => {
+                let action = if column.not_null {
+                    "SET NOT NULL"
+                } else {
+                    "DROP NOT NULL"
+                };
+                format!("ALTER TABLE {} ALTER COLUMN {} {};",
+                        qualified_name(&table.schema, &table.name),
+                        column.name,
+                        action)
+            }
+            Change::AddForeignKey(ref table, ref column) => {
+                match column.foreign {
+                    Some(ref foreign) => {
+                        format!("ALTER TABLE {} ADD {};",
+                                qualified_name(&table.schema, &table.name),
+                                foreign_key_constraint(table, column, foreign))
+                    }
+                    None => return Err(DbError::new(&format!("Column {} has no foreign key", column.name))),
+                }
+            }
+            Change::DropForeignKey(ref table, ref column) => {
+                format!("ALTER TABLE {} DROP CONSTRAINT {}_{}_fkey;",
+                        qualified_name(&table.schema, &table.name),
+                        table.name,
+                        column.name)
+            }
+        };
+        let mut w = SqlFrag::new(self.sql_options());
+        w.append(&sql);
+        Ok(w)
+    }
+
+}
+
+/// the name of the table prefixed with the schema, when there is one
+fn qualified_name(schema: &str, name: &str) -> String {
+    if schema.is_empty() || name.contains('.') {
+        name.to_owned()
+    } else {
+        format!("{}.{}", schema, name)
     }
+}
+
+/// quote the text as an sql string literal
+fn quote_literal(text: &str) -> String {
+    format!("'{}'", text.replace("'", "''"))
+}
 
+/// the named foreign key constraint of the column,
+/// the referred table is assumed to be in the same schema when not specified
+fn foreign_key_constraint(table: &Table, column: &Column, foreign: &Foreign) -> String {
+    let schema = if foreign.schema.is_empty() {
+        &table.schema
+    } else {
+        &foreign.schema
+    };
+    format!("CONSTRAINT {}_{}_fkey FOREIGN KEY ({}) REFERENCES {} ({})",
+            table.name,
+            column.name,
+            column.name,
+            qualified_name(schema, &foreign.table),
+            foreign.column)
 }
 
 /// this can be condensed with using just extracting the table definition
@@ -592,7 +923,7 @@ impl DatabaseDev for Postgres {
 
 
 
-    fn get_table_metadata(&self, schema: &str, table: &str, is_view: bool) -> Table {
+    fn get_table_metadata(&self, schema: &str, table: &str, is_view: bool) -> Result<Table, DbError> {
 
         let mut columns = self.get_table_columns(schema, table);
         let comment = self.get_table_comment(schema, table);
@@ -611,7 +942,7 @@ impl DatabaseDev for Postgres {
             }
         }
 
-        Table {
+        Ok(Table {
             schema: schema.to_owned(),
             name: table.to_owned(),
             parent_table: parent,
@@ -619,10 +950,10 @@ impl DatabaseDev for Postgres {
             comment: comment,
             columns: columns,
             is_view: is_view,
-        }
+        })
     }
 
-    fn get_all_tables(&self) -> Vec<(String, String, bool)> {
+    fn get_all_tables(&self) -> Result<Vec<(String, String, bool)>, DbError> {
         let sql = "
                 SELECT
                     pg_class.relname AS table,
@@ -642,15 +973,15 @@ impl DatabaseDev for Postgres {
 
                 ";
         let conn = self.get_connection();
-        let stmt = conn.prepare(&sql).unwrap();
+        let stmt = try!(conn.prepare(&sql));
         let mut tables: Vec<(String, String, bool)> = Vec::new();
-        for row in stmt.query(&[]).unwrap() {
+        for row in try!(stmt.query(&[])) {
             let table: String = row.get("table");
             let schema: String = row.get("schema");
             let is_view: bool = row.get("is_view");
             tables.push((schema, table, is_view));
         }
-        tables
+        Ok(tables)
     }
 
 
@@ -842,3 +1173,118 @@ impl DatabaseDev for Postgres {
     }
 
 }
+
+/// classify the postgresql error into the platform agnostic DbError,
+/// based on the SQLSTATE code of the error
+pub fn to_db_error(err: PgError) -> DbError {
+    let info = match err {
+        PgError::Db(ref e) => {
+            Some((e.code().clone(),
+                  e.constraint().map(|c| c.to_owned()),
+                  e.table().map(|t| t.to_owned()),
+                  e.column().map(|c| c.to_owned())))
+        }
+        _ => None,
+    };
+    let is_io = match err {
+        PgError::Io(_) => true,
+        _ => false,
+    };
+    classify_error(info, is_io, PlatformError::from(err))
+}
+
+/// the DbError of the SQLSTATE code, together with the constraint, table and column of the error
+fn classify_error(info: Option<(SqlState, Option<String>, Option<String>, Option<String>)>,
+                  is_io: bool,
+                  cause: PlatformError)
+                  -> DbError {
+    match info {
+        Some((SqlState::UniqueViolation, constraint, table, _)) => {
+            DbError::UniqueViolation {
+                constraint: constraint,
+                table: table,
+                cause: cause,
+            }
+        }
+        Some((SqlState::ForeignKeyViolation, constraint, table, _)) => {
+            DbError::ForeignKeyViolation {
+                constraint: constraint,
+                table: table,
+                cause: cause,
+            }
+        }
+        Some((SqlState::NotNullViolation, _, table, column)) => {
+            DbError::NotNullViolation {
+                column: column,
+                table: table,
+                cause: cause,
+            }
+        }
+        Some((SqlState::TRSerializationFailure, _, _, _)) |
+        Some((SqlState::TRDeadlockDetected, _, _, _)) => DbError::SerializationFailure(cause),
+        Some((SqlState::QueryCanceled, _, _, _)) |
+        Some((SqlState::LockNotAvailable, _, _, _)) => DbError::Timeout(cause),
+        Some((SqlState::AdminShutdown, _, _, _)) |
+        Some((SqlState::CrashShutdown, _, _, _)) |
+        Some((SqlState::CannotConnectNow, _, _, _)) => DbError::ConnectionLost(cause),
+        Some(_) => DbError::PlatformError(cause),
+        None => {
+            if is_io {
+                DbError::ConnectionLost(cause)
+            } else {
+                DbError::PlatformError(cause)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+fn io_cause() -> PlatformError {
+    PlatformError::from(PgError::Io(io::Error::new(io::ErrorKind::Other, "test")))
+}
+
+#[test]
+fn test_classify_error() {
+    let info = Some((SqlState::UniqueViolation,
+                     Some("product_pkey".to_owned()),
+                     Some("product".to_owned()),
+                     None));
+    match classify_error(info, false, io_cause()) {
+        DbError::UniqueViolation { constraint, table, .. } => {
+            assert_eq!(constraint, Some("product_pkey".to_owned()));
+            assert_eq!(table, Some("product".to_owned()));
+        }
+        other => panic!("expecting a unique violation, got {:?}", other),
+    }
+    let info = Some((SqlState::ForeignKeyViolation,
+                     Some("product_owner_fkey".to_owned()),
+                     Some("product".to_owned()),
+                     None));
+    match classify_error(info, false, io_cause()) {
+        DbError::ForeignKeyViolation { constraint, .. } => {
+            assert_eq!(constraint, Some("product_owner_fkey".to_owned()))
+        }
+        other => panic!("expecting a foreign key violation, got {:?}", other),
+    }
+    let info = Some((SqlState::NotNullViolation, None, Some("product".to_owned()), Some("name".to_owned())));
+    match classify_error(info, false, io_cause()) {
+        DbError::NotNullViolation { column, .. } => assert_eq!(column, Some("name".to_owned())),
+        other => panic!("expecting a not null violation, got {:?}", other),
+    }
+    match classify_error(Some((SqlState::TRDeadlockDetected, None, None, None)), false, io_cause()) {
+        DbError::SerializationFailure(_) => (),
+        other => panic!("expecting a serialization failure, got {:?}", other),
+    }
+    match classify_error(Some((SqlState::LockNotAvailable, None, None, None)), false, io_cause()) {
+        DbError::Timeout(_) => (),
+        other => panic!("expecting a timeout, got {:?}", other),
+    }
+    match classify_error(Some((SqlState::SyntaxError, None, None, None)), false, io_cause()) {
+        DbError::PlatformError(_) => (),
+        other => panic!("expecting a platform error, got {:?}", other),
+    }
+    match classify_error(None, true, io_cause()) {
+        DbError::ConnectionLost(_) => (),
+        other => panic!("expecting a lost connection, got {:?}", other),
+    }
+}
@@ -1,5 +1,6 @@
 use query::Query;
-use dao::Dao;
+use query::{Filter, Equality, Operand, ColumnName, TableName};
+use dao::{Dao, DaoResult, Type};
 
 use dao::Value;
 use database::{Database, DatabaseDev};
@@ -15,28 +16,73 @@ use r2d2::PooledConnection;
 use r2d2_sqlite::SqliteConnectionManager;
 use regex::Regex;
 use std::collections::BTreeMap;
+use std::cell::{Cell, RefCell};
+use rusqlite::SqliteError;
+use platform::PlatformError;
+use diff::Change;
+use rustc_serialize::json::{Json, ToJson};
+use uuid::Uuid;
+use chrono::datetime::DateTime;
+use chrono::naive::date::NaiveDate;
+use chrono::naive::time::NaiveTime;
+use chrono::naive::datetime::NaiveDateTime;
+use chrono::offset::utc::UTC;
 
 pub struct Sqlite {
     pool: Option<PooledConnection<SqliteConnectionManager>>,
+    /// the number of nested transactions currently open
+    depth: Cell<usize>,
+    /// the declared types of the columns of each table already looked up,
+    /// forgotten when the schema is changed on this connection
+    declared_types: RefCell<BTreeMap<String, BTreeMap<String, Type>>>,
 }
 
 impl Sqlite {
     pub fn new() -> Self {
-        Sqlite { pool: None }
+        Sqlite {
+            pool: None,
+            depth: Cell::new(0),
+            declared_types: RefCell::new(BTreeMap::new()),
+        }
     }
 
     pub fn with_pooled_connection(pool: PooledConnection<SqliteConnectionManager>) -> Self {
-        Sqlite { pool: Some(pool) }
+        Sqlite {
+            pool: Some(pool),
+            depth: Cell::new(0),
+            declared_types: RefCell::new(BTreeMap::new()),
+        }
     }
 
-    fn from_rust_type_tosql<'a>(&self, types: &'a [Value]) -> Vec<&'a ToSql> {
-        let mut params: Vec<&ToSql> = vec![];
+    /// convert the values into sqlite storage classes,
+    /// integers are bound as INTEGER, floats as REAL, Vec<u8> as BLOB and the rest as TEXT
+    fn from_rust_type_tosql(&self, types: &[Value]) -> Vec<Box<ToSql>> {
+        let mut params: Vec<Box<ToSql>> = vec![];
         for t in types {
-            match t {
-                &Value::String(ref x) => {
-                    params.push(x);
+            match *t {
+                Value::Bool(x) => params.push(Box::new(if x { 1i64 } else { 0i64 })),
+                Value::I8(x) => params.push(Box::new(x as i64)),
+                Value::I16(x) => params.push(Box::new(x as i64)),
+                Value::I32(x) => params.push(Box::new(x as i64)),
+                Value::I64(x) => params.push(Box::new(x)),
+                Value::U8(x) => params.push(Box::new(x as i64)),
+                Value::U16(x) => params.push(Box::new(x as i64)),
+                Value::U32(x) => params.push(Box::new(x as i64)),
+                Value::U64(x) => params.push(Box::new(x as i64)),
+                Value::F32(x) => params.push(Box::new(x as f64)),
+                Value::F64(x) => params.push(Box::new(x)),
+                Value::String(ref x) => params.push(Box::new(x.to_owned())),
+                Value::VecU8(ref x) => params.push(Box::new(x.clone())),
+                Value::Object(_) => params.push(Box::new(t.to_json().to_string())),
+                Value::Json(ref x) => params.push(Box::new(x.to_string())),
+                Value::Uuid(ref x) => params.push(Box::new(x.to_hyphenated_string())),
+                Value::DateTime(ref x) => params.push(Box::new(datetime_text(x))),
+                Value::NaiveDate(ref x) => params.push(Box::new(x.format(DATE_FORMAT).to_string())),
+                Value::NaiveTime(ref x) => params.push(Box::new(x.format(TIME_FORMAT).to_string())),
+                Value::NaiveDateTime(ref x) => {
+                    params.push(Box::new(x.format(DATETIME_FORMAT).to_string()))
                 }
-                _ => panic!("not yet here {:?}", t),
+                Value::None => params.push(Box::new(None::<String>)),
             }
         }
         params
@@ -49,78 +95,127 @@ impl Sqlite {
         }
     }
 
-    /// convert a record of a row into rust type
+    /// convert a record of a row into rust type,
+    /// based on the storage class of the value, since sqlite does not enforce the declared type
     fn from_sql_to_rust_type(&self, row: &SqliteRow, index: usize) -> Value {
-        let value = row.get_checked(index as i32);
-        match value {
-            Ok(value) => Value::String(value),
-            Err(_) => Value::Null,
+        let index = index as i32;
+        match row.get_checked::<Option<i64>>(index) {
+            Ok(Some(value)) => return Value::I64(value),
+            Ok(None) => return Value::None,
+            Err(_) => (),
+        }
+        if let Ok(Some(value)) = row.get_checked::<Option<f64>>(index) {
+            return Value::F64(value);
+        }
+        if let Ok(Some(value)) = row.get_checked::<Option<String>>(index) {
+            return Value::String(value);
         }
+        if let Ok(Some(value)) = row.get_checked::<Option<Vec<u8>>>(index) {
+            return Value::VecU8(value);
+        }
+        Value::None
     }
 
-    ///
-    /// convert rust data type names to database data type names
-    /// will be used in generating SQL for table creation
-    /// FIXME, need to restore the exact data type as before
-    fn rust_type_to_dbtype(&self, rust_type: &str) -> String {
-
-        let rust_type = match rust_type {
-            "bool" => {
-                "boolean".to_owned()
-            }
-            "i8" => {
-                "integer".to_owned()
-            }
-            "i16" => {
-                "integer".to_owned()
-            }
-            "i32" => {
-                "integer".to_owned()
-            }
-            "u32" => {
-                "integer".to_owned()
-            }
-            "i64" => {
-                "integer".to_owned()
-            }
-            "f32" => {
-                "real".to_owned()
-            }
-            "f64" => {
-                "real".to_owned()
-            }
-            "String" => {
-                "text".to_owned()
-            }
-            "Vec<u8>" => {
-                "blob".to_owned()
-            }
-            "Json" => {
-                "text".to_owned()
-            }
-            "Uuid" => {
-                "text".to_owned()
-            }
-            "NaiveDateTime" => {
-                "numeric".to_owned()
-            }
-            "DateTime<UTC>" => {
-                "numeric".to_owned()
-            }
-            "NaiveDate" => {
-                "numeric".to_owned()
+    /// the declared types of the columns of the table, looked up once per table
+    fn table_types(&self, table: &str) -> BTreeMap<String, Type> {
+        if let Some(types) = self.declared_types.borrow().get(table) {
+            return types.clone();
+        }
+        match self.lookup_declared_types(table) {
+            // a view, a subquery alias or a table that doesn't exist yet is not remembered,
+            // its values are left in their storage class
+            Ok(ref types) if types.is_empty() => BTreeMap::new(),
+            Ok(types) => {
+                self.declared_types.borrow_mut().insert(table.to_owned(), types.clone());
+                types
             }
-            "NaiveTime" => {
-                "numeric".to_owned()
+            Err(_) => BTreeMap::new(),
+        }
+    }
+
+    fn lookup_declared_types(&self, table: &str) -> Result<BTreeMap<String, Type>, DbError> {
+        let conn = match self.pool.as_ref() {
+            Some(conn) => conn,
+            None => return Err(DbError::new("No connection for this database")),
+        };
+        let sql = format!("PRAGMA table_info(\"{}\")", table.replace("\"", "\"\""));
+        let mut stmt = try!(conn.prepare(&sql));
+        let rows = try!(stmt.query(&[]));
+        let mut types = BTreeMap::new();
+        for row in rows {
+            let row = try!(row);
+            let name: String = try!(row.get_checked(1));
+            let db_data_type: String = try!(row.get_checked(2));
+            if let Some(ty) = declared_type(&db_data_type) {
+                types.insert(name, ty);
             }
-            "HashMap<String, Option<String>>" => {
-                "text".to_owned()
+        }
+        Ok(types)
+    }
+
+    /// forget the declared types when the statement changes the schema
+    fn forget_declared_types(&self, sql: &str) {
+        let sql = sql.trim_left().to_uppercase();
+        if sql.starts_with("CREATE") || sql.starts_with("ALTER") || sql.starts_with("DROP") {
+            self.declared_types.borrow_mut().clear();
+        }
+    }
+
+    /// the declared types of the result columns of the query,
+    /// each column is looked up on the table it is selected from,
+    /// the columns of the joined tables are referred to with the table name or the alias of the join
+    fn get_declared_types(&self, query: &Query) -> BTreeMap<String, Type> {
+        let mut tables = vec![];
+        if let Some(from) = query.get_from_table() {
+            tables.push((from.name.to_owned(), from.name.to_owned()));
+        }
+        for join in &query.joins {
+            let reference = match join.alias {
+                Some(ref alias) => alias.to_owned(),
+                None => join.table_name.name.to_owned(),
+            };
+            tables.push((reference, join.table_name.name.to_owned()));
+        }
+        let mut declared = BTreeMap::new();
+        for field in &query.enumerated_fields {
+            let column = match field.operand {
+                Operand::ColumnName(ref column) => column,
+                _ => continue,
+            };
+            let candidates: Vec<&(String, String)> = match column.table {
+                Some(ref table) => tables.iter().filter(|&&(ref reference, _)| reference == table).collect(),
+                None => tables.iter().collect(),
+            };
+            for &&(_, ref table) in &candidates {
+                let types = self.table_types(table);
+                if column.column == "*" {
+                    for (name, ty) in types {
+                        declared.entry(name).or_insert(ty);
+                    }
+                } else if let Some(ty) = types.get(&column.column) {
+                    let name = match field.name {
+                        Some(ref rename) => rename.to_owned(),
+                        None => column.column.to_owned(),
+                    };
+                    declared.insert(name, ty.clone());
+                    break;
+                }
             }
-            _ => panic!("Unable to get the equivalent database data type for {}",
-                        rust_type),
-        };
-        rust_type
+        }
+        declared
+    }
 
+    /// cast the values of the dao into the declared types of its columns
+    fn cast_to_declared(&self, dao: Dao, declared: &BTreeMap<String, Type>) -> Dao {
+        let mut casted = Dao::new();
+        for (column, value) in dao.as_map() {
+            let value = match declared.get(column) {
+                Some(ty) => cast(value.clone(), ty),
+                None => value.clone(),
+            };
+            casted.set_value(column, value);
+        }
+        casted
     }
 
     /// get the foreign keys of table
@@ -200,10 +295,9 @@ impl Sqlite {
             let mut column_comments = BTreeMap::new();
             let mut index = 0;
             for column in columns {
-                let comment = if comments.len() > 0 {
-                    comments[index + 1].clone()
-                } else {
-                    None
+                let comment = match comments.get(index + 1) {
+                    Some(comment) => comment.clone(),
+                    None => None,
                 };
                 column_comments.insert(column, comment);
                 index += 1;
@@ -219,7 +313,9 @@ impl Sqlite {
         let sql = format!("SELECT sql FROM sqlite_master WHERE type = 'table' AND tbl_name = '{}'",
                           table);
         let result = self.execute_sql_with_return(&sql, &vec![]).unwrap();
-        assert_eq!(result.len(), 1);
+        if result.len() != 1 {
+            return None;
+        }
         let ref dao = result[0];
         let create_sql: String = dao.get("sql");
         match Sqlite::extract_comments(&create_sql) {
@@ -238,7 +334,9 @@ impl Sqlite {
         let sql = format!("SELECT sql FROM sqlite_master WHERE type = 'table' AND tbl_name = '{}'",
                           table);
         let result = self.execute_sql_with_return(&sql, &vec![]).unwrap();
-        assert_eq!(result.len(), 1);
+        if result.len() != 1 {
+            return BTreeMap::new();
+        }
         let ref dao = result[0];
         let create_sql: String = dao.get("sql");
         match Sqlite::extract_comments(&create_sql) {
@@ -282,17 +380,15 @@ impl Database for Sqlite {
             None => Err(DbError::new("Unable to get database version")),
         }
     }
-    fn begin(&self) {
-        unimplemented!()
-    }
-    fn commit(&self) {
-        unimplemented!()
+    fn transaction_depth(&self) -> &Cell<usize> {
+        &self.depth
     }
-    fn rollback(&self) {
-        unimplemented!()
-    }
-    fn is_transacted(&self) -> bool {
-        false
+
+    fn execute_batch(&self, sql: &str) -> Result<(), DbError> {
+        println!("SQL: \n{}", sql);
+        self.declared_types.borrow_mut().clear();
+        try!(self.get_connection().execute_batch(sql));
+        Ok(())
     }
     fn is_closed(&self) -> bool {
         false
@@ -315,35 +411,109 @@ impl Database for Sqlite {
         vec![
             SqlOption::UsesNumberedParam,  // uses numbered parameters
             SqlOption::SupportsCTE,
+            SqlOption::SupportsIntersect,
+            SqlOption::SupportsWindowFunction,
+            SqlOption::SupportsOnConflict,
         ]
     }
 
-    fn insert(&self, query: &Query) -> Result<Dao, DbError> {
-        let sql_frag = self.build_insert(query);
-        match self.execute_sql_with_one_return(&sql_frag.sql, &sql_frag.params) {
-            Ok(Some(result)) => Ok(result),
-            Ok(None) => Err(DbError::new("No result from insert")),
-            Err(e) => Err(e),
-        }
+    /// every table has a rowid, the last one inserted on this connection is last_insert_rowid()
+    fn inserted_filters(&self, _query: &Query) -> Result<Vec<Filter>, DbError> {
+        let filter = Filter::bare_new(Operand::ColumnName(ColumnName::from_str("rowid")),
+                                      Equality::EQ,
+                                      Operand::Sql("last_insert_rowid()".to_owned(), vec![]));
+        Ok(vec![filter])
     }
-    fn update(&self, _query: &Query) -> Dao {
-        unimplemented!()
+
+    /// new rowids are one more than the largest one, so the rows of an insert get consecutive rowids
+    fn generates_consecutive_keys(&self, _query: &Query) -> Result<bool, DbError> {
+        Ok(true)
+    }
+
+    /// the rows of the last insert on this connection are the ones up to last_insert_rowid()
+    fn inserted_range_filters(&self, _query: &Query, rows: usize) -> Result<(String, Vec<Filter>), DbError> {
+        let filter = Filter::bare_new(Operand::ColumnName(ColumnName::from_str("rowid")),
+                                      Equality::GT,
+                                      Operand::Sql("last_insert_rowid() - ?".to_owned(),
+                                                   vec![Value::I64(rows as i64)]));
+        Ok(("rowid".to_owned(), vec![filter]))
     }
+
+    /// the columns of the primary key in their order in the key,
+    /// the rowid when the table has no declared primary key
+    fn primary_columns(&self, table: &TableName) -> Result<Vec<String>, DbError> {
+        let conn = match self.pool.as_ref() {
+            Some(conn) => conn,
+            None => return Err(DbError::new("No connection for this database")),
+        };
+        let sql = format!("PRAGMA table_info(\"{}\")", table.name.replace("\"", "\"\""));
+        let mut stmt = try!(conn.prepare(&sql));
+        let rows = try!(stmt.query(&[]));
+        let mut keys = vec![];
+        for row in rows {
+            let row = try!(row);
+            let name: String = try!(row.get_checked(1));
+            let pk: i64 = try!(row.get_checked(5));
+            if pk > 0 {
+                keys.push((pk, name));
+            }
+        }
+        keys.sort();
+        if keys.is_empty() {
+            return Ok(vec!["rowid".to_owned()]);
+        }
+        Ok(keys.into_iter().map(|(_, name)| name).collect())
+    }
+
     fn delete(&self, _query: &Query) -> Result<usize, String> {
         unimplemented!()
     }
 
+    /// the values are casted into the declared types of the columns,
+    /// so they can be extracted the same way as the other platforms
+    fn execute_with_return(&self, query: &Query) -> Result<DaoResult, DbError> {
+        let sql_frag = &try!(self.build_query(query));
+        let result = try!(self.execute_sql_with_return(&sql_frag.sql, &sql_frag.params));
+        let declared = self.get_declared_types(query);
+        let dao_result = DaoResult {
+            dao: result.into_iter().map(|dao| self.cast_to_declared(dao, &declared)).collect(),
+            renamed_columns: query.get_renamed_columns(),
+            total: None,
+            page: None,
+            page_size: None,
+            next_cursor: None,
+            prev_cursor: None,
+        };
+        Ok(dao_result)
+    }
+
+    fn execute_with_one_return(&self, query: &Query) -> Result<Option<Dao>, DbError> {
+        let sql_frag = &try!(self.build_query(query));
+        let dao = try!(self.execute_sql_with_one_return(&sql_frag.sql, &sql_frag.params));
+        match dao {
+            Some(dao) => {
+                let declared = self.get_declared_types(query);
+                Ok(Some(self.cast_to_declared(dao, &declared)))
+            }
+            None => Ok(None),
+        }
+    }
+
     /// sqlite does not return the columns mentioned in the query,
-    /// you have to specify it yourself
+    /// you have to specify it yourself.
+    /// The values are returned in their storage class, ie: a datetime column is a Value::String,
+    /// since the declared types are only known from the table of a Query,
+    /// use `execute_with_return` to get the values casted into the declared types of the columns
     /// TODO: found this
     /// http://jgallagher.github.io/rusqlite/rusqlite/struct.SqliteStatement.html#method.column_names
     fn execute_sql_with_return(&self, sql: &str, params: &[Value]) -> Result<Vec<Dao>, DbError> {
         println!("SQL: \n{}", sql);
         println!("param: {:?}", params);
         let conn = self.get_connection();
-        let mut stmt = conn.prepare(sql).unwrap();
+        let mut stmt = try!(conn.prepare(sql));
         let mut daos = vec![];
-        let param = self.from_rust_type_tosql(params);
+        let to_sql_types = self.from_rust_type_tosql(params);
+        let param: Vec<&ToSql> = to_sql_types.iter().map(|p| &**p).collect();
         let mut columns = vec![];
         for c in stmt.column_names() {
             columns.push(c.to_owned());
@@ -356,7 +526,6 @@ impl Database for Sqlite {
             let mut dao = Dao::new();
             for col in &columns {
                 let rtype = self.from_sql_to_rust_type(&row, index);
-                println!("{:?}", rtype);
                 dao.set_value(col, rtype);
                 index += 1;
             }
@@ -385,27 +554,22 @@ impl Database for Sqlite {
         println!("SQL: \n{}", sql);
         println!("param: {:?}", params);
         let to_sql_types = self.from_rust_type_tosql(params);
+        let param: Vec<&ToSql> = to_sql_types.iter().map(|p| &**p).collect();
         let conn = self.get_connection();
-        let result = conn.execute(sql, &to_sql_types);
-        match result {
-            Ok(result) => {
-                Ok(result as usize)
-            }
-            Err(e) => {
-                Err(DbError::new(&format!("Something is wrong, {}", e)))
-            }
-        }
+        self.forget_declared_types(sql);
+        let result = try!(conn.execute(sql, &param));
+        Ok(result as usize)
     }
 
 }
 
 impl DatabaseDDL for Sqlite {
-    fn create_schema(&self, _schema: &str) {
-        panic!("sqlite does not support schema")
+    fn create_schema(&self, _schema: &str) -> Result<(), DbError> {
+        Err(DbError::new("sqlite does not support schema"))
     }
 
-    fn drop_schema(&self, _schema: &str) {
-        panic!("sqlite does not support schema")
+    fn drop_schema(&self, _schema: &str) -> Result<(), DbError> {
+        Err(DbError::new("sqlite does not support schema"))
     }
 
     fn build_create_table(&self, table: &Table) -> SqlFrag {
@@ -458,29 +622,59 @@ impl DatabaseDDL for Sqlite {
         w.append(")");
         w
     }
-    fn create_table(&self, table: &Table) {
+    fn create_table(&self, table: &Table) -> Result<(), DbError> {
         let frag = self.build_create_table(table);
-        match self.execute_sql(&frag.sql, &vec![]) {
-            Ok(_) => println!("created table.."),
-            Err(e) => panic!("table not created {}", e),
-        }
+        try!(self.execute_sql(&frag.sql, &vec![]));
+        println!("created table..");
+        Ok(())
     }
 
-    fn rename_table(&self, _table: &Table, _new_tablename: String) {
+    fn rename_table(&self, _table: &Table, _new_tablename: String) -> Result<(), DbError> {
         unimplemented!()
     }
 
-    fn drop_table(&self, _table: &Table) {
+    fn drop_table(&self, _table: &Table) -> Result<(), DbError> {
         unimplemented!()
     }
 
-    fn set_foreign_constraint(&self, _model: &Table) {
+    fn set_foreign_constraint(&self, _model: &Table) -> Result<(), DbError> {
         unimplemented!()
     }
 
-    fn set_primary_constraint(&self, _model: &Table) {
+    fn set_primary_constraint(&self, _model: &Table) -> Result<(), DbError> {
         unimplemented!()
     }
+
+    /// sqlite can only add columns to an existing table,
+    /// the other alterations need the table to be recreated
+    fn build_change(&self, change: &Change) -> Result<SqlFrag, DbError> {
+        let sql = match *change {
+            Change::AddTable(ref table) => return Ok(self.build_create_table(table)),
+            Change::DropTable(ref table) => format!("DROP TABLE {}", table.name),
+            Change::AddColumn(ref table, ref column) => {
+                let mut sql = format!("ALTER TABLE {} ADD COLUMN {} {}",
+                                      table.name,
+                                      column.name,
+                                      self.rust_type_to_dbtype(&column.data_type));
+                if column.not_null {
+                    sql.push_str(" NOT NULL");
+                }
+                if let Some(ref default) = column.default {
+                    sql.push_str(" DEFAULT ");
+                    sql.push_str(default);
+                }
+                sql
+            }
+            _ => {
+                return Err(DbError::new(&format!("sqlite does not support {:?}, \
+                                                  the table needs to be recreated",
+                                                 change)))
+            }
+        };
+        let mut w = SqlFrag::new(self.sql_options());
+        w.append(&sql);
+        Ok(w)
+    }
 }
 
 impl DatabaseDev for Sqlite {
@@ -492,7 +686,7 @@ impl DatabaseDev for Sqlite {
         unimplemented!()
     }
 
-    fn get_table_metadata(&self, schema: &str, table: &str, _is_view: bool) -> Table {
+    fn get_table_metadata(&self, schema: &str, table: &str, _is_view: bool) -> Result<Table, DbError> {
         println!("extracting table meta data in sqlite");
         let sql = format!("PRAGMA table_info({});", table);
         let result = self.execute_sql_with_return(&sql, &vec![]);
@@ -506,33 +700,34 @@ impl DatabaseDev for Sqlite {
                 let mut columns = vec![];
                 for r in result {
                     let column: String = r.get("name");
-                    let data_type: String = r.get("type");
-                    let default_value: String = r.get("dflt_value");
-                    let not_null: String = r.get("notnull");
-                    let pk: String = r.get("pk");
+                    let db_data_type: String = r.get("type");
+                    let default_value: Option<String> = r.get_opt("dflt_value");
+                    let not_null: i64 = r.get("notnull");
+                    let pk: i64 = r.get("pk");
                     println!("column: {}", column);
-                    println!("data_type: {}", data_type);
+                    println!("data_type: {}", db_data_type);
                     println!("not null: {}", not_null);
                     println!("pk: {}", pk);
-                    println!("default_value: {}", default_value);
+                    println!("default_value: {:?}", default_value);
+                    let (_, data_type) = self.dbtype_to_rust_type(&db_data_type);
 
                     let column_comment = self.get_column_comment(&column_comments, &column);
                     let column_foreign = self.get_column_foreign(&foreign, &column);
                     let column = Column {
                         name: column,
-                        data_type: data_type.to_owned(),
-                        db_data_type: data_type.to_owned(),
-                        is_primary: pk != "0",
+                        data_type: data_type,
+                        db_data_type: db_data_type,
+                        is_primary: pk != 0,
                         is_unique: false,
-                        default: Some(default_value),
+                        default: default_value,
                         comment: column_comment,
-                        not_null: not_null != "0",
+                        not_null: not_null != 0,
                         is_inherited: false,
                         foreign: column_foreign,
                     };
                     columns.push(column);
                 }
-                Table {
+                Ok(Table {
                     schema: "".to_owned(),
                     name: table.to_owned(),
                     parent_table: None,
@@ -540,15 +735,13 @@ impl DatabaseDev for Sqlite {
                     comment: table_comment,
                     columns: columns,
                     is_view: false,
-                }
-            }
-            Err(e) => {
-                panic!("No matching table found {}", e);
+                })
             }
+            Err(e) => Err(e),
         }
     }
 
-    fn get_all_tables(&self) -> Vec<(String, String, bool)> {
+    fn get_all_tables(&self) -> Result<Vec<(String, String, bool)>, DbError> {
         let sql = "SELECT type, name, tbl_name, sql FROM sqlite_master WHERE type = 'table'";
         let result = self.execute_sql_with_return(&sql, &vec![]);
         match result {
@@ -560,11 +753,9 @@ impl DatabaseDev for Sqlite {
                     let is_view = false;
                     tables.push((schema, table, is_view))
                 }
-                tables
-            }
-            Err(e) => {
-                panic!("Unable to get tables due to {}", e)
+                Ok(tables)
             }
+            Err(e) => Err(e),
         }
     }
 
@@ -572,12 +763,200 @@ impl DatabaseDev for Sqlite {
         vec![]
     }
 
-    fn dbtype_to_rust_type(&self, _db_type: &str) -> (Vec<String>, String) {
-        unimplemented!()
+    /// the declared types of sqlite are free form,
+    /// columns with types that can not be recognized are treated as String
+    fn dbtype_to_rust_type(&self, db_type: &str) -> (Vec<String>, String) {
+        match declared_type(db_type) {
+            Some(Type::Bool) => (vec![], "bool".to_owned()),
+            Some(Type::I8) => (vec![], "i8".to_owned()),
+            Some(Type::I16) => (vec![], "i16".to_owned()),
+            Some(Type::I32) => (vec![], "i32".to_owned()),
+            Some(Type::I64) => (vec![], "i64".to_owned()),
+            Some(Type::F64) => (vec![], "f64".to_owned()),
+            Some(Type::VecU8) => (vec![], "Vec<u8>".to_owned()),
+            Some(Type::Json) => {
+                (vec!["rustc_serialize::json::Json".to_owned()], "Json".to_owned())
+            }
+            Some(Type::Uuid) => (vec!["uuid::Uuid".to_owned()], "Uuid".to_owned()),
+            Some(Type::DateTime) => {
+                (vec!["chrono::datetime::DateTime".to_owned(),
+                      "chrono::offset::utc::UTC".to_owned()],
+                 "DateTime<UTC>".to_owned())
+            }
+            Some(Type::NaiveDateTime) => {
+                (vec!["chrono::naive::datetime::NaiveDateTime".to_owned()],
+                 "NaiveDateTime".to_owned())
+            }
+            Some(Type::NaiveDate) => {
+                (vec!["chrono::naive::date::NaiveDate".to_owned()],
+                 "NaiveDate".to_owned())
+            }
+            Some(Type::NaiveTime) => {
+                (vec!["chrono::naive::time::NaiveTime".to_owned()],
+                 "NaiveTime".to_owned())
+            }
+            _ => (vec![], "String".to_owned()),
+        }
     }
 
-    fn rust_type_to_dbtype(&self, _rust_type: &str) -> String {
-        unimplemented!()
+    ///
+    /// convert rust data type names to database data type names
+    /// will be used in generating SQL for table creation
+    /// the names are chosen so they are recognized again by `dbtype_to_rust_type`
+    fn rust_type_to_dbtype(&self, rust_type: &str) -> String {
+        match rust_type {
+            "bool" => {
+                "boolean".to_owned()
+            }
+            "i8" => {
+                "tinyint".to_owned()
+            }
+            "i16" => {
+                "smallint".to_owned()
+            }
+            "i32" | "u8" | "u16" => {
+                "integer".to_owned()
+            }
+            "i64" | "u32" | "u64" => {
+                "bigint".to_owned()
+            }
+            "f32" | "f64" => {
+                "real".to_owned()
+            }
+            "String" => {
+                "text".to_owned()
+            }
+            "Vec<u8>" => {
+                "blob".to_owned()
+            }
+            "Json" => {
+                "json".to_owned()
+            }
+            "Uuid" => {
+                "uuid".to_owned()
+            }
+            "NaiveDateTime" => {
+                "timestamp".to_owned()
+            }
+            "DateTime<UTC>" => {
+                "timestamp with time zone".to_owned()
+            }
+            "NaiveDate" => {
+                "date".to_owned()
+            }
+            "NaiveTime" => {
+                "time".to_owned()
+            }
+            "HashMap<String, Option<String>>" => {
+                "text".to_owned()
+            }
+            _ => panic!("Unable to get the equivalent database data type for {}",
+                        rust_type),
+        }
+    }
+}
+
+const DATE_FORMAT: &'static str = "%Y-%m-%d";
+const TIME_FORMAT: &'static str = "%H:%M:%S%.f";
+const DATETIME_FORMAT: &'static str = "%Y-%m-%d %H:%M:%S%.f";
+/// the datetime written with a `T` separator, ie: by other programs writing ISO 8601
+const ISO_DATETIME_FORMAT: &'static str = "%Y-%m-%dT%H:%M:%S%.f";
+
+/// the datetime in UTC, written the same as CURRENT_TIMESTAMP and the date functions of sqlite,
+/// so it compares and sorts with them as text
+fn datetime_text(datetime: &DateTime<UTC>) -> String {
+    datetime.naive_utc().format(DATETIME_FORMAT).to_string()
+}
+
+/// the datetime with either a space or a `T` separator
+fn parse_naive_datetime(text: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(text, DATETIME_FORMAT)
+        .or_else(|_| NaiveDateTime::parse_from_str(text, ISO_DATETIME_FORMAT))
+        .ok()
+}
+
+/// the generic type of the declared column type, ie: `varchar(20)` is a String,
+/// None when the declared type is not recognized
+fn declared_type(db_type: &str) -> Option<Type> {
+    let db_type = db_type.to_lowercase();
+    let db_type = match db_type.find('(') {
+        Some(pos) => db_type[..pos].trim().to_owned(),
+        None => db_type.trim().to_owned(),
+    };
+    match &db_type as &str {
+        "boolean" | "bool" => Some(Type::Bool),
+        "tinyint" => Some(Type::I8),
+        "smallint" => Some(Type::I16),
+        "integer" | "int" | "mediumint" => Some(Type::I32),
+        "bigint" => Some(Type::I64),
+        "real" | "double" | "double precision" | "float" | "numeric" | "decimal" => Some(Type::F64),
+        "text" | "varchar" | "character varying" | "char" | "character" | "clob" => {
+            Some(Type::String)
+        }
+        "blob" | "bytea" => Some(Type::VecU8),
+        "json" | "jsonb" => Some(Type::Json),
+        "uuid" => Some(Type::Uuid),
+        "timestamp with time zone" | "timestamptz" => Some(Type::DateTime),
+        "timestamp" | "timestamp without time zone" | "datetime" => Some(Type::NaiveDateTime),
+        "date" => Some(Type::NaiveDate),
+        "time" | "time without time zone" | "time with time zone" => Some(Type::NaiveTime),
+        _ => None,
+    }
+}
+
+/// cast the value read from its storage class into the declared type,
+/// the value is left as is when it can not be converted
+fn cast(value: Value, ty: &Type) -> Value {
+    match (value, ty) {
+        (Value::I64(x), &Type::Bool) => Value::Bool(x != 0),
+        (Value::I64(x), &Type::I8) => Value::I8(x as i8),
+        (Value::I64(x), &Type::I16) => Value::I16(x as i16),
+        (Value::I64(x), &Type::I32) => Value::I32(x as i32),
+        (Value::I64(x), &Type::F64) => Value::F64(x as f64),
+        (Value::String(x), &Type::Json) => {
+            match Json::from_str(&x) {
+                Ok(json) => Value::Json(json),
+                Err(_) => Value::String(x),
+            }
+        }
+        (Value::String(x), &Type::Uuid) => {
+            match Uuid::parse_str(&x) {
+                Ok(uuid) => Value::Uuid(uuid),
+                Err(_) => Value::String(x),
+            }
+        }
+        (Value::String(x), &Type::DateTime) => {
+            // the datetimes bound and CURRENT_TIMESTAMP of sqlite are in UTC, but without the timezone,
+            // rfc3339 is still read for the values written before
+            match parse_naive_datetime(&x) {
+                Some(naive) => Value::DateTime(DateTime::from_utc(naive, UTC)),
+                None => {
+                    match DateTime::parse_from_rfc3339(&x) {
+                        Ok(dt) => Value::DateTime(dt.with_timezone(&UTC)),
+                        Err(_) => Value::String(x),
+                    }
+                }
+            }
+        }
+        (Value::String(x), &Type::NaiveDateTime) => {
+            match parse_naive_datetime(&x) {
+                Some(naive) => Value::NaiveDateTime(naive),
+                None => Value::String(x),
+            }
+        }
+        (Value::String(x), &Type::NaiveDate) => {
+            match NaiveDate::parse_from_str(&x, DATE_FORMAT) {
+                Ok(date) => Value::NaiveDate(date),
+                Err(_) => Value::String(x),
+            }
+        }
+        (Value::String(x), &Type::NaiveTime) => {
+            match NaiveTime::parse_from_str(&x, TIME_FORMAT) {
+                Ok(time) => Value::NaiveTime(time),
+                Err(_) => Value::String(x),
+            }
+        }
+        (value, _) => value,
     }
 }
 
@@ -601,3 +980,126 @@ CREATE TABLE product_availability (
     ";
     let _ = Sqlite::extract_comments(create_sql);
 }
+
+/// extract the table and column from the message of a failed constraint,
+/// ie: `UNIQUE constraint failed: product.name`
+fn failed_constraint_target(message: &str) -> (Option<String>, Option<String>) {
+    match message.splitn(2, ": ").nth(1) {
+        Some(target) => {
+            let first = target.split(',').next().unwrap_or("").trim();
+            let mut parts = first.splitn(2, '.');
+            let table = parts.next().map(|t| t.to_owned());
+            let column = parts.next().map(|c| c.to_owned());
+            (table, column)
+        }
+        None => (None, None),
+    }
+}
+
+/// classify the sqlite error into the platform agnostic DbError,
+/// constraint violations all share the same code, so the message is inspected
+pub fn to_db_error(err: SqliteError) -> DbError {
+    let code = err.code;
+    let message = err.message.clone();
+    let cause = PlatformError::from(err);
+    match code {
+        // SQLITE_CONSTRAINT
+        19 => {
+            let (table, column) = failed_constraint_target(&message);
+            if message.starts_with("UNIQUE") || message.starts_with("PRIMARY KEY") {
+                DbError::UniqueViolation {
+                    constraint: None,
+                    table: table,
+                    cause: cause,
+                }
+            } else if message.starts_with("FOREIGN KEY") {
+                DbError::ForeignKeyViolation {
+                    constraint: None,
+                    table: table,
+                    cause: cause,
+                }
+            } else if message.starts_with("NOT NULL") {
+                DbError::NotNullViolation {
+                    column: column,
+                    table: table,
+                    cause: cause,
+                }
+            } else {
+                DbError::PlatformError(cause)
+            }
+        }
+        // SQLITE_BUSY, SQLITE_LOCKED: the busy timeout has elapsed
+        5 | 6 => DbError::Timeout(cause),
+        // SQLITE_IOERR, SQLITE_CANTOPEN: the database file is unreachable
+        10 | 14 => DbError::ConnectionLost(cause),
+        _ => DbError::PlatformError(cause),
+    }
+}
+
+#[test]
+fn test_failed_constraint_target() {
+    assert_eq!(failed_constraint_target("NOT NULL constraint failed: product.name"),
+               (Some("product".to_owned()), Some("name".to_owned())));
+    assert_eq!(failed_constraint_target("UNIQUE constraint failed: product.name, product.sku"),
+               (Some("product".to_owned()), Some("name".to_owned())));
+    assert_eq!(failed_constraint_target("FOREIGN KEY constraint failed"),
+               (None, None));
+}
+
+#[cfg(test)]
+fn sqlite_error(code: i32, message: &str) -> SqliteError {
+    SqliteError {
+        code: code,
+        message: message.to_owned(),
+    }
+}
+
+#[test]
+fn test_to_db_error() {
+    match to_db_error(sqlite_error(19, "UNIQUE constraint failed: product.name")) {
+        DbError::UniqueViolation { table, .. } => assert_eq!(table, Some("product".to_owned())),
+        other => panic!("expecting a unique violation, got {:?}", other),
+    }
+    match to_db_error(sqlite_error(19, "FOREIGN KEY constraint failed")) {
+        DbError::ForeignKeyViolation { .. } => (),
+        other => panic!("expecting a foreign key violation, got {:?}", other),
+    }
+    match to_db_error(sqlite_error(19, "NOT NULL constraint failed: product.name")) {
+        DbError::NotNullViolation { column, .. } => assert_eq!(column, Some("name".to_owned())),
+        other => panic!("expecting a not null violation, got {:?}", other),
+    }
+    match to_db_error(sqlite_error(5, "database is locked")) {
+        DbError::Timeout(_) => (),
+        other => panic!("expecting a timeout, got {:?}", other),
+    }
+    match to_db_error(sqlite_error(1, "no such table: product")) {
+        DbError::PlatformError(_) => (),
+        other => panic!("expecting a platform error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_cast_to_declared_type() {
+    assert_eq!(declared_type("VARCHAR(20)"), Some(Type::String));
+    assert_eq!(declared_type("geometry"), None);
+    assert_eq!(cast(Value::I64(1), &Type::Bool), Value::Bool(true));
+    assert_eq!(cast(Value::I64(20), &Type::I32), Value::I32(20));
+    assert_eq!(cast(Value::String("2015-12-01".to_owned()), &Type::NaiveDate),
+               Value::NaiveDate(NaiveDate::from_ymd(2015, 12, 1)));
+    assert_eq!(cast(Value::String("not a date".to_owned()), &Type::NaiveDate),
+               Value::String("not a date".to_owned()));
+}
+
+#[test]
+fn test_datetime_text() {
+    let naive = NaiveDate::from_ymd(2016, 3, 4).and_hms_milli(5, 6, 7, 80);
+    let datetime = DateTime::from_utc(naive, UTC);
+    assert_eq!(datetime_text(&datetime), "2016-03-04 05:06:07.080");
+
+    let read = cast(Value::String("2016-03-04 05:06:07.080".to_owned()), &Type::DateTime);
+    assert_eq!(read, Value::DateTime(datetime));
+    let read = cast(Value::String("2016-03-04T06:06:07.080+01:00".to_owned()), &Type::DateTime);
+    assert_eq!(read, Value::DateTime(datetime));
+    let read = cast(Value::String("2016-03-04T05:06:07.080".to_owned()), &Type::NaiveDateTime);
+    assert_eq!(read, Value::NaiveDateTime(naive));
+}
@@ -57,9 +57,19 @@ impl Platform {
             Platform::Postgres(ref pg) => pg,
             #[cfg(feature = "sqlite")]
             Platform::Sqlite(ref lite) => lite,
+            #[cfg(feature = "mysql")]
+            Platform::Mysql(ref my) => my,
             _ => unimplemented!(),
         }
     }
+
+    /// execute the closure inside a transaction on this connection,
+    /// commits on Ok, rolls back on Err or when the closure panics
+    pub fn transaction<T, F>(&self, f: F) -> Result<T, DbError>
+        where F: FnOnce(&Database) -> Result<T, DbError>
+    {
+        self.as_ref().transaction(f)
+    }
 }
 
 /// Postgres, Sqlite uses r2d2 connection manager,
@@ -149,8 +159,21 @@ impl ManagedPool {
             }
             #[cfg(feature = "mysql")]
             ManagedPool::Mysql(ref pool) => {
-                let my = Mysql::with_pooled_connection(pool.clone().unwrap());// I hope cloning doesn't really clone the pool, just the Arc
-                Ok(Platform::Mysql(my))
+                match *pool {
+                    Some(ref pool) => {
+                        match pool.get_conn() {
+                            Ok(conn) => {
+                                let my = Mysql::with_pooled_connection(conn);
+                                try!(my.detect_version());
+                                Ok(Platform::Mysql(my))
+                            }
+                            Err(e) => {
+                                Err(DbError::new(&format!("Unable to connect due to {}", e)))
+                            }
+                        }
+                    }
+                    None => Err(DbError::new("Mysql pool is not initialized")),
+                }
             }
             _ => Err(DbError::new("Any other database is not yet supported")),
         }
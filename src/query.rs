@@ -1,6 +1,5 @@
 use dao::{Value, ToValue};
 use table::Table;
-use std::collections::BTreeMap;
 use database::Database;
 use dao::DaoResult;
 use dao::IsDao;
@@ -8,7 +7,17 @@ use dao::Dao;
 use table::IsTable;
 use writer::SqlFrag;
 use std::fmt;
+use std::error;
 use database::DbError;
+use uuid::Uuid;
+use chrono::{Datelike, Timelike};
+use chrono::datetime::DateTime;
+use chrono::naive::date::NaiveDate;
+use chrono::naive::time::NaiveTime;
+use chrono::naive::datetime::NaiveDateTime;
+use chrono::offset::utc::UTC;
+use rustc_serialize::json::Json;
+use rustc_serialize::base64::{ToBase64, FromBase64, URL_SAFE};
 
 #[derive(Debug)]
 #[derive(Clone)]
@@ -32,8 +41,71 @@ pub struct Join {
     pub modifier: Option<Modifier>,
     pub join_type: Option<JoinType>,
     pub table_name: TableName,
+    /// the name the joined table is referred to, needed when joining the same table twice
+    pub alias: Option<String>,
+    /// column1 = column2 for each of the columns
     pub column1: Vec<String>,
     pub column2: Vec<String>,
+    /// other conditions to join on, besides the equal columns
+    pub on: Vec<Filter>,
+}
+
+impl Join {
+    pub fn new(modifier: Option<Modifier>, join_type: Option<JoinType>, table: &ToTableName) -> Self {
+        Join {
+            modifier: modifier,
+            join_type: join_type,
+            table_name: table.to_table_name(),
+            alias: None,
+            column1: vec![],
+            column2: vec![],
+            on: vec![],
+        }
+    }
+
+    pub fn alias(&mut self, alias: &str) -> &mut Self {
+        self.alias = Some(alias.to_owned());
+        self
+    }
+
+    /// the joined table as it is referred to in the rest of the query,
+    /// an aliased table is named by its alias and so are its columns
+    pub fn referred_table(&self) -> TableName {
+        match self.alias {
+            Some(ref alias) => {
+                let columns = self.table_name
+                                  .columns
+                                  .iter()
+                                  .map(|c| {
+                                      ColumnName {
+                                          column: c.column.to_owned(),
+                                          table: Some(alias.to_owned()),
+                                          schema: None,
+                                      }
+                                  })
+                                  .collect();
+                TableName {
+                    schema: None,
+                    name: alias.to_owned(),
+                    columns: columns,
+                }
+            }
+            None => self.table_name.clone(),
+        }
+    }
+
+    /// join on column1 = column2
+    pub fn on_columns(&mut self, column1: &str, column2: &str) -> &mut Self {
+        self.column1.push(column1.to_owned());
+        self.column2.push(column2.to_owned());
+        self
+    }
+
+    /// join on any condition, ie: ranges, functions or values
+    pub fn on(&mut self, filter: Filter) -> &mut Self {
+        self.on.push(filter);
+        self
+    }
 }
 #[derive(Debug)]
 #[derive(Clone)]
@@ -42,6 +114,48 @@ pub enum Direction {
     DESC,
 }
 
+/// combine the rows of queries
+#[derive(Debug)]
+#[derive(Clone)]
+#[allow(non_camel_case_types)]
+pub enum SetOperation {
+    UNION,
+    UNION_ALL,
+    INTERSECT,
+    EXCEPT,
+}
+
+/// the strength of the lock on the selected rows
+#[derive(Debug)]
+#[derive(Clone)]
+pub enum LockStrength {
+    /// FOR UPDATE, the rows can not be updated, deleted or locked by other transactions
+    UPDATE,
+    /// FOR SHARE, the rows can still be read and share locked by other transactions
+    SHARE,
+}
+
+/// what to do when the rows are already locked by another transaction
+#[derive(Debug)]
+#[derive(Clone)]
+#[allow(non_camel_case_types)]
+pub enum LockWait {
+    /// fail right away instead of waiting
+    NOWAIT,
+    /// leave out the locked rows, ie: picking jobs off a queue
+    SKIP_LOCKED,
+}
+
+/// lock the selected rows until the end of the transaction, ie: FOR UPDATE SKIP LOCKED
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Lock {
+    pub strength: LockStrength,
+    /// lock only the rows of these tables, all the tables of the query when empty
+    pub tables: Vec<String>,
+    /// wait for the locked rows when None
+    pub wait: Option<LockWait>,
+}
 
 ////
 /// Filter struct merged to query
@@ -66,9 +180,104 @@ pub enum Equality {
     IN,
     NOT_IN, // NOT_IN,
     LIKE,
-    ILIKE, //FIXME add ILIKE
+    /// case insensitive LIKE, emulated with LOWER() on databases without ILIKE
+    ILIKE,
     IS_NOT_NULL, // NOT_NULL,
     IS_NULL, // IS_NULL,
+    /// the right operand is a Vec of the lower and upper bound
+    BETWEEN,
+    NOT_BETWEEN,
+    /// the right operand is a query, the left operand is not used
+    EXISTS,
+    NOT_EXISTS,
+}
+
+/// the unit of a window frame
+#[derive(Debug)]
+#[derive(Clone)]
+pub enum FrameUnit {
+    ROWS,
+    RANGE,
+}
+
+/// the start or end of a window frame
+#[derive(Debug)]
+#[derive(Clone)]
+#[allow(non_camel_case_types)]
+pub enum FrameBound {
+    UNBOUNDED_PRECEDING,
+    PRECEDING(usize),
+    CURRENT_ROW,
+    FOLLOWING(usize),
+    UNBOUNDED_FOLLOWING,
+}
+
+/// the rows of the partition the window function is applied to,
+/// ie: ROWS BETWEEN UNBOUNDED PRECEDING AND CURRENT ROW
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Frame {
+    pub unit: FrameUnit,
+    pub start: FrameBound,
+    /// when there is no end, the frame ends at the current row
+    pub end: Option<FrameBound>,
+}
+
+/// the window of a window function, ie: OVER (PARTITION BY .. ORDER BY ..)
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Window {
+    pub partition_by: Vec<Operand>,
+    pub order_by: Vec<(String, Direction)>,
+    pub frame: Option<Frame>,
+}
+
+impl Window {
+    pub fn new() -> Self {
+        Window {
+            partition_by: vec![],
+            order_by: vec![],
+            frame: None,
+        }
+    }
+
+    pub fn partition_by(&mut self, columns: Vec<&str>) -> &mut Self {
+        for c in columns {
+            let column_name = ColumnName::from_str(c);
+            self.partition_by.push(Operand::ColumnName(column_name));
+        }
+        self
+    }
+
+    pub fn asc(&mut self, column: &str) -> &mut Self {
+        self.order_by.push((column.to_owned(), Direction::ASC));
+        self
+    }
+
+    pub fn desc(&mut self, column: &str) -> &mut Self {
+        self.order_by.push((column.to_owned(), Direction::DESC));
+        self
+    }
+
+    /// ROWS frame, counting the rows before and after the current row
+    pub fn rows(&mut self, start: FrameBound, end: Option<FrameBound>) -> &mut Self {
+        self.frame = Some(Frame {
+            unit: FrameUnit::ROWS,
+            start: start,
+            end: end,
+        });
+        self
+    }
+
+    /// RANGE frame, the rows with the same ordering values as the current row are peers
+    pub fn range(&mut self, start: FrameBound, end: Option<FrameBound>) -> &mut Self {
+        self.frame = Some(Frame {
+            unit: FrameUnit::RANGE,
+            start: start,
+            end: end,
+        });
+        self
+    }
 }
 
 /// function in a sql statement
@@ -77,6 +286,52 @@ pub enum Equality {
 pub struct Function {
     pub function: String,
     pub params: Vec<Operand>,
+    /// makes this a window function, ie: row_number() OVER (..)
+    pub over: Option<Window>,
+}
+
+impl Function {
+    pub fn new(function: &str, params: Vec<Operand>) -> Self {
+        Function {
+            function: function.to_owned(),
+            params: params,
+            over: None,
+        }
+    }
+
+    /// apply the function over a window of rows instead of aggregating them
+    pub fn over(&mut self, window: Window) -> &mut Self {
+        self.over = Some(window);
+        self
+    }
+}
+
+/// CASE WHEN filter THEN operand .. ELSE operand END
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Case {
+    pub when: Vec<(Filter, Operand)>,
+    pub else_: Option<Box<Operand>>,
+}
+
+impl Case {
+    pub fn new() -> Self {
+        Case {
+            when: vec![],
+            else_: None,
+        }
+    }
+
+    pub fn when(&mut self, filter: Filter, then: Operand) -> &mut Self {
+        self.when.push((filter, then));
+        self
+    }
+
+    /// the ELSE of the case, NULL when not specified
+    pub fn otherwise(&mut self, operand: Operand) -> &mut Self {
+        self.else_ = Some(Box::new(operand));
+        self
+    }
 }
 
 /// Operands can be columns, functions, query or value types
@@ -89,6 +344,12 @@ pub enum Operand {
     Query(Query),
     Value(Value),
     Vec(Vec<Operand>),
+    Case(Case),
+    /// a raw sql fragment written as is, for what the builder can not express,
+    /// each `?` in the sql is replaced with the placeholder of the next parameter,
+    /// `??` is written as a literal `?`, ie: the jsonb operators `??`, `??|` and `??&`.
+    /// A `?` inside a quoted string literal is left as is
+    Sql(String, Vec<Value>),
 }
 
 /// expression has left operand,
@@ -110,6 +371,8 @@ pub struct Filter {
     /// TODO: maybe renamed to LHS, supports functions and SQL
     pub condition: Condition,
     pub sub_filters: Vec<Filter>, //[FIXME] rename to sub_filters
+    /// NOT of the condition together with its sub filters
+    pub negated: bool,
 }
 
 impl Filter {
@@ -125,6 +388,7 @@ impl Filter {
                 right: right,
             },
             sub_filters: vec![],
+            negated: false,
         }
     }
 
@@ -139,6 +403,7 @@ impl Filter {
                 right: right,
             },
             sub_filters: vec![],
+            negated: false,
         }
     }
 
@@ -153,10 +418,55 @@ impl Filter {
                 right: right,
             },
             sub_filters: vec![],
+            negated: false,
         }
     }
 
 
+    /// column BETWEEN low AND high
+    pub fn between(column: &str, low: &ToValue, high: &ToValue) -> Self {
+        let range = Operand::Vec(vec![Operand::Value(low.to_db_type()),
+                                      Operand::Value(high.to_db_type())]);
+        Filter::bare_new(Operand::ColumnName(ColumnName::from_str(column)),
+                         Equality::BETWEEN,
+                         range)
+    }
+
+    pub fn not_between(column: &str, low: &ToValue, high: &ToValue) -> Self {
+        let mut filter = Filter::between(column, low, high);
+        filter.condition.equality = Equality::NOT_BETWEEN;
+        filter
+    }
+
+    /// compare the column to the result of the query, ie: column IN (SELECT ..),
+    /// the query can refer to the columns of the outer query by their table name
+    pub fn with_query(column: &str, equality: Equality, query: Query) -> Self {
+        let mut query = query;
+        query.finalize();
+        Filter::bare_new(Operand::ColumnName(ColumnName::from_str(column)),
+                         equality,
+                         Operand::Query(query))
+    }
+
+    /// EXISTS (query)
+    pub fn exists(query: Query) -> Self {
+        let mut query = query;
+        query.finalize();
+        Filter::bare_new(Operand::Vec(vec![]), Equality::EXISTS, Operand::Query(query))
+    }
+
+    pub fn not_exists(query: Query) -> Self {
+        let mut filter = Filter::exists(query);
+        filter.condition.equality = Equality::NOT_EXISTS;
+        filter
+    }
+
+    /// NOT ( filter ), the sub filters included
+    pub fn negate(&mut self) -> &mut Self {
+        self.negated = !self.negated;
+        self
+    }
+
     pub fn is_null(column: &str) -> Self {
         Filter::new(column, Equality::IS_NULL, &())
     }
@@ -414,10 +724,39 @@ impl ToTableName for Table {
 }
 
 /// Query Error
+#[derive(Debug)]
 pub enum Error {
     NoTableSpecified(String),
     NoColumnSpecified(String),
     SqlError(String),
+    /// the value can not be converted into the requested type
+    TypeMismatch(String),
+    /// the record has no value for the column
+    MissingColumn(String),
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::NoTableSpecified(ref description) => description,
+            Error::NoColumnSpecified(ref description) => description,
+            Error::SqlError(ref description) => description,
+            Error::TypeMismatch(ref description) => description,
+            Error::MissingColumn(_) => "the record has no value for the column",
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::NoTableSpecified(ref description) => write!(f, "No table specified: {}", description),
+            Error::NoColumnSpecified(ref description) => write!(f, "No column specified: {}", description),
+            Error::SqlError(ref description) => write!(f, "{}", description),
+            Error::TypeMismatch(ref description) => write!(f, "Type mismatch: {}", description),
+            Error::MissingColumn(ref column) => write!(f, "The record has no value for column {}", column),
+        }
+    }
 }
 
 
@@ -431,10 +770,12 @@ pub struct Page{
 }
 
 impl Page{
+    /// pages start at 0, the first page has no offset
     fn to_limit(&self)->Limit{
+        let offset = self.page * self.page_size;
         Limit{
             limit: self.page_size,
-            offset: Some(self.page * self.page_size),
+            offset: if offset > 0 { Some(offset) } else { None },
         }
     }
 }
@@ -448,12 +789,198 @@ pub struct Limit{
     pub offset: Option<usize>,
 }
 
+/// seek the records after (or before) the last seen values of the ORDER BY columns
+/// instead of skipping the records with OFFSET,
+/// the ORDER BY columns should be unique together, ie: end with the primary key
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub struct Keyset{
+    /// the values of the ORDER BY columns of the last seen record, empty for the first page
+    pub values: Vec<Value>,
+    pub page_size: usize,
+    /// seek the records before the values, ie: the previous page
+    pub backward: bool,
+}
+
+impl Keyset{
+
+    pub fn first(page_size: usize) -> Self {
+        Keyset {
+            values: vec![],
+            page_size: page_size,
+            backward: false,
+        }
+    }
+
+    /// the last page when seeking backward without values
+    pub fn last(page_size: usize) -> Self {
+        Keyset {
+            values: vec![],
+            page_size: page_size,
+            backward: true,
+        }
+    }
+
+    pub fn after(values: Vec<Value>, page_size: usize) -> Self {
+        Keyset {
+            values: values,
+            page_size: page_size,
+            backward: false,
+        }
+    }
+
+    pub fn before(values: Vec<Value>, page_size: usize) -> Self {
+        Keyset {
+            values: values,
+            page_size: page_size,
+            backward: true,
+        }
+    }
+
+    /// the keyset of the page the cursor returned in the DaoResult points to
+    pub fn from_cursor(cursor: &str, page_size: usize) -> Result<Self, DbError> {
+        let invalid = || DbError::new(&format!("Invalid cursor: {}", cursor));
+        let bytes = match cursor.from_base64() {
+            Ok(bytes) => bytes,
+            Err(_) => return Err(invalid()),
+        };
+        let json = match String::from_utf8(bytes).ok().and_then(|s| Json::from_str(&s).ok()) {
+            Some(json) => json,
+            None => return Err(invalid()),
+        };
+        let items = match json {
+            Json::Array(items) => items,
+            _ => return Err(invalid()),
+        };
+        let mut strings = vec![];
+        for item in items {
+            match item {
+                Json::String(item) => strings.push(item),
+                _ => return Err(invalid()),
+            }
+        }
+        if strings.is_empty() {
+            return Err(invalid());
+        }
+        let backward = match &strings[0] as &str {
+            "after" => false,
+            "before" => true,
+            _ => return Err(invalid()),
+        };
+        let mut values = vec![];
+        for item in &strings[1..] {
+            match decode_cursor_value(item) {
+                Some(value) => values.push(value),
+                None => return Err(invalid()),
+            }
+        }
+        Ok(Keyset {
+            values: values,
+            page_size: page_size,
+            backward: backward,
+        })
+    }
+
+    /// an opaque token of the values, the types are kept so they can be compared again
+    pub fn cursor(values: &[Value], backward: bool) -> Result<String, DbError> {
+        let direction = if backward { "before" } else { "after" };
+        let mut items = vec![Json::String(direction.to_owned())];
+        for value in values {
+            match encode_cursor_value(value) {
+                Some(item) => items.push(Json::String(item)),
+                None => return Err(DbError::new(&format!("{:?} can not be used in a cursor", value))),
+            }
+        }
+        Ok(Json::Array(items).to_string().as_bytes().to_base64(URL_SAFE))
+    }
+}
+
+/// the value prefixed with its type
+fn encode_cursor_value(value: &Value) -> Option<String> {
+    let item = match *value {
+        Value::Bool(x) => format!("bool:{}", x),
+        Value::I8(x) => format!("i8:{}", x),
+        Value::I16(x) => format!("i16:{}", x),
+        Value::I32(x) => format!("i32:{}", x),
+        Value::I64(x) => format!("i64:{}", x),
+        Value::U8(x) => format!("u8:{}", x),
+        Value::U16(x) => format!("u16:{}", x),
+        Value::U32(x) => format!("u32:{}", x),
+        Value::U64(x) => format!("u64:{}", x),
+        Value::F32(x) => format!("f32:{}", x),
+        Value::F64(x) => format!("f64:{}", x),
+        Value::String(ref x) => format!("string:{}", x),
+        Value::Uuid(ref x) => format!("uuid:{}", x),
+        Value::DateTime(ref x) => format!("datetime:{}.{}", x.timestamp(), x.nanosecond()),
+        Value::NaiveDateTime(ref x) => format!("naive_datetime:{}.{}", x.timestamp(), x.nanosecond()),
+        Value::NaiveDate(ref x) => format!("date:{}", x.num_days_from_ce()),
+        Value::NaiveTime(ref x) => format!("time:{}.{}", x.num_seconds_from_midnight(), x.nanosecond()),
+        Value::None => "null:".to_owned(),
+        _ => return None,
+    };
+    Some(item)
+}
+
+fn decode_cursor_value(item: &str) -> Option<Value> {
+    let mut split = item.splitn(2, ':');
+    let tag = split.next().unwrap_or("");
+    let text = match split.next() {
+        Some(text) => text,
+        None => return None,
+    };
+    // seconds and nanoseconds of the time values
+    let seconds = || {
+        let mut parts = text.splitn(2, '.');
+        match (parts.next().and_then(|s| s.parse::<i64>().ok()),
+               parts.next().and_then(|n| n.parse::<u32>().ok())) {
+            (Some(secs), Some(nanos)) => Some((secs, nanos)),
+            _ => None,
+        }
+    };
+    match tag {
+        "bool" => text.parse().ok().map(Value::Bool),
+        "i8" => text.parse().ok().map(Value::I8),
+        "i16" => text.parse().ok().map(Value::I16),
+        "i32" => text.parse().ok().map(Value::I32),
+        "i64" => text.parse().ok().map(Value::I64),
+        "u8" => text.parse().ok().map(Value::U8),
+        "u16" => text.parse().ok().map(Value::U16),
+        "u32" => text.parse().ok().map(Value::U32),
+        "u64" => text.parse().ok().map(Value::U64),
+        "f32" => text.parse().ok().map(Value::F32),
+        "f64" => text.parse().ok().map(Value::F64),
+        "string" => Some(Value::String(text.to_owned())),
+        "uuid" => Uuid::parse_str(text).ok().map(Value::Uuid),
+        "datetime" => {
+            seconds().and_then(|(secs, nanos)| NaiveDateTime::from_timestamp_opt(secs, nanos))
+                     .map(|dt| Value::DateTime(DateTime::from_utc(dt, UTC)))
+        }
+        "naive_datetime" => {
+            seconds().and_then(|(secs, nanos)| NaiveDateTime::from_timestamp_opt(secs, nanos))
+                     .map(Value::NaiveDateTime)
+        }
+        "date" => {
+            text.parse().ok()
+                .and_then(NaiveDate::from_num_days_from_ce_opt)
+                .map(Value::NaiveDate)
+        }
+        "time" => {
+            seconds().and_then(|(secs, nanos)| NaiveTime::from_num_seconds_from_midnight_opt(secs as u32, nanos))
+                     .map(Value::NaiveTime)
+        }
+        "null" => Some(Value::None),
+        _ => None,
+    }
+}
+
 #[derive(Debug)]
 #[derive(PartialEq)]
 #[derive(Clone)]
 pub enum Range{
     Page(Page),
     Limit(Limit),
+    Keyset(Keyset),
 }
 
 impl Range{
@@ -466,6 +993,13 @@ impl Range{
             &Range::Limit(ref limit) => {
                 limit.clone()
             }
+            // 1 more record is fetched to know whether there is a page after
+            &Range::Keyset(ref keyset) => {
+                Limit{
+                    limit: keyset.page_size + 1,
+                    offset: None,
+                }
+            }
         }
     }
     
@@ -485,7 +1019,12 @@ pub struct Query {
     /// whether to enumate all columns in involved models
     pub enumerate_all: bool,
 
-    pub declared_query: BTreeMap<String, Query>,
+    /// the queries declared in the WITH clause, in the order they are declared
+    /// since a declared query can refer to the ones before it
+    pub declared_query: Vec<(String, Query)>,
+
+    /// the declared queries can refer to themselves, ie: WITH RECURSIVE
+    pub recursive: bool,
 
     ///fields can be functions, column sql query, and even columns
     /// TODO; merge enumerated column to this, add a builder for fields
@@ -529,8 +1068,27 @@ pub struct Query {
     /// The data values, used in bulk inserting, updating,
     pub values:Vec<Operand>,
 
+    /// the rows inserted after the row in `values`, for inserting multiple records in 1 statement
+    pub rows: Vec<Vec<Operand>>,
+
+    /// the query whose records are inserted, ie: INSERT INTO .. SELECT
+    pub source: Option<Box<Query>>,
+
     /// the returning clause of the query when supported,
     pub enumerated_returns: Vec<Field>,
+
+    /// the unique columns, which when in conflict on insert will update the existing record instead (upsert)
+    pub on_conflict: Vec<String>,
+
+    /// the queries combined with this query, ie: UNION,
+    /// the order by and range of this query applies to the combined rows
+    pub combined: Vec<(SetOperation, Query)>,
+
+    /// the locking of the selected rows, ie: FOR UPDATE
+    pub lock: Option<Lock>,
+
+    /// the paging methods that were mixed up, reported as an error when the query is built
+    pub range_error: Option<String>,
 }
 
 impl Query {
@@ -541,7 +1099,8 @@ impl Query {
             sql_type: SqlType::SELECT,
             distinct: false,
             enumerate_all: false,
-            declared_query: BTreeMap::new(),
+            declared_query: vec![],
+            recursive: false,
             enumerated_fields: vec![],
             distinct_on_columns: vec![],
             filters: vec![],
@@ -553,7 +1112,13 @@ impl Query {
             range: None,
             from: None,
             values: vec![],
+            rows: vec![],
+            source: None,
             enumerated_returns: vec![],
+            on_conflict: vec![],
+            combined: vec![],
+            lock: None,
+            range_error: None,
         }
     }
 
@@ -629,6 +1194,32 @@ impl Query {
         self
     }
 
+    /// enumerate a field, such as a function or a column with an alias
+    pub fn add_field(&mut self, field: Field) -> &mut Self {
+        self.enumerated_fields.push(field);
+        self
+    }
+
+    /// enumerate the result of a query which returns a single value, named as alias
+    pub fn column_query(&mut self, query: Query, alias: &str) -> &mut Self {
+        let mut query = query;
+        query.finalize();
+        let field = Field {
+            operand: Operand::Query(query),
+            name: Some(alias.to_owned()),
+        };
+        self.add_field(field)
+    }
+
+    /// enumerate the result of the function, named as alias
+    pub fn function(&mut self, function: Function, alias: &str) -> &mut Self {
+        let field = Field {
+            operand: Operand::Function(function),
+            name: Some(alias.to_owned()),
+        };
+        self.add_field(field)
+    }
+
     pub fn group_by(&mut self, columns: Vec<&str>) -> &mut Self {
         for c in columns {
             let column_name = ColumnName::from_str(c);
@@ -667,7 +1258,7 @@ impl Query {
         self
     }
 
-    /// when paging multiple records
+    /// when paging multiple records, the first page is 0
     pub fn set_page(&mut self, page: usize) -> &mut Self {
         let new_range = match self.range{
             Some(ref range) => {
@@ -677,7 +1268,12 @@ impl Query {
                         Some(Range::Page(Page{page:page, page_size: p.page_size}))
                     },
                     &Range::Limit(_) => {
-                        panic!("Do not mix page->page_size with limit and offset");
+                        self.range_error = Some("Do not mix page->page_size with limit and offset".to_owned());
+                        return self;
+                    }
+                    &Range::Keyset(_) => {
+                        self.range_error = Some("Do not mix page->page_size with keyset paging".to_owned());
+                        return self;
                     }
                 }
             },
@@ -699,7 +1295,12 @@ impl Query {
                         Some(Range::Page(Page{page:p.page, page_size: page_size}))
                     },
                     &Range::Limit(_) => {
-                        panic!("Do not mix page->page_size with limit and offset");
+                        self.range_error = Some("Do not mix page->page_size with limit and offset".to_owned());
+                        return self;
+                    }
+                    &Range::Keyset(_) => {
+                        self.range_error = Some("Do not mix page->page_size with keyset paging".to_owned());
+                        return self;
                     }
                     
                 }
@@ -761,19 +1362,109 @@ impl Query {
 
     /// if the database support CTE declareted query i.e WITH,
     /// then this query will be declared
-    /// if database doesn't support WITH queries, then this query will be
-    /// wrapped in the from_query
-    /// build a builder for this
+    /// building the query fails on databases that doesn't support WITH queries
     pub fn declare_query(&mut self, query: Query, alias: &str) -> &mut Self {
-        self.declared_query.insert(alias.to_owned(), query);
+        self.declared_query.retain(|&(ref a, _)| a != alias);
+        self.declared_query.push((alias.to_owned(), query));
+        self
+    }
+
+    /// combine the rows of the query, the columns should match the columns of this query,
+    /// order_by and the limit set on this query applies to the combined rows
+    pub fn combine(&mut self, operation: SetOperation, query: Query) -> &mut Self {
+        self.combined.push((operation, query));
+        self
+    }
+
+    /// lock the selected rows for update, ie: SELECT .. FOR UPDATE
+    pub fn for_update(&mut self) -> &mut Self {
+        self.set_lock(LockStrength::UPDATE)
+    }
+
+    /// lock the selected rows against updates while still allowing other share locks
+    pub fn for_share(&mut self) -> &mut Self {
+        self.set_lock(LockStrength::SHARE)
+    }
+
+    fn set_lock(&mut self, strength: LockStrength) -> &mut Self {
+        match self.lock {
+            Some(ref mut lock) => lock.strength = strength,
+            None => {
+                self.lock = Some(Lock {
+                    strength: strength,
+                    tables: vec![],
+                    wait: None,
+                })
+            }
+        }
+        self
+    }
+
+    /// the lock being set, defaults to FOR UPDATE
+    fn get_lock(&mut self) -> &mut Lock {
+        if self.lock.is_none() {
+            self.set_lock(LockStrength::UPDATE);
+        }
+        self.lock.as_mut().unwrap()
+    }
+
+    /// lock only the rows of this table, ie: FOR UPDATE OF product
+    pub fn lock_of(&mut self, table: &str) -> &mut Self {
+        self.get_lock().tables.push(table.to_owned());
+        self
+    }
+
+    /// fail instead of waiting for the rows locked by other transactions
+    pub fn nowait(&mut self) -> &mut Self {
+        self.get_lock().wait = Some(LockWait::NOWAIT);
+        self
+    }
+
+    /// leave out the rows locked by other transactions
+    pub fn skip_locked(&mut self) -> &mut Self {
+        self.get_lock().wait = Some(LockWait::SKIP_LOCKED);
         self
     }
 
+    /// UNION, the duplicate rows are removed
+    pub fn union(&mut self, query: Query) -> &mut Self {
+        self.combine(SetOperation::UNION, query)
+    }
+
+    /// UNION ALL, the duplicate rows are kept
+    pub fn union_all(&mut self, query: Query) -> &mut Self {
+        self.combine(SetOperation::UNION_ALL, query)
+    }
+
+    pub fn intersect(&mut self, query: Query) -> &mut Self {
+        self.combine(SetOperation::INTERSECT, query)
+    }
+
+    pub fn except(&mut self, query: Query) -> &mut Self {
+        self.combine(SetOperation::EXCEPT, query)
+    }
+
+    /// declare a common table expression, which can then be selected from by its alias
+    /// ie: WITH alias AS (query) SELECT * FROM alias
+    pub fn with(&mut self, alias: &str, query: Query) -> &mut Self {
+        self.declare_query(query, alias)
+    }
+
+    /// declare a common table expression which can refer to itself,
+    /// usually a UNION ALL of the starting rows and the rows joined to the alias,
+    /// this turns the whole WITH clause into WITH RECURSIVE
+    pub fn with_recursive(&mut self, alias: &str, query: Query) -> &mut Self {
+        self.recursive = true;
+        self.declare_query(query, alias)
+    }
+
     /// a query to query from
     /// use WITH (query) t1 SELECT from t1 declaration in postgresql, sqlite
     /// use SELECT FROM (query) in oracle, mysql, others
     /// alias of the table
     pub fn from_query(&mut self, query: Query, alias: &str) -> &mut Self {
+        let mut query = query;
+        query.finalize();
         let operand = Operand::Query(query);
         let field = Field {
             operand: operand,
@@ -809,39 +1500,24 @@ impl Query {
         self.left_join(&table, column1, column2)
     }
     pub fn left_join(&mut self, table: &ToTableName, column1: &str, column2: &str) -> &mut Self {
-        let join = Join {
-            modifier: Some(Modifier::LEFT),
-            join_type: None,
-            table_name: table.to_table_name(),
-            column1: vec![column1.to_owned()],
-            column2: vec![column2.to_owned()],
-        };
+        let mut join = Join::new(Some(Modifier::LEFT), None, table);
+        join.on_columns(column1, column2);
         self.join(join)
     }
     pub fn right_join_table(&mut self, table: &str, column1: &str, column2: &str) -> &mut Self {
         self.right_join(&table, column1, column2)
     }
     pub fn right_join(&mut self, table: &ToTableName, column1: &str, column2: &str) -> &mut Self {
-        let join = Join {
-            modifier: Some(Modifier::RIGHT),
-            join_type: None,
-            table_name: table.to_table_name(),
-            column1: vec![column1.to_owned()],
-            column2: vec![column2.to_owned()],
-        };
+        let mut join = Join::new(Some(Modifier::RIGHT), None, table);
+        join.on_columns(column1, column2);
         self.join(join)
     }
     pub fn full_join_table(&mut self, table: &str, column1: &str, column2: &str) -> &mut Self {
         self.full_join(&table, column1, column2)
     }
     pub fn full_join(&mut self, table: &ToTableName, column1: &str, column2: &str) -> &mut Self {
-        let join = Join {
-            modifier: Some(Modifier::FULL),
-            join_type: None,
-            table_name: table.to_table_name(),
-            column1: vec![column1.to_owned()],
-            column2: vec![column2.to_owned()],
-        };
+        let mut join = Join::new(Some(Modifier::FULL), None, table);
+        join.on_columns(column1, column2);
         self.join(join)
     }
 
@@ -849,13 +1525,26 @@ impl Query {
         self.inner_join(&table, column1, column2)
     }
     pub fn inner_join(&mut self, table: &ToTableName, column1: &str, column2: &str) -> &mut Self {
-        let join = Join {
-            modifier: None,
-            join_type: Some(JoinType::INNER),
-            table_name: table.to_table_name(),
-            column1: vec![column1.to_owned()],
-            column2: vec![column2.to_owned()],
-        };
+        let mut join = Join::new(None, Some(JoinType::INNER), table);
+        join.on_columns(column1, column2);
+        self.join(join)
+    }
+
+    /// join every row of the table, without condition
+    pub fn cross_join_table(&mut self, table: &str) -> &mut Self {
+        self.cross_join(&table)
+    }
+    pub fn cross_join(&mut self, table: &ToTableName) -> &mut Self {
+        let join = Join::new(None, Some(JoinType::CROSS), table);
+        self.join(join)
+    }
+
+    /// join on the columns with the same name in both tables
+    pub fn natural_join_table(&mut self, table: &str) -> &mut Self {
+        self.natural_join(&table)
+    }
+    pub fn natural_join(&mut self, table: &ToTableName) -> &mut Self {
+        let join = Join::new(None, Some(JoinType::NATURAL), table);
         self.join(join)
     }
 
@@ -903,8 +1592,9 @@ impl Query {
             tables.push(from.clone());
         }
         for j in &self.joins {
-            if !tables.contains(&&j.table_name) {
-                tables.push(j.table_name.clone());
+            let table = j.referred_table();
+            if !tables.contains(&table) {
+                tables.push(table);
             }
         }
         tables
@@ -935,6 +1625,12 @@ impl Query {
         if self.excluded_columns.is_empty() && self.enumerated_fields.is_empty() {
             self.all();
         }
+        for &mut (_, ref mut declared) in &mut self.declared_query {
+            declared.finalize();
+        }
+        for &mut (_, ref mut combined) in &mut self.combined {
+            combined.finalize();
+        }
         self
     }
 
@@ -1050,6 +1746,11 @@ impl Query {
         self.add_filter(Filter::new(column, equality, value))
     }
 
+    /// column IN (query), column = (query)
+    pub fn filter_query(&mut self, column: &str, equality: Equality, query: Query) -> &mut Self {
+        self.add_filter(Filter::with_query(column, equality, query))
+    }
+
     /// column = value
     pub fn filter_eq(&mut self, column: &str, value: &ToValue) -> &mut Self {
         self.add_filter(Filter::new(column, Equality::EQ, value))
@@ -1082,12 +1783,68 @@ impl Query {
         self.add_value(operand)
     }
 
+    /// add another row of values to be inserted,
+    /// in the same order as the columns
+    pub fn add_row(&mut self, values: Vec<Operand>) -> &mut Self {
+        self.rows.push(values);
+        self
+    }
+
+    pub fn row(&mut self, values: &[&ToValue]) -> &mut Self {
+        let operands = values.iter().map(|v| Operand::Value(v.to_db_type())).collect();
+        self.add_row(operands)
+    }
+
+    /// all the rows of values to be inserted, the first one being `values`
+    pub fn get_rows(&self) -> Vec<&Vec<Operand>> {
+        let mut rows = vec![];
+        if !self.values.is_empty() {
+            rows.push(&self.values);
+        }
+        for row in &self.rows {
+            rows.push(row);
+        }
+        rows
+    }
+
+    /// split the rows of this insert into queries of at most `rows_per_query` rows each,
+    /// used for keeping the parameters under the limit of the database
+    pub fn split_rows(&self, rows_per_query: usize) -> Vec<Query> {
+        let rows: Vec<Vec<Operand>> = self.get_rows().into_iter().cloned().collect();
+        let mut queries = vec![];
+        for chunk in rows.chunks(rows_per_query) {
+            let mut query = self.clone();
+            query.values = chunk[0].clone();
+            query.rows = chunk[1..].to_vec();
+            queries.push(query);
+        }
+        queries
+    }
+
+    /// insert the records of the query instead of values,
+    /// the query should select as many columns as the enumerated columns
+    pub fn insert_from_query(&mut self, query: Query) -> &mut Self {
+        let mut query = query;
+        query.finalize();
+        self.source = Some(Box::new(query));
+        self
+    }
+
     /// set a value of a column when inserting/updating records
     pub fn set(&mut self, column: &str, value: &ToValue) -> &mut Self {
         self.column(column);
         self.value(value)
     }
 
+    /// when inserting, update the existing record instead
+    /// if it conflicts with these unique columns (upsert)
+    pub fn on_conflict_update(&mut self, columns: Vec<&str>) -> &mut Self {
+        for c in columns {
+            self.on_conflict.push(c.to_owned());
+        }
+        self
+    }
+
     pub fn return_all(&mut self) -> &mut Self {
         self.enumerate_column_as_return("*")
     }
@@ -1111,7 +1868,7 @@ impl Query {
     }
 
     /// build the query only, not executed, useful when debugging
-    pub fn build(&mut self, db: &Database) -> SqlFrag {
+    pub fn build(&mut self, db: &Database) -> Result<SqlFrag, DbError> {
         self.finalize();
         db.build_query(self)
     }
@@ -1119,7 +1876,9 @@ impl Query {
     /// expects a return, such as select, insert/update with returning clause
     pub fn retrieve(&mut self, db: &Database) -> Result<DaoResult, DbError> {
         self.finalize();
-        db.execute_with_return(self)
+        let mut result = try!(db.execute_with_return(self));
+        try!(self.apply_keyset(&mut result));
+        Ok(result)
     }
 
     /// expects a return, such as select, insert/update with returning clause
@@ -1130,6 +1889,143 @@ impl Query {
         db.execute_with_one_return(self)
     }
 
+    /// insert all the rows, in as many statements as the parameter limit of the database requires
+    pub fn insert_batch(&mut self, db: &Database) -> Result<Vec<Dao>, DbError> {
+        self.finalize();
+        db.insert_batch(self)
+    }
+
+    /// page through the records by seeking past the values of the ORDER BY columns
+    pub fn set_keyset(&mut self, keyset: Keyset) -> &mut Self {
+        self.range = Some(Range::Keyset(keyset));
+        self
+    }
+
+    /// seek the page the cursor points to, or the first page when there is no cursor yet
+    pub fn seek(&mut self, cursor: Option<&str>, page_size: usize) -> Result<&mut Self, DbError> {
+        let keyset = match cursor {
+            Some(cursor) => try!(Keyset::from_cursor(cursor, page_size)),
+            None => Keyset::first(page_size),
+        };
+        Ok(self.set_keyset(keyset))
+    }
+
+    /// drop the extra record fetched to know whether there is a following page,
+    /// restore the order of the records when seeking backward, and set the cursors
+    fn apply_keyset(&self, result: &mut DaoResult) -> Result<(), DbError> {
+        let keyset = match self.range {
+            Some(Range::Keyset(ref keyset)) => keyset,
+            _ => return Ok(()),
+        };
+        let has_more = result.dao.len() > keyset.page_size;
+        result.dao.truncate(keyset.page_size);
+        let (has_next, has_prev) = if keyset.backward {
+            result.dao.reverse();
+            (!keyset.values.is_empty(), has_more)
+        } else {
+            (has_more, !keyset.values.is_empty())
+        };
+        let next_cursor = match result.dao.last() {
+            Some(last) if has_next => Some(try!(self.cursor_of(last, false))),
+            _ => None,
+        };
+        let prev_cursor = match result.dao.first() {
+            Some(first) if has_prev => Some(try!(self.cursor_of(first, true))),
+            _ => None,
+        };
+        result.page_size = Some(keyset.page_size);
+        result.next_cursor = next_cursor;
+        result.prev_cursor = prev_cursor;
+        Ok(())
+    }
+
+    /// the cursor pointing after or before this record
+    fn cursor_of(&self, dao: &Dao, backward: bool) -> Result<String, DbError> {
+        let mut values = vec![];
+        for &(ref column, _) in &self.order_by {
+            let name = match column.rfind('.') {
+                Some(index) => &column[index + 1..],
+                None => column,
+            };
+            match dao.values.get(column).or_else(|| dao.values.get(name)) {
+                Some(value) => values.push(value.clone()),
+                None => {
+                    return Err(DbError::new(&format!("The ORDER BY column {} should be selected for keyset paging",
+                                                     column)))
+                }
+            }
+        }
+        Keyset::cursor(&values, backward)
+    }
+
+    /// the query counting all the records of this query regardless of the page,
+    /// the ordering and range are dropped.
+    /// Distinct, grouped and combined queries are counted as a subquery
+    pub fn count_query(&self) -> Query {
+        let mut inner = self.clone();
+        inner.order_by = vec![];
+        inner.range = None;
+        inner.lock = None;
+        let total = Field {
+            operand: Operand::Sql("COUNT(*)".to_owned(), vec![]),
+            name: Some("total".to_owned()),
+        };
+        let is_aggregated = inner.distinct || !inner.distinct_on_columns.is_empty() ||
+                            !inner.group_by.is_empty() || !inner.combined.is_empty();
+        if is_aggregated {
+            let mut count = Query::select();
+            count.add_field(total)
+                 .from_query(inner, "counted");
+            count
+        } else {
+            inner.enumerate_all = false;
+            inner.enumerated_fields = vec![total];
+            inner.excluded_columns = vec![];
+            inner
+        }
+    }
+
+    /// the number of records of this query regardless of the page
+    pub fn count(&self, db: &Database) -> Result<usize, DbError> {
+        let mut count = self.count_query();
+        match try!(count.retrieve_one(db)) {
+            Some(dao) => {
+                let total: i64 = try!(dao.try_get("total"));
+                Ok(total as usize)
+            }
+            None => Err(DbError::new("No result from the count query")),
+        }
+    }
+
+    /// retrieve the records of the page,
+    /// the result includes the total number of records and the page it is in, counted from 0
+    pub fn retrieve_page(&mut self, db: &Database) -> Result<DaoResult, DbError> {
+        self.finalize();
+        let total = try!(self.count(db));
+        let mut result = try!(db.execute_with_return(self));
+        try!(self.apply_keyset(&mut result));
+        result.total = Some(total);
+        match self.range {
+            Some(Range::Page(ref page)) => {
+                result.page = Some(page.page);
+                result.page_size = Some(page.page_size);
+            }
+            Some(Range::Limit(ref limit)) => {
+                if limit.limit > 0 {
+                    result.page = Some(limit.offset.unwrap_or(0) / limit.limit);
+                }
+                result.page_size = Some(limit.limit);
+            }
+            // there is no page number when seeking
+            Some(Range::Keyset(_)) => (),
+            None => {
+                result.page = Some(0);
+                result.page_size = Some(total);
+            }
+        }
+        Ok(result)
+    }
+
     /// delete, update without caring for the return
     pub fn execute(&mut self, db: &Database) -> Result<usize, DbError> {
         self.finalize();
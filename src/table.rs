@@ -26,8 +26,15 @@ pub struct Column {
 
 impl Column {
 
-    fn is_keyword(str: &str) -> bool {
-        let keyword = ["type", "yield", "macro"];
+    /// the rust keywords, including the ones reserved for future use
+    pub fn is_keyword(str: &str) -> bool {
+        let keyword = ["abstract", "alignof", "as", "become", "box", "break", "const",
+                       "continue", "crate", "do", "else", "enum", "extern", "false", "final",
+                       "fn", "for", "if", "impl", "in", "let", "loop", "macro", "match", "mod",
+                       "move", "mut", "offsetof", "override", "priv", "proc", "pub", "pure",
+                       "ref", "return", "self", "sizeof", "static", "struct", "super", "trait",
+                       "true", "type", "typeof", "unsafe", "unsized", "use", "virtual", "where",
+                       "while", "yield"];
         keyword.contains(&str)
     }
     
@@ -212,8 +219,13 @@ impl PartialEq for Table {
 impl Table {
 
     /// return the long name of the table using schema.table_name
+    /// the table name prefixed with the schema, when there is one
     pub fn complete_name(&self) -> String {
-        format!("{}.{}", self.schema, self.name)
+        if self.schema.is_empty() {
+            self.name.to_owned()
+        } else {
+            format!("{}.{}", self.schema, self.name)
+        }
     }
 
     /// capitalize the first later, if there is underscore remove it then capitalize the next letter
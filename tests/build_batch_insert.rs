@@ -0,0 +1,80 @@
+extern crate rustorm;
+
+use rustorm::query::Query;
+use rustorm::query::Equality;
+use rustorm::database::Database;
+use rustorm::platform::Postgres;
+
+
+#[test]
+fn test_multi_row_insert() {
+    let pg = Postgres::new();
+
+    let mut query = Query::insert();
+    query.into_table("bazaar.product")
+         .columns(vec!["product_id", "name"])
+         .row(&[&1, &"product1"])
+         .row(&[&2, &"product2"])
+         .row(&[&3, &"product3"])
+         .return_all();
+
+    let frag = query.build(&pg).unwrap();
+
+    let expected = "
+   INSERT INTO bazaar.product( product_id, name )\x20
+   VALUES ($1 , $2 ),
+          ($3 , $4 ),
+          ($5 , $6 )\x20
+RETURNING *
+".to_string();
+    println!("actual:   {{\n{}}} [{}]", frag.sql, frag.sql.len());
+    println!("expected: {{{}}} [{}]", expected, expected.len());
+    assert!(frag.sql.trim() == expected.trim());
+    assert_eq!(frag.params.len(), 6);
+}
+
+#[test]
+fn test_batch_insert_is_chunked() {
+    let pg = Postgres::new();
+
+    let mut query = Query::insert();
+    query.into_table("bazaar.product")
+         .columns(vec!["product_id", "name"]);
+    for i in 0..20000 {
+        query.row(&[&i, &"product"]);
+    }
+
+    let frags = pg.build_insert_batch(&query).unwrap();
+    let params: Vec<usize> = frags.iter().map(|f| f.params.len()).collect();
+    println!("params: {:?}", params);
+    assert_eq!(params, vec![32766, 7234]);
+    assert!(params.iter().all(|p| *p <= pg.max_params()));
+}
+
+#[test]
+fn test_insert_from_query() {
+    let pg = Postgres::new();
+
+    let mut archived = Query::select();
+    archived.columns(vec!["product_id", "name"])
+            .from_table("bazaar.archived_product")
+            .filter("discontinued", Equality::EQ, &false);
+
+    let mut query = Query::insert();
+    query.into_table("bazaar.product")
+         .columns(vec!["product_id", "name"])
+         .insert_from_query(archived);
+
+    let frag = query.build(&pg).unwrap();
+
+    let expected = "
+   INSERT INTO bazaar.product( product_id, name )\x20
+   SELECT product_id, name
+     FROM bazaar.archived_product
+    WHERE discontinued = $1
+".to_string();
+    println!("actual:   {{\n{}}} [{}]", frag.sql, frag.sql.len());
+    println!("expected: {{{}}} [{}]", expected, expected.len());
+    assert!(frag.sql.trim() == expected.trim());
+    assert_eq!(frag.params.len(), 1);
+}
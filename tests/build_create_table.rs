@@ -0,0 +1,71 @@
+extern crate rustorm;
+
+use rustorm::table::{Table, Column, Foreign};
+use rustorm::database::DatabaseDDL;
+use rustorm::platform::Postgres;
+
+
+fn column(name: &str, db_data_type: &str) -> Column {
+    Column {
+        name: name.to_owned(),
+        data_type: "".to_owned(),
+        db_data_type: db_data_type.to_owned(),
+        is_primary: false,
+        is_unique: false,
+        default: None,
+        comment: None,
+        not_null: false,
+        foreign: None,
+        is_inherited: false,
+    }
+}
+
+#[test]
+fn test_create_table() {
+    let pg = Postgres::new();
+
+    let mut product_id = column("product_id", "uuid");
+    product_id.is_primary = true;
+    product_id.not_null = true;
+    product_id.default = Some("uuid_generate_v4()".to_owned());
+
+    let mut name = column("name", "character varying");
+    name.comment = Some("the product's name".to_owned());
+
+    let mut owner_id = column("owner_id", "uuid");
+    owner_id.foreign = Some(Foreign {
+        schema: "bazaar".to_owned(),
+        table: "users".to_owned(),
+        column: "user_id".to_owned(),
+    });
+
+    let mut created = column("created", "timestamp with time zone");
+    created.is_inherited = true;
+
+    let table = Table {
+        schema: "bazaar".to_owned(),
+        name: "product".to_owned(),
+        parent_table: Some("record".to_owned()),
+        sub_table: vec![],
+        comment: Some("all the products".to_owned()),
+        columns: vec![product_id, name, owner_id, created],
+        is_view: false,
+    };
+
+    let frag = pg.build_create_table(&table);
+
+    let expected = "
+CREATE TABLE bazaar.product (
+    product_id uuid NOT NULL DEFAULT uuid_generate_v4(),
+    name character varying,
+    owner_id uuid,
+    PRIMARY KEY (product_id),
+    CONSTRAINT product_owner_id_fkey FOREIGN KEY (owner_id) REFERENCES bazaar.users (user_id)
+) INHERITS (bazaar.record);
+COMMENT ON TABLE bazaar.product IS 'all the products';
+COMMENT ON COLUMN bazaar.product.name IS 'the product''s name';
+".to_string();
+    println!("actual:   {{\n{}}} [{}]", frag.sql, frag.sql.len());
+    println!("expected: {{{}}} [{}]", expected, expected.len());
+    assert!(frag.sql.trim() == expected.trim());
+}
@@ -0,0 +1,78 @@
+extern crate rustorm;
+
+use rustorm::query::Query;
+use rustorm::query::Equality;
+use rustorm::query::Filter;
+use rustorm::platform::Postgres;
+
+
+#[test]
+fn test_with_query() {
+    let pg = Postgres::new();
+
+    let mut electronic = Query::select();
+    electronic.columns(vec!["category_id", "name"])
+              .from_table("bazaar.category")
+              .filter("name", Equality::EQ, &"Electronic");
+
+    let mut query = Query::select_all();
+    query.with("electronic", electronic)
+         .from_table("electronic")
+         .filter("name", Equality::LIKE, &"Elec%");
+    let frag = query.build(&pg).unwrap();
+
+    let expected = "
+     WITH electronic AS (
+   SELECT category_id, name
+     FROM bazaar.category
+    WHERE name = $1\x20
+)
+   SELECT *
+     FROM electronic
+    WHERE name LIKE $2\x20
+".to_string();
+    println!("actual:   {{\n{}}} [{}]", frag.sql, frag.sql.len());
+    println!("expected: {{{}}} [{}]", expected, expected.len());
+    assert!(frag.sql.trim() == expected.trim());
+    assert_eq!(frag.params.len(), 2);
+}
+
+#[test]
+fn test_with_recursive_query() {
+    let pg = Postgres::new();
+
+    let mut roots = Query::select();
+    roots.columns(vec!["category_id", "parent_category_id", "name"])
+         .from_table("bazaar.category")
+         .add_filter(Filter::is_null("parent_category_id"));
+
+    let mut children = Query::select();
+    children.columns(vec!["category.category_id", "category.parent_category_id", "category.name"])
+            .from_table("bazaar.category")
+            .inner_join_table("tree", "tree.category_id", "category.parent_category_id");
+
+    roots.union_all(children);
+
+    let mut query = Query::select_all();
+    query.with_recursive("tree", roots)
+         .from_table("tree");
+    let frag = query.build(&pg).unwrap();
+
+    let expected = "
+WITH RECURSIVE tree AS (
+   SELECT category_id, parent_category_id, name
+     FROM bazaar.category
+    WHERE parent_category_id IS NULL
+UNION ALL\x20
+   SELECT category.category_id, category.parent_category_id, category.name
+     FROM bazaar.category
+          INNER JOIN tree\x20
+          ON tree.category_id = category.parent_category_id\x20
+)
+   SELECT *
+     FROM tree
+".to_string();
+    println!("actual:   {{\n{}}} [{}]", frag.sql, frag.sql.len());
+    println!("expected: {{{}}} [{}]", expected, expected.len());
+    assert!(frag.sql.trim() == expected.trim());
+}
@@ -0,0 +1,119 @@
+extern crate rustorm;
+
+use rustorm::query::{Query, Join, Filter, Equality, Operand, ColumnName};
+use rustorm::query::{Modifier, JoinType};
+use rustorm::table::{Table, Column};
+use rustorm::platform::Postgres;
+
+
+fn column(column: &str) -> Operand {
+    Operand::ColumnName(ColumnName::from_str(column))
+}
+
+fn employee() -> Table {
+    let columns = vec!["employee_id", "name", "manager_id"]
+                      .iter()
+                      .map(|name| {
+                          Column {
+                              name: name.to_string(),
+                              data_type: "".to_owned(),
+                              db_data_type: "".to_owned(),
+                              is_primary: *name == "employee_id",
+                              is_unique: false,
+                              default: None,
+                              comment: None,
+                              not_null: false,
+                              foreign: None,
+                              is_inherited: false,
+                          }
+                      })
+                      .collect();
+    Table {
+        schema: "bazaar".to_owned(),
+        name: "employee".to_owned(),
+        parent_table: None,
+        sub_table: vec![],
+        comment: None,
+        columns: columns,
+        is_view: false,
+    }
+}
+
+#[test]
+fn test_self_join_and_range_join() {
+    let pg = Postgres::new();
+
+    let mut manager = Join::new(Some(Modifier::LEFT), None, &"bazaar.employee");
+    manager.alias("manager")
+           .on_columns("manager.employee_id", "employee.manager_id");
+
+    let mut grade = Join::new(None, Some(JoinType::INNER), &"bazaar.salary_grade");
+    grade.on(Filter::bare_new(column("employee.salary"),
+                              Equality::GTE,
+                              column("salary_grade.min_salary")))
+         .on(Filter::bare_new(column("employee.salary"),
+                              Equality::LT,
+                              column("salary_grade.max_salary")));
+
+    let mut query = Query::select_all();
+    query.from_table("bazaar.employee")
+         .join(manager)
+         .join(grade)
+         .cross_join_table("bazaar.currency")
+         .filter("employee.active", Equality::EQ, &true);
+    let frag = query.build(&pg).unwrap();
+
+    let expected = "
+   SELECT *
+     FROM bazaar.employee
+          LEFT JOIN bazaar.employee manager\x20
+          ON manager.employee_id = employee.manager_id\x20
+          INNER JOIN bazaar.salary_grade\x20
+          ON employee.salary >= salary_grade.min_salary
+          AND employee.salary < salary_grade.max_salary
+          CROSS JOIN bazaar.currency\x20
+    WHERE employee.active = $1\x20
+".to_string();
+    println!("actual:   {{\n{}}} [{}]", frag.sql, frag.sql.len());
+    println!("expected: {{{}}} [{}]", expected, expected.len());
+    assert!(frag.sql.trim() == expected.trim());
+}
+
+#[test]
+fn test_join_without_condition() {
+    let pg = Postgres::new();
+
+    let mut query = Query::select_all();
+    query.from_table("bazaar.product")
+         .join(Join::new(None, Some(JoinType::INNER), &"bazaar.category"));
+
+    assert!(query.build(&pg).is_err());
+}
+
+#[test]
+fn test_self_join_columns_use_the_alias() {
+    let employee = employee();
+    let mut manager = Join::new(Some(Modifier::LEFT), None, &employee);
+    manager.alias("manager")
+           .on_columns("manager.employee_id", "employee.manager_id");
+
+    let mut query = Query::enumerate_all();
+    query.from(&employee)
+         .join(manager);
+
+    let tables: Vec<String> = query.get_involved_tables().iter().map(|t| t.complete_name()).collect();
+    assert_eq!(tables, vec!["bazaar.employee", "manager"]);
+
+    query.finalize();
+    let columns: Vec<String> = query.get_enumerated_columns().iter().map(|c| c.complete_name()).collect();
+    assert_eq!(columns,
+               vec!["employee.employee_id",
+                    "employee.name",
+                    "employee.manager_id",
+                    "manager.employee_id",
+                    "manager.name",
+                    "manager.manager_id"]);
+    let renamed: Vec<String> = query.get_renamed_columns().iter().map(|&(_, ref r)| r.to_owned()).collect();
+    assert!(renamed.contains(&"manager.name".to_owned()));
+    assert!(renamed.contains(&"employee.name".to_owned()));
+}
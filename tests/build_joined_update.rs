@@ -0,0 +1,176 @@
+extern crate rustorm;
+
+use rustorm::query::Query;
+use rustorm::query::Equality;
+use rustorm::query::{Field, Operand, TableName};
+use rustorm::platform::Postgres;
+#[cfg(feature = "sqlite")]
+use rustorm::platform::Sqlite;
+#[cfg(feature = "mysql")]
+use rustorm::platform::Mysql;
+
+
+#[test]
+fn test_update_from() {
+    let pg = Postgres::new();
+
+    let mut query = Query::update();
+    query.from_table("bazaar.orders")
+         .set("status", &"inactive customer")
+         .inner_join_table("bazaar.customer", "orders.customer_id", "customer.customer_id")
+         .filter("customer.active", Equality::EQ, &false);
+    let frag = query.build(&pg).unwrap();
+
+    let expected = "
+   UPDATE bazaar.orders
+      SET status = $1\x20
+     FROM bazaar.customer
+    WHERE orders.customer_id = customer.customer_id
+      AND customer.active = $2
+".to_string();
+    println!("actual:   {{\n{}}} [{}]", frag.sql, frag.sql.len());
+    println!("expected: {{{}}} [{}]", expected, expected.len());
+    assert!(frag.sql.trim() == expected.trim());
+    assert_eq!(frag.params.len(), 2);
+}
+
+#[test]
+fn test_delete_using() {
+    let pg = Postgres::new();
+
+    let mut query = Query::delete();
+    query.from_table("bazaar.orders")
+         .inner_join_table("bazaar.customer", "orders.customer_id", "customer.customer_id")
+         .filter("customer.active", Equality::EQ, &false);
+    let frag = query.build(&pg).unwrap();
+
+    let expected = "
+DELETE FROM bazaar.orders
+    USING bazaar.customer
+    WHERE orders.customer_id = customer.customer_id
+      AND customer.active = $1
+".to_string();
+    println!("actual:   {{\n{}}} [{}]", frag.sql, frag.sql.len());
+    println!("expected: {{{}}} [{}]", expected, expected.len());
+    assert!(frag.sql.trim() == expected.trim());
+}
+
+#[test]
+fn test_update_with_left_join_first_is_err() {
+    let pg = Postgres::new();
+
+    let mut query = Query::update();
+    query.from_table("bazaar.orders")
+         .set("status", &"inactive customer")
+         .left_join_table("bazaar.customer", "orders.customer_id", "customer.customer_id");
+    assert!(query.build(&pg).is_err());
+}
+
+#[cfg(feature = "mysql")]
+#[test]
+fn test_mysql_multi_table_update() {
+    let my = Mysql::new();
+
+    let mut query = Query::update();
+    query.from_table("orders")
+         .set("status", &"inactive customer")
+         .inner_join_table("customer", "orders.customer_id", "customer.customer_id")
+         .filter("customer.active", Equality::EQ, &false);
+    let frag = query.build(&my).unwrap();
+
+    let expected = "
+   UPDATE orders
+          INNER JOIN customer\x20
+          ON orders.customer_id = customer.customer_id\x20
+      SET orders.status = ?
+    WHERE customer.active = ?
+".to_string();
+    println!("actual:   {{\n{}}} [{}]", frag.sql, frag.sql.len());
+    println!("expected: {{{}}} [{}]", expected, expected.len());
+    assert!(frag.sql.trim() == expected.trim());
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn test_sqlite_update_falls_back_to_exists() {
+    let lite = Sqlite::new();
+
+    let mut query = Query::update();
+    query.from_table("orders")
+         .set("status", &"inactive customer")
+         .inner_join_table("customer", "orders.customer_id", "customer.customer_id")
+         .filter("customer.active", Equality::EQ, &false);
+    let frag = query.build(&lite).unwrap();
+
+    let expected = "
+   UPDATE orders
+      SET status = $1\x20
+    WHERE EXISTS (
+   SELECT 1
+     FROM customer
+    WHERE orders.customer_id = customer.customer_id
+      AND active = $2\x20
+)
+".to_string();
+    println!("actual:   {{\n{}}} [{}]", frag.sql, frag.sql.len());
+    println!("expected: {{{}}} [{}]", expected, expected.len());
+    assert!(frag.sql.trim() == expected.trim());
+}
+
+#[test]
+fn test_update_from_with_alias() {
+    let pg = Postgres::new();
+
+    let mut query = Query::update();
+    query.from_field(Field {
+             operand: Operand::TableName(TableName::from_str("bazaar.orders")),
+             name: Some("o".to_owned()),
+         })
+         .set("status", &"inactive customer")
+         .inner_join_table("bazaar.customer", "o.customer_id", "customer.customer_id")
+         .filter("customer.active", Equality::EQ, &false);
+    let frag = query.build(&pg).unwrap();
+
+    let expected = "
+   UPDATE bazaar.orders AS o
+      SET status = $1\x20
+     FROM bazaar.customer
+    WHERE o.customer_id = customer.customer_id
+      AND customer.active = $2
+".to_string();
+    println!("actual:   {{\n{}}} [{}]", frag.sql, frag.sql.len());
+    println!("expected: {{{}}} [{}]", expected, expected.len());
+    assert!(frag.sql.trim() == expected.trim());
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn test_sqlite_exists_qualifies_the_updated_columns() {
+    let lite = Sqlite::new();
+
+    let mut query = Query::update();
+    query.from_field(Field {
+             operand: Operand::TableName(TableName::from_str("orders")),
+             name: Some("o".to_owned()),
+         })
+         .set("status", &"inactive customer")
+         .inner_join_table("customer", "o.customer_id", "customer.customer_id")
+         .filter("customer.active", Equality::EQ, &false)
+         .filter("status", Equality::NEQ, &"closed");
+    let frag = query.build(&lite).unwrap();
+
+    let expected = "
+   UPDATE orders AS o
+      SET status = $1\x20
+    WHERE EXISTS (
+   SELECT 1
+     FROM customer
+    WHERE o.customer_id = customer.customer_id
+      AND active = $2\x20
+      AND o.status != $3\x20
+)
+".to_string();
+    println!("actual:   {{\n{}}} [{}]", frag.sql, frag.sql.len());
+    println!("expected: {{{}}} [{}]", expected, expected.len());
+    assert!(frag.sql.trim() == expected.trim());
+}
@@ -0,0 +1,138 @@
+extern crate rustorm;
+
+use rustorm::query::Query;
+use rustorm::query::Equality;
+use rustorm::query::Keyset;
+use rustorm::dao::Value;
+use rustorm::platform::Postgres;
+
+
+#[test]
+fn test_keyset_first_page() {
+    let pg = Postgres::new();
+
+    let mut query = Query::select_all();
+    query.from_table("bazaar.product")
+         .asc("name")
+         .asc("product_id")
+         .set_keyset(Keyset::first(10));
+    let frag = query.build(&pg).unwrap();
+
+    let expected = "
+   SELECT *
+     FROM bazaar.product
+ ORDER BY name ASC, product_id ASC
+    LIMIT 11
+".to_string();
+    println!("actual:   {{\n{}}} [{}]", frag.sql, frag.sql.len());
+    println!("expected: {{{}}} [{}]", expected, expected.len());
+    assert!(frag.sql.trim() == expected.trim());
+}
+
+#[test]
+fn test_keyset_seek_forward() {
+    let pg = Postgres::new();
+
+    let values = vec![Value::String("product5".to_owned()), Value::I32(5)];
+    let mut query = Query::select_all();
+    query.from_table("bazaar.product")
+         .asc("name")
+         .asc("product_id")
+         .set_keyset(Keyset::after(values, 10));
+    let frag = query.build(&pg).unwrap();
+
+    let expected = "
+   SELECT *
+     FROM bazaar.product
+    WHERE (name, product_id) > ($1 , $2 )
+ ORDER BY name ASC, product_id ASC
+    LIMIT 11
+".to_string();
+    println!("actual:   {{\n{}}} [{}]", frag.sql, frag.sql.len());
+    println!("expected: {{{}}} [{}]", expected, expected.len());
+    assert!(frag.sql.trim() == expected.trim());
+    assert_eq!(frag.params.len(), 2);
+}
+
+#[test]
+fn test_keyset_seek_backward() {
+    let pg = Postgres::new();
+
+    let values = vec![Value::String("product5".to_owned()), Value::I32(5)];
+    let mut query = Query::select_all();
+    query.from_table("bazaar.product")
+         .asc("name")
+         .asc("product_id")
+         .set_keyset(Keyset::before(values, 10));
+    let frag = query.build(&pg).unwrap();
+
+    let expected = "
+   SELECT *
+     FROM bazaar.product
+    WHERE (name, product_id) < ($1 , $2 )
+ ORDER BY name DESC, product_id DESC
+    LIMIT 11
+".to_string();
+    println!("actual:   {{\n{}}} [{}]", frag.sql, frag.sql.len());
+    println!("expected: {{{}}} [{}]", expected, expected.len());
+    assert!(frag.sql.trim() == expected.trim());
+}
+
+#[test]
+fn test_keyset_mixed_directions() {
+    let pg = Postgres::new();
+
+    let values = vec![Value::String("product5".to_owned()), Value::I32(5)];
+    let mut query = Query::select_all();
+    query.from_table("bazaar.product")
+         .filter("price", Equality::GT, &10)
+         .asc("name")
+         .desc("product_id")
+         .set_keyset(Keyset::after(values, 10));
+    let frag = query.build(&pg).unwrap();
+
+    let expected = "
+   SELECT *
+     FROM bazaar.product
+    WHERE price > $1\x20
+      AND (name > $2 OR (name = $3 AND product_id < $4 ))
+ ORDER BY name ASC, product_id DESC
+    LIMIT 11
+".to_string();
+    println!("actual:   {{\n{}}} [{}]", frag.sql, frag.sql.len());
+    println!("expected: {{{}}} [{}]", expected, expected.len());
+    assert!(frag.sql.trim() == expected.trim());
+    assert_eq!(frag.params.len(), 4);
+}
+
+#[test]
+fn test_keyset_cursor() {
+    let values = vec![Value::String("product5".to_owned()), Value::I32(5)];
+    let cursor = Keyset::cursor(&values, true).unwrap();
+    let keyset = Keyset::from_cursor(&cursor, 10).unwrap();
+    assert_eq!(keyset, Keyset::before(values, 10));
+    assert!(Keyset::from_cursor("not a cursor", 10).is_err());
+}
+
+#[test]
+fn test_keyset_rejects_grouped_and_null() {
+    let pg = Postgres::new();
+
+    let values = vec![Value::I64(5), Value::I32(5)];
+    let mut query = Query::select();
+    query.column("category_id")
+         .from_table("bazaar.product")
+         .group_by(vec!["category_id"])
+         .desc("COUNT(*)")
+         .asc("category_id")
+         .set_keyset(Keyset::after(values, 10));
+    assert!(query.build(&pg).is_err());
+
+    let values = vec![Value::None, Value::I32(5)];
+    let mut query = Query::select_all();
+    query.from_table("bazaar.product")
+         .asc("name")
+         .asc("product_id")
+         .set_keyset(Keyset::after(values, 10));
+    assert!(query.build(&pg).is_err());
+}
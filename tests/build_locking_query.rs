@@ -0,0 +1,125 @@
+extern crate rustorm;
+
+use rustorm::query::Query;
+use rustorm::query::Equality;
+use rustorm::platform::Postgres;
+#[cfg(feature = "sqlite")]
+use rustorm::platform::Sqlite;
+#[cfg(feature = "mysql")]
+use rustorm::platform::Mysql;
+
+
+#[test]
+fn test_for_update_skip_locked() {
+    let pg = Postgres::new();
+
+    let mut query = Query::select_all();
+    query.from_table("bazaar.job")
+         .filter("status", Equality::EQ, &"pending")
+         .asc("created")
+         .limit(1)
+         .for_update()
+         .skip_locked();
+    let frag = query.build(&pg).unwrap();
+
+    let expected = "
+   SELECT *
+     FROM bazaar.job
+    WHERE status = $1\x20
+ ORDER BY created ASC
+    LIMIT 1
+      FOR UPDATE SKIP LOCKED
+".to_string();
+    println!("actual:   {{\n{}}} [{}]", frag.sql, frag.sql.len());
+    println!("expected: {{{}}} [{}]", expected, expected.len());
+    assert!(frag.sql.trim() == expected.trim());
+}
+
+#[test]
+fn test_for_share_of_table_nowait() {
+    let pg = Postgres::new();
+
+    let mut query = Query::select_all();
+    query.from_table("bazaar.product")
+         .left_join_table("bazaar.product_category", "product.product_id", "product_category.product_id")
+         .for_share()
+         .lock_of("product")
+         .nowait();
+    let frag = query.build(&pg).unwrap();
+
+    let expected = "
+   SELECT *
+     FROM bazaar.product
+          LEFT JOIN bazaar.product_category\x20
+          ON product.product_id = product_category.product_id\x20
+      FOR SHARE OF product NOWAIT
+".to_string();
+    println!("actual:   {{\n{}}} [{}]", frag.sql, frag.sql.len());
+    println!("expected: {{{}}} [{}]", expected, expected.len());
+    assert!(frag.sql.trim() == expected.trim());
+}
+
+#[cfg(feature = "mysql")]
+#[test]
+fn test_mysql_for_update_skip_locked() {
+    let my = Mysql::with_version("8.0.21");
+
+    let mut query = Query::select_all();
+    query.from_table("job")
+         .filter("status", Equality::EQ, &"pending")
+         .limit(1)
+         .for_update()
+         .skip_locked();
+    let frag = query.build(&my).unwrap();
+
+    let expected = "
+   SELECT *
+     FROM job
+    WHERE status = ?
+    LIMIT 1
+      FOR UPDATE SKIP LOCKED
+".to_string();
+    println!("actual:   {{\n{}}} [{}]", frag.sql, frag.sql.len());
+    println!("expected: {{{}}} [{}]", expected, expected.len());
+    assert!(frag.sql.trim() == expected.trim());
+}
+
+#[cfg(feature = "mysql")]
+#[test]
+fn test_mysql_before_8_0_lock_in_share_mode() {
+    let my = Mysql::with_version("5.7.30");
+
+    let mut query = Query::select_all();
+    query.from_table("product")
+         .filter("product_id", Equality::EQ, &1)
+         .for_share();
+    let frag = query.build(&my).unwrap();
+
+    let expected = "
+   SELECT *
+     FROM product
+    WHERE product_id = ?
+     LOCK IN SHARE MODE
+".to_string();
+    println!("actual:   {{\n{}}} [{}]", frag.sql, frag.sql.len());
+    println!("expected: {{{}}} [{}]", expected, expected.len());
+    assert!(frag.sql.trim() == expected.trim());
+
+    // SKIP LOCKED needs mysql 8.0
+    let mut query = Query::select_all();
+    query.from_table("job")
+         .for_update()
+         .skip_locked();
+    assert!(query.build(&my).is_err());
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn test_sqlite_has_no_row_locking() {
+    let lite = Sqlite::new();
+
+    let mut query = Query::select_all();
+    query.from_table("job")
+         .for_update();
+    assert!(query.build(&lite).is_err());
+}
@@ -0,0 +1,88 @@
+extern crate rustorm;
+
+use rustorm::query::Query;
+use rustorm::query::Equality;
+use rustorm::platform::Postgres;
+
+
+#[test]
+fn test_page_and_count_query() {
+    let pg = Postgres::new();
+
+    let mut query = Query::select_all();
+    query.from_table("bazaar.product")
+         .filter("price", Equality::GT, &10)
+         .asc("name")
+         .set_page(3)
+         .set_page_size(10);
+
+    let mut count = query.count_query();
+    let frag = query.build(&pg).unwrap();
+
+    let expected = "
+   SELECT *
+     FROM bazaar.product
+    WHERE price > $1\x20
+ ORDER BY name ASC
+    LIMIT 10
+   OFFSET 30
+".to_string();
+    println!("actual:   {{\n{}}} [{}]", frag.sql, frag.sql.len());
+    println!("expected: {{{}}} [{}]", expected, expected.len());
+    assert!(frag.sql.trim() == expected.trim());
+
+    let frag = count.build(&pg).unwrap();
+
+    let expected = "
+   SELECT COUNT(*) AS total
+     FROM bazaar.product
+    WHERE price > $1
+".to_string();
+    println!("actual:   {{\n{}}} [{}]", frag.sql, frag.sql.len());
+    println!("expected: {{{}}} [{}]", expected, expected.len());
+    assert!(frag.sql.trim() == expected.trim());
+}
+
+#[test]
+fn test_first_page_has_no_offset() {
+    let pg = Postgres::new();
+
+    let mut query = Query::select_all();
+    query.from_table("bazaar.product")
+         .limit(5);
+    let frag = query.build(&pg).unwrap();
+
+    let expected = "
+   SELECT *
+     FROM bazaar.product
+    LIMIT 5
+".to_string();
+    println!("actual:   {{\n{}}} [{}]", frag.sql, frag.sql.len());
+    assert!(frag.sql.trim() == expected.trim());
+}
+
+#[test]
+fn test_count_grouped_query() {
+    let pg = Postgres::new();
+
+    let mut query = Query::select();
+    query.column("category_id")
+         .from_table("bazaar.product")
+         .group_by(vec!["category_id"])
+         .set_page(2)
+         .set_page_size(20);
+
+    let frag = query.count_query().build(&pg).unwrap();
+
+    let expected = "
+   SELECT COUNT(*) AS total
+     FROM (
+   SELECT category_id
+     FROM bazaar.product
+ GROUP BY category_id\x20
+) AS counted
+".to_string();
+    println!("actual:   {{\n{}}} [{}]", frag.sql, frag.sql.len());
+    println!("expected: {{{}}} [{}]", expected, expected.len());
+    assert!(frag.sql.trim() == expected.trim());
+}
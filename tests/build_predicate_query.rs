@@ -0,0 +1,136 @@
+extern crate rustorm;
+
+use rustorm::query::{Query, Filter, Equality, Field, Operand, Case};
+use rustorm::dao::Value;
+use rustorm::platform::Postgres;
+#[cfg(feature = "sqlite")]
+use rustorm::platform::Sqlite;
+
+
+#[test]
+fn test_between_not_case_and_sql() {
+    let pg = Postgres::new();
+
+    let mut label = Case::new();
+    label.when(Filter::new("price", Equality::GT, &50),
+               Operand::Value(Value::String("premium".to_owned())))
+         .otherwise(Operand::Value(Value::String("regular".to_owned())));
+
+    let mut discontinued = Filter::new("status", Equality::EQ, &"discontinued");
+    discontinued.or("stocks", Equality::LTE, &0)
+                .negate();
+
+    let mut query = Query::select();
+    query.column("name")
+         .add_field(Field {
+             operand: Operand::Case(label),
+             name: Some("label".to_owned()),
+         })
+         .add_field(Field {
+             operand: Operand::Sql("round(price * ?, 2)".to_owned(), vec![Value::F64(1.12)]),
+             name: Some("price_with_tax".to_owned()),
+         })
+         .from_table("bazaar.product")
+         .add_filter(Filter::between("price", &10, &100))
+         .add_filter(discontinued);
+    let frag = query.build(&pg).unwrap();
+
+    let expected = "
+   SELECT name, CASE WHEN price > $1 THEN $2 ELSE $3 END AS label, round(price * $4 , 2) AS price_with_tax
+     FROM bazaar.product
+    WHERE price BETWEEN $5 AND $6\x20
+      AND NOT ( status = $7 OR stocks <= $8 )
+".to_string();
+    println!("actual:   {{\n{}}} [{}]", frag.sql, frag.sql.len());
+    println!("expected: {{{}}} [{}]", expected, expected.len());
+    assert!(frag.sql.trim() == expected.trim());
+    assert_eq!(frag.params.len(), 8);
+}
+
+#[test]
+fn test_not_exists() {
+    let pg = Postgres::new();
+
+    let mut png = Query::select();
+    png.column("photo_id")
+       .from_table("bazaar.photo")
+       .filter("url", Equality::LIKE, &"%.png");
+
+    let mut query = Query::select_all();
+    query.from_table("bazaar.product")
+         .add_filter(Filter::not_exists(png));
+    let frag = query.build(&pg).unwrap();
+
+    let expected = "
+   SELECT *
+     FROM bazaar.product
+    WHERE NOT EXISTS (
+   SELECT photo_id
+     FROM bazaar.photo
+    WHERE url LIKE $1\x20
+)
+".to_string();
+    println!("actual:   {{\n{}}} [{}]", frag.sql, frag.sql.len());
+    println!("expected: {{{}}} [{}]", expected, expected.len());
+    assert!(frag.sql.trim() == expected.trim());
+}
+
+#[test]
+fn test_raw_sql_parameter_count() {
+    let pg = Postgres::new();
+
+    let mut query = Query::select();
+    query.add_field(Field {
+             operand: Operand::Sql("? + ?".to_owned(), vec![Value::I32(1)]),
+             name: Some("total".to_owned()),
+         })
+         .from_table("bazaar.product");
+
+    assert!(query.build(&pg).is_err());
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn test_ilike_emulation() {
+    let lite = Sqlite::new();
+
+    let mut query = Query::select_all();
+    query.from_table("product")
+         .filter("name", Equality::ILIKE, &"gtx%");
+    let frag = query.build(&lite).unwrap();
+
+    let expected = "
+   SELECT *
+     FROM product
+    WHERE LOWER(name) LIKE LOWER($1 )
+".to_string();
+    println!("actual:   {{\n{}}} [{}]", frag.sql, frag.sql.len());
+    assert!(frag.sql.trim() == expected.trim());
+}
+
+#[test]
+fn test_raw_sql_escaped_question_mark() {
+    let pg = Postgres::new();
+
+    let mut query = Query::select();
+    query.add_field(Field {
+             operand: Operand::Sql("info ??| array['color', ?]".to_owned(),
+                                   vec![Value::String("size".to_owned())]),
+             name: Some("tagged".to_owned()),
+         })
+         .add_field(Field {
+             operand: Operand::Sql("'why?'".to_owned(), vec![]),
+             name: Some("question".to_owned()),
+         })
+         .from_table("bazaar.product");
+    let frag = query.build(&pg).unwrap();
+
+    let expected = "
+   SELECT info ?| array['color', $1 ] AS tagged, 'why?' AS question
+     FROM bazaar.product
+".to_string();
+    println!("actual:   {{\n{}}} [{}]", frag.sql, frag.sql.len());
+    println!("expected: {{{}}} [{}]", expected, expected.len());
+    assert!(frag.sql.trim() == expected.trim());
+    assert_eq!(frag.params.len(), 1);
+}
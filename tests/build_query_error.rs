@@ -0,0 +1,60 @@
+extern crate rustorm;
+
+use rustorm::query::Query;
+use rustorm::query::Error;
+use rustorm::query::Keyset;
+use rustorm::database::DbError;
+use rustorm::platform::Postgres;
+
+
+#[test]
+fn test_insert_without_table() {
+    let pg = Postgres::new();
+
+    let mut query = Query::insert();
+    query.set("name", &"product1");
+
+    match query.build(&pg) {
+        Err(DbError::QueryError(Error::NoTableSpecified(_))) => (),
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(frag) => panic!("should not build: {}", frag.sql),
+    }
+}
+
+#[test]
+fn test_select_without_from() {
+    let pg = Postgres::new();
+
+    let mut query = Query::select_all();
+
+    assert!(query.build(&pg).is_err());
+}
+
+#[test]
+fn test_page_mixed_with_keyset() {
+    let pg = Postgres::new();
+
+    let mut query = Query::select_all();
+    query.from_table("bazaar.product")
+         .asc("product_id")
+         .set_keyset(Keyset::first(10))
+         .set_page(2);
+
+    match query.build(&pg) {
+        Err(DbError::QueryError(Error::SqlError(_))) => (),
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(frag) => panic!("should not build: {}", frag.sql),
+    }
+}
+
+#[test]
+fn test_page_size_mixed_with_limit() {
+    let pg = Postgres::new();
+
+    let mut query = Query::select_all();
+    query.from_table("bazaar.product")
+         .limit(10)
+         .set_page_size(20);
+
+    assert!(query.build(&pg).is_err());
+}
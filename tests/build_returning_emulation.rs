@@ -0,0 +1,70 @@
+extern crate rustorm;
+
+#[cfg(feature = "sqlite")]
+use rustorm::query::Query;
+#[cfg(feature = "sqlite")]
+use rustorm::database::Database;
+#[cfg(feature = "sqlite")]
+use rustorm::platform::Sqlite;
+#[cfg(feature = "sqlite")]
+use rustorm::pool::ManagedPool;
+
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn test_sqlite_insert_selects_back_by_rowid() {
+    let lite = Sqlite::new();
+
+    let mut insert = Query::insert();
+    insert.into_table("product")
+          .set("name", &"product1")
+          .return_all();
+    let frag = insert.build(&lite).unwrap();
+
+    let expected = "
+   INSERT INTO product( name )\x20
+   VALUES ($1 )
+".to_string();
+    println!("actual:   {{\n{}}} [{}]", frag.sql, frag.sql.len());
+    assert!(frag.sql.trim() == expected.trim());
+
+    let mut select = Query::select_all();
+    select.from_table("product")
+          .add_filters(lite.inserted_filters(&insert).unwrap());
+    let frag = select.build(&lite).unwrap();
+
+    let expected = "
+   SELECT *
+     FROM product
+    WHERE rowid = last_insert_rowid()
+".to_string();
+    println!("actual:   {{\n{}}} [{}]", frag.sql, frag.sql.len());
+    assert!(frag.sql.trim() == expected.trim());
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn test_sqlite_update_selects_back_by_primary_key() {
+    let pool = ManagedPool::init("sqlite:///update_reselect_test.db", 1).unwrap();
+    let db = pool.connect().unwrap();
+    db.as_ref()
+      .execute_batch("DROP TABLE IF EXISTS product;
+                      CREATE TABLE product(product_id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+                      INSERT INTO product(product_id, name) VALUES (1, 'product1');")
+      .unwrap();
+
+    // the filtered column is changed, the record no longer matches the filter
+    let mut update = Query::update();
+    update.from_table("product")
+          .set("name", &"renamed")
+          .filter_eq("name", &"product1");
+    let dao = db.as_ref().update(&update).unwrap();
+    assert_eq!(dao.get::<i64>("product_id"), 1);
+    assert_eq!(dao.get::<String>("name"), "renamed");
+
+    let mut update = Query::update();
+    update.from_table("product")
+          .set("name", &"none")
+          .filter_eq("name", &"product1");
+    assert!(db.as_ref().update(&update).is_err());
+}
@@ -0,0 +1,58 @@
+extern crate rustorm;
+
+use rustorm::table::{Table, Column};
+use rustorm::diff;
+use rustorm::platform::Postgres;
+
+
+fn column(name: &str, db_data_type: &str) -> Column {
+    Column {
+        name: name.to_owned(),
+        data_type: "".to_owned(),
+        db_data_type: db_data_type.to_owned(),
+        is_primary: false,
+        is_unique: false,
+        default: None,
+        comment: None,
+        not_null: false,
+        foreign: None,
+        is_inherited: false,
+    }
+}
+
+fn product(columns: Vec<Column>) -> Table {
+    Table {
+        schema: "bazaar".to_owned(),
+        name: "product".to_owned(),
+        parent_table: None,
+        sub_table: vec![],
+        comment: None,
+        columns: columns,
+        is_view: false,
+    }
+}
+
+#[test]
+fn test_alter_table_sql() {
+    let pg = Postgres::new();
+
+    let live = product(vec![column("product_id", "uuid"),
+                            column("price", "integer"),
+                            column("barcode", "text")]);
+    let mut price = column("price", "double precision");
+    price.default = Some("0".to_owned());
+    price.not_null = true;
+    let defined = product(vec![column("product_id", "uuid"), price, column("name", "text")]);
+
+    let changes = diff::diff(&[live], &[defined]);
+    let frags = diff::to_sql(&pg, &changes).unwrap();
+    let sql: Vec<&str> = frags.iter().map(|f| &f.sql as &str).collect();
+
+    let expected = vec!["ALTER TABLE bazaar.product ALTER COLUMN price TYPE double precision;",
+                        "ALTER TABLE bazaar.product ALTER COLUMN price SET DEFAULT 0;",
+                        "ALTER TABLE bazaar.product ALTER COLUMN price SET NOT NULL;",
+                        "ALTER TABLE bazaar.product ADD COLUMN name text;",
+                        "ALTER TABLE bazaar.product DROP COLUMN barcode;"];
+    println!("actual: {:#?}", sql);
+    assert_eq!(sql, expected);
+}
@@ -0,0 +1,49 @@
+extern crate rustorm;
+
+use rustorm::query::{Query, Filter, Equality, Operand, ColumnName};
+use rustorm::platform::Postgres;
+
+
+#[test]
+fn test_correlated_and_in_subquery() {
+    let pg = Postgres::new();
+
+    let mut photo_count = Query::select();
+    photo_count.column("count(*)")
+               .from_table("bazaar.product_photo")
+               .add_filter(Filter::bare_new(Operand::ColumnName(ColumnName::from_str("product_photo.product_id")),
+                                            Equality::EQ,
+                                            Operand::ColumnName(ColumnName::from_str("product.product_id"))));
+
+    let mut electronic = Query::select();
+    electronic.column("category_id")
+              .from_table("bazaar.category")
+              .filter("name", Equality::LIKE, &"Elec%");
+
+    let mut query = Query::select();
+    query.column("name")
+         .column_query(photo_count, "photos")
+         .from_table("bazaar.product")
+         .filter("price", Equality::GT, &10)
+         .filter_query("category_id", Equality::IN, electronic);
+    let frag = query.build(&pg).unwrap();
+
+    let expected = "
+   SELECT name, (
+   SELECT count(*)
+     FROM bazaar.product_photo
+    WHERE product_id = product.product_id
+) AS photos
+     FROM bazaar.product
+    WHERE price > $1\x20
+      AND category_id IN (
+   SELECT category_id
+     FROM bazaar.category
+    WHERE name LIKE $2\x20
+)
+".to_string();
+    println!("actual:   {{\n{}}} [{}]", frag.sql, frag.sql.len());
+    println!("expected: {{{}}} [{}]", expected, expected.len());
+    assert!(frag.sql.trim() == expected.trim());
+    assert_eq!(frag.params.len(), 2);
+}
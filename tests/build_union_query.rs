@@ -0,0 +1,115 @@
+extern crate rustorm;
+
+use rustorm::query::Query;
+use rustorm::query::Equality;
+use rustorm::platform::Postgres;
+#[cfg(feature = "sqlite")]
+use rustorm::platform::Sqlite;
+
+
+#[test]
+fn test_union_all() {
+    let pg = Postgres::new();
+
+    let mut categories = Query::select();
+    categories.columns(vec!["name", "created"])
+              .from_table("bazaar.category")
+              .filter("name", Equality::EQ, &"Electronic");
+
+    let mut query = Query::select();
+    query.columns(vec!["name", "created"])
+         .from_table("bazaar.product")
+         .filter("name", Equality::LIKE, &"GTX%")
+         .union_all(categories)
+         .desc("created");
+    let frag = query.build(&pg).unwrap();
+
+    let expected = "
+   SELECT name, created
+     FROM bazaar.product
+    WHERE name LIKE $1\x20
+UNION ALL\x20
+   SELECT name, created
+     FROM bazaar.category
+    WHERE name = $2\x20
+ ORDER BY created DESC
+".to_string();
+    println!("actual:   {{\n{}}} [{}]", frag.sql, frag.sql.len());
+    println!("expected: {{{}}} [{}]", expected, expected.len());
+    assert!(frag.sql.trim() == expected.trim());
+    assert_eq!(frag.params.len(), 2);
+}
+
+#[test]
+fn test_order_by_on_combined_query() {
+    let pg = Postgres::new();
+
+    let mut categories = Query::select();
+    categories.column("name")
+              .from_table("bazaar.category")
+              .asc("name");
+
+    let mut query = Query::select();
+    query.column("name")
+         .from_table("bazaar.product")
+         .except(categories);
+
+    assert!(query.build(&pg).is_err());
+}
+
+#[test]
+fn test_nested_combined_query() {
+    let pg = Postgres::new();
+
+    let mut discontinued = Query::select();
+    discontinued.column("name")
+                .from_table("bazaar.discontinued");
+
+    let mut categories = Query::select();
+    categories.column("name")
+              .from_table("bazaar.category")
+              .union(discontinued);
+
+    let mut query = Query::select();
+    query.column("name")
+         .from_table("bazaar.product")
+         .except(categories);
+    let frag = query.build(&pg).unwrap();
+
+    let expected = "
+   SELECT name
+     FROM bazaar.product
+   EXCEPT (
+   SELECT name
+     FROM bazaar.category
+    UNION\x20
+   SELECT name
+     FROM bazaar.discontinued
+)
+".to_string();
+    println!("actual:   {{\n{}}} [{}]", frag.sql, frag.sql.len());
+    println!("expected: {{{}}} [{}]", expected, expected.len());
+    assert!(frag.sql.trim() == expected.trim());
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn test_nested_combined_query_sqlite() {
+    let sqlite = Sqlite::new();
+
+    let mut discontinued = Query::select();
+    discontinued.column("name")
+                .from_table("discontinued");
+
+    let mut categories = Query::select();
+    categories.column("name")
+              .from_table("category")
+              .union(discontinued);
+
+    let mut query = Query::select();
+    query.column("name")
+         .from_table("product")
+         .except(categories);
+
+    assert!(query.build(&sqlite).is_err());
+}
@@ -0,0 +1,82 @@
+extern crate rustorm;
+
+use rustorm::query::Query;
+use rustorm::platform::Postgres;
+#[cfg(feature = "sqlite")]
+use rustorm::platform::Sqlite;
+#[cfg(feature = "mysql")]
+use rustorm::platform::Mysql;
+
+
+#[test]
+fn test_upsert_query() {
+    let pg = Postgres::new();
+
+    let mut query = Query::insert();
+
+    query.into_table("bazaar.product")
+         .set("product_id", &1)
+         .set("name", &"product1")
+         .on_conflict_update(vec!["product_id"])
+         .return_all();
+
+    let frag = query.build(&pg).unwrap();
+
+    let expected = "
+   INSERT INTO bazaar.product( product_id, name )\x20
+   VALUES ($1 , $2 )\x20
+       ON CONFLICT (product_id) DO UPDATE SET name = EXCLUDED.name\x20
+RETURNING *
+".to_string();
+    println!("actual:   {{\n{}}} [{}]", frag.sql, frag.sql.len());
+    println!("expected: {{{}}} [{}]", expected, expected.len());
+    assert!(frag.sql.trim() == expected.trim());
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn test_sqlite_upsert_query() {
+    let lite = Sqlite::new();
+
+    let mut query = Query::insert();
+
+    query.into_table("product")
+         .set("product_id", &1)
+         .set("name", &"product1")
+         .on_conflict_update(vec!["product_id"]);
+
+    let frag = query.build(&lite).unwrap();
+
+    let expected = "
+   INSERT INTO product( product_id, name )\x20
+   VALUES ($1 , $2 )\x20
+       ON CONFLICT (product_id) DO UPDATE SET name = EXCLUDED.name
+".to_string();
+    println!("actual:   {{\n{}}} [{}]", frag.sql, frag.sql.len());
+    println!("expected: {{{}}} [{}]", expected, expected.len());
+    assert!(frag.sql.trim() == expected.trim());
+}
+
+#[cfg(feature = "mysql")]
+#[test]
+fn test_mysql_upsert_query() {
+    let my = Mysql::new();
+
+    let mut query = Query::insert();
+
+    query.into_table("product")
+         .set("product_id", &1)
+         .set("name", &"product1")
+         .on_conflict_update(vec!["product_id"]);
+
+    let frag = query.build(&my).unwrap();
+
+    let expected = "
+   INSERT INTO product( product_id, name )\x20
+   VALUES (?, ?)\x20
+       ON DUPLICATE KEY UPDATE name = VALUES(name)
+".to_string();
+    println!("actual:   {{\n{}}} [{}]", frag.sql, frag.sql.len());
+    println!("expected: {{{}}} [{}]", expected, expected.len());
+    assert!(frag.sql.trim() == expected.trim());
+}
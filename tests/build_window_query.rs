@@ -0,0 +1,39 @@
+extern crate rustorm;
+
+use rustorm::query::{Query, Function, Window, FrameBound, Operand, ColumnName};
+use rustorm::platform::Postgres;
+
+
+#[test]
+fn test_window_functions() {
+    let pg = Postgres::new();
+
+    let mut by_category = Window::new();
+    by_category.partition_by(vec!["category_id"])
+               .desc("price");
+    let mut price_rank = Function::new("row_number", vec![]);
+    price_rank.over(by_category);
+
+    let mut running = Window::new();
+    running.asc("created")
+           .rows(FrameBound::UNBOUNDED_PRECEDING, Some(FrameBound::CURRENT_ROW));
+    let mut running_total = Function::new("sum",
+                                          vec![Operand::ColumnName(ColumnName::from_str("price"))]);
+    running_total.over(running);
+
+    let mut query = Query::select();
+    query.columns(vec!["name", "price"])
+         .function(price_rank, "price_rank")
+         .function(running_total, "running_total")
+         .from_table("bazaar.product");
+    let frag = query.build(&pg).unwrap();
+
+    let expected = "
+   SELECT name, price, row_number() OVER (PARTITION BY category_id ORDER BY price DESC) AS price_rank,\x20
+          sum(price) OVER (ORDER BY created ASC ROWS BETWEEN UNBOUNDED PRECEDING AND CURRENT ROW) AS running_total
+     FROM bazaar.product
+".to_string();
+    println!("actual:   {{\n{}}} [{}]", frag.sql, frag.sql.len());
+    println!("expected: {{{}}} [{}]", expected, expected.len());
+    assert!(frag.sql.trim() == expected.trim());
+}
@@ -0,0 +1,85 @@
+extern crate rustorm;
+#[macro_use]
+extern crate rustorm_derive;
+extern crate uuid;
+
+use uuid::Uuid;
+use rustorm::dao::{Dao, IsDao};
+use rustorm::table::IsTable;
+
+
+#[derive(Debug, Clone, IsDao, IsTable)]
+#[rustorm(schema = "bazaar", table = "product")]
+pub struct Product {
+    #[rustorm(primary)]
+    pub product_id: Uuid,
+    #[rustorm(column = "name")]
+    pub title: Option<String>,
+    pub price: f64,
+    #[rustorm(skip)]
+    pub selected: bool,
+}
+
+#[derive(Debug, Clone, IsDao, IsTable)]
+pub struct ProductAvailability {
+    #[rustorm(primary)]
+    pub product_id: Uuid,
+    pub available: Option<bool>,
+    #[rustorm(nullable)]
+    pub stocks: f64,
+    #[rustorm(default = "now()")]
+    pub updated: String,
+}
+
+#[test]
+fn test_derive_table() {
+    let table = Product::table();
+    assert_eq!(table.complete_name(), "bazaar.product");
+    let columns: Vec<&str> = table.columns.iter().map(|c| &c.name as &str).collect();
+    assert_eq!(columns, vec!["product_id", "name", "price"]);
+    let primary: Vec<&str> = table.primary_columns().iter().map(|c| &c.name as &str).collect();
+    assert_eq!(primary, vec!["product_id"]);
+    assert_eq!(table.non_nullable_columns(), vec!["product_id", "price"]);
+    assert_eq!(table.get_column("name").unwrap().data_type, "String");
+
+    let table = ProductAvailability::table();
+    assert_eq!(table.complete_name(), "product_availability");
+    assert_eq!(table.non_nullable_columns(), vec!["product_id", "updated"]);
+    assert_eq!(table.get_column("updated").unwrap().default, Some("now()".to_owned()));
+    assert_eq!(table.get_column("stocks").unwrap().default, None);
+}
+
+#[test]
+fn test_derive_dao() {
+    let product = Product {
+        product_id: Uuid::parse_str("6db712e6-cc50-4c3a-8269-451c98ace5ad").unwrap(),
+        title: Some("GTX660 Ti videocard".to_owned()),
+        price: 200.0,
+        selected: true,
+    };
+    let dao = product.to_dao();
+    assert!(dao.values.get("selected").is_none());
+
+    let copy = Product::from_dao(&dao);
+    assert_eq!(copy.product_id, product.product_id);
+    assert_eq!(copy.title, product.title);
+    assert_eq!(copy.price, 200.0);
+    assert_eq!(copy.selected, false);
+
+    let mut dao = Dao::new();
+    dao.set("product_id", &product.product_id);
+    dao.set_null("name");
+    dao.set("price", &10.0);
+    let product = Product::from_dao(&dao);
+    assert_eq!(product.title, None);
+
+    // a NULL in a nullable column that is not an Option is read as the default
+    let mut dao = Dao::new();
+    dao.set("product_id", &product.product_id);
+    dao.set_null("available");
+    dao.set_null("stocks");
+    dao.set("updated", &"2015-12-01 10:00:00");
+    let availability = ProductAvailability::from_dao(&dao);
+    assert_eq!(availability.available, None);
+    assert_eq!(availability.stocks, 0.0);
+}
@@ -0,0 +1,46 @@
+extern crate rustorm;
+
+#[cfg(feature = "sqlite")]
+use rustorm::query::Query;
+#[cfg(feature = "sqlite")]
+use rustorm::pool::ManagedPool;
+#[cfg(feature = "sqlite")]
+use rustorm::dao::Value;
+
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn test_joined_columns_are_casted_per_table() {
+    let pool = ManagedPool::init("sqlite:///declared_types_test.db", 1).unwrap();
+    let db = pool.connect().unwrap();
+    db.as_ref()
+      .execute_batch("DROP TABLE IF EXISTS pet;
+                      DROP TABLE IF EXISTS person;
+                      CREATE TABLE person(person_id INTEGER PRIMARY KEY, code TEXT);
+                      CREATE TABLE pet(pet_id INTEGER PRIMARY KEY, person_id INTEGER, code BOOLEAN);
+                      INSERT INTO person VALUES (1, 'seven');
+                      INSERT INTO pet VALUES (1, 1, 1);")
+      .unwrap();
+
+    let mut query = Query::select();
+    query.column("person.code")
+         .column("pet.code")
+         .from_table("person")
+         .left_join_table("pet", "pet.person_id", "person.person_id");
+    let result = query.retrieve(db.as_ref()).unwrap();
+    let dao = &result.dao[0];
+    println!("dao: {:?}", dao);
+    assert_eq!(dao.values.get("person.code"), Some(&Value::String("seven".to_owned())));
+    assert_eq!(dao.values.get("pet.code"), Some(&Value::Bool(true)));
+
+    // a table unknown to sqlite is left in its storage class instead of panicking
+    let mut query = Query::select_all();
+    query.from_query({
+                         let mut inner = Query::select_all();
+                         inner.from_table("person");
+                         inner
+                     },
+                     "p");
+    let result = query.retrieve(db.as_ref()).unwrap();
+    assert_eq!(result.dao.len(), 1);
+}
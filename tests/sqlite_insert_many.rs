@@ -0,0 +1,85 @@
+extern crate rustorm;
+#[cfg(feature = "sqlite")]
+#[macro_use]
+extern crate rustorm_derive;
+
+#[cfg(feature = "sqlite")]
+use rustorm::pool::ManagedPool;
+#[cfg(feature = "sqlite")]
+use rustorm::em::EntityManager;
+
+
+#[cfg(feature = "sqlite")]
+#[derive(Debug, Clone, IsDao, IsTable)]
+pub struct Product {
+    #[rustorm(primary)]
+    pub product_id: i64,
+    pub name: String,
+    pub description: Option<String>,
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn test_insert_many_selects_back_each_record() {
+    let pool = ManagedPool::init("sqlite:///insert_many_test.db", 1).unwrap();
+    let db = pool.connect().unwrap();
+    db.as_ref()
+      .execute_batch("DROP TABLE IF EXISTS product;
+                      CREATE TABLE product(product_id INTEGER PRIMARY KEY, name TEXT NOT NULL,
+                                           description TEXT);")
+      .unwrap();
+
+    let em = EntityManager::new(db.as_ref());
+    let products = vec![Product {
+                            product_id: 1,
+                            name: "GTX660 Ti videocard".to_owned(),
+                            description: None,
+                        },
+                        Product {
+                            product_id: 2,
+                            name: "Mouse".to_owned(),
+                            description: Some("wireless".to_owned()),
+                        }];
+    let inserted = em.insert_many(&products).unwrap();
+    assert_eq!(inserted.len(), 2);
+    assert_eq!(inserted[0].product_id, 1);
+    assert_eq!(inserted[1].name, "Mouse");
+    assert_eq!(inserted[1].description, Some("wireless".to_owned()));
+}
+
+#[cfg(feature = "sqlite")]
+#[derive(Debug, Clone, IsDao, IsTable)]
+pub struct Category {
+    #[rustorm(primary)]
+    pub category_id: Option<i64>,
+    pub name: String,
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn test_insert_many_selects_back_generated_keys_in_order() {
+    let pool = ManagedPool::init("sqlite:///insert_many_generated_test.db", 1).unwrap();
+    let db = pool.connect().unwrap();
+    db.as_ref()
+      .execute_batch("DROP TABLE IF EXISTS category;
+                      CREATE TABLE category(category_id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+                      INSERT INTO category(category_id, name) VALUES (10, 'existing');")
+      .unwrap();
+
+    let em = EntityManager::new(db.as_ref());
+    let categories: Vec<Category> = ["mouse", "keyboard", "monitor"]
+                                        .iter()
+                                        .map(|name| {
+                                            Category {
+                                                category_id: None,
+                                                name: name.to_string(),
+                                            }
+                                        })
+                                        .collect();
+    let inserted = em.insert_many(&categories).unwrap();
+    assert_eq!(inserted.len(), 3);
+    assert_eq!(inserted[0].category_id, Some(11));
+    assert_eq!(inserted[0].name, "mouse");
+    assert_eq!(inserted[2].category_id, Some(13));
+    assert_eq!(inserted[2].name, "monitor");
+}
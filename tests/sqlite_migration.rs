@@ -0,0 +1,103 @@
+extern crate rustorm;
+
+#[cfg(feature = "sqlite")]
+use rustorm::pool::ManagedPool;
+#[cfg(feature = "sqlite")]
+use rustorm::migration::{Migrator, Migration, MigrationStatus};
+#[cfg(feature = "sqlite")]
+use rustorm::database::Database;
+
+
+#[cfg(feature = "sqlite")]
+fn migrations() -> Vec<Migration> {
+    vec![Migration::sql(2,
+                        "add_price",
+                        "ALTER TABLE product ADD COLUMN price INTEGER;",
+                        None),
+         Migration::sql(1,
+                        "create_product",
+                        "CREATE TABLE product(product_id INTEGER PRIMARY KEY, name TEXT DEFAULT 'a;b');
+                         INSERT INTO product(product_id) VALUES (1);",
+                        Some("DROP TABLE product;"))]
+}
+
+#[cfg(feature = "sqlite")]
+fn reset(db: &Database) {
+    db.execute_batch("DROP TABLE IF EXISTS product;
+                      DROP TABLE IF EXISTS rustorm_migrations;")
+      .unwrap();
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn test_migrate_and_rollback() {
+    let pool = ManagedPool::init("sqlite:///migration_test.db", 1).unwrap();
+    let db = pool.connect().unwrap();
+    reset(db.as_ref());
+
+    let migrator = Migrator::new(migrations());
+    assert_eq!(migrator.migrate(db.as_ref()).unwrap(), vec![1, 2]);
+    assert_eq!(migrator.migrate(db.as_ref()).unwrap(), Vec::<u64>::new());
+    let name = db.as_ref()
+                 .execute_sql_with_one_return("SELECT name FROM product", &[])
+                 .unwrap()
+                 .unwrap();
+    assert_eq!(name.get::<String>("name"), "a;b");
+
+    // the last migration has no down step
+    assert!(migrator.rollback(db.as_ref()).is_err());
+
+    let first = Migrator::new(migrations().into_iter().filter(|m| m.version == 1).collect());
+    let status = first.status(db.as_ref()).unwrap();
+    assert_eq!(status,
+               vec![MigrationStatus {
+                        version: 1,
+                        name: "create_product".to_owned(),
+                        applied: true,
+                        modified: false,
+                    }]);
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn test_failed_migration_is_not_recorded() {
+    let pool = ManagedPool::init("sqlite:///migration_failed_test.db", 1).unwrap();
+    let db = pool.connect().unwrap();
+    reset(db.as_ref());
+
+    let migrator = Migrator::new(vec![Migration::sql(1,
+                                                     "create_product",
+                                                     "CREATE TABLE product(product_id INTEGER);
+                                                      INSERT INTO no_such_table VALUES (1);",
+                                                     Some("DROP TABLE product;"))]);
+    assert!(migrator.migrate(db.as_ref()).is_err());
+    let status = migrator.status(db.as_ref()).unwrap();
+    assert!(!status[0].applied);
+    assert!(db.as_ref().execute_sql_with_return("SELECT * FROM product", &[]).is_err());
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn test_revert_and_modified() {
+    let pool = ManagedPool::init("sqlite:///migration_revert_test.db", 1).unwrap();
+    let db = pool.connect().unwrap();
+    reset(db.as_ref());
+
+    let migrator = Migrator::new(vec![Migration::sql(1,
+                                                     "create_product",
+                                                     "CREATE TABLE product(product_id INTEGER PRIMARY KEY);",
+                                                     Some("DROP TABLE product;"))]);
+    assert_eq!(migrator.migrate(db.as_ref()).unwrap(), vec![1]);
+    assert_eq!(migrator.redo(db.as_ref()).unwrap(), Some(1));
+    assert_eq!(migrator.rollback(db.as_ref()).unwrap(), Some(1));
+    assert_eq!(migrator.rollback(db.as_ref()).unwrap(), None);
+    assert!(db.as_ref().execute_sql_with_return("SELECT * FROM product", &[]).is_err());
+
+    assert_eq!(migrator.migrate(db.as_ref()).unwrap(), vec![1]);
+    let modified = Migrator::new(vec![Migration::sql(1,
+                                                     "create_product",
+                                                     "CREATE TABLE product(product_id TEXT);",
+                                                     Some("DROP TABLE product;"))]);
+    assert!(modified.status(db.as_ref()).unwrap()[0].modified);
+    assert!(modified.migrate(db.as_ref()).is_err());
+}
@@ -0,0 +1,83 @@
+extern crate rustorm;
+#[cfg(feature = "sqlite")]
+#[macro_use]
+extern crate rustorm_derive;
+
+#[cfg(feature = "sqlite")]
+use rustorm::pool::ManagedPool;
+#[cfg(feature = "sqlite")]
+use rustorm::em::EntityManager;
+#[cfg(feature = "sqlite")]
+use rustorm::query::{Filter, Equality};
+#[cfg(feature = "sqlite")]
+use rustorm::dao::IsDao;
+
+
+#[cfg(feature = "sqlite")]
+#[derive(Debug, Clone, IsDao, IsTable)]
+pub struct Product {
+    #[rustorm(primary)]
+    pub product_id: i64,
+    pub name: String,
+    pub version: i64,
+}
+
+#[cfg(feature = "sqlite")]
+#[derive(Debug, Clone, IsDao, IsTable)]
+pub struct Note {
+    pub title: String,
+    pub body: String,
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn test_update_with_filter_that_matches_nothing() {
+    let pool = ManagedPool::init("sqlite:///update_with_filter_test.db", 1).unwrap();
+    let db = pool.connect().unwrap();
+    db.as_ref()
+      .execute_batch("DROP TABLE IF EXISTS product;
+                      CREATE TABLE product(product_id INTEGER PRIMARY KEY, name TEXT NOT NULL,
+                                           version INTEGER NOT NULL);
+                      INSERT INTO product(product_id, name, version) VALUES (1, 'Mouse', 2);")
+      .unwrap();
+
+    let em = EntityManager::new(db.as_ref());
+    let product = Product {
+        product_id: 1,
+        name: "Wireless mouse".to_owned(),
+        version: 3,
+    };
+    // the record has moved on to version 2, the stale update is not applied
+    let stale = vec![Filter::new("product_id", Equality::EQ, &1),
+                     Filter::new("version", Equality::EQ, &1)];
+    let result: Result<Product, _> = em.update_with_filter(&product.to_dao(), stale);
+    assert!(result.is_err());
+
+    let current = vec![Filter::new("product_id", Equality::EQ, &1),
+                       Filter::new("version", Equality::EQ, &2)];
+    let updated: Product = em.update_with_filter(&product.to_dao(), current).unwrap();
+    assert_eq!(updated.name, "Wireless mouse");
+    assert_eq!(updated.version, 3);
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn test_update_with_filter_on_the_changed_column_without_primary_key() {
+    let pool = ManagedPool::init("sqlite:///update_without_primary_test.db", 1).unwrap();
+    let db = pool.connect().unwrap();
+    db.as_ref()
+      .execute_batch("DROP TABLE IF EXISTS note;
+                      CREATE TABLE note(title TEXT NOT NULL, body TEXT NOT NULL);
+                      INSERT INTO note(title, body) VALUES ('draft', 'first');")
+      .unwrap();
+
+    let em = EntityManager::new(db.as_ref());
+    let note = Note {
+        title: "final".to_owned(),
+        body: "second".to_owned(),
+    };
+    let filter = vec![Filter::new("title", Equality::EQ, &"draft")];
+    let updated: Note = em.update_with_filter(&note.to_dao(), filter).unwrap();
+    assert_eq!(updated.title, "final");
+    assert_eq!(updated.body, "second");
+}
@@ -61,7 +61,7 @@ fn test_filter_eq_query() {
          .having("count(*)", Equality::GT, &1)
          .asc("product.name")
          .desc("product.created");
-    let frag = query.build(db.as_ref());
+    let frag = query.build(db.as_ref()).unwrap();
 
     let expected = "
    SELECT *
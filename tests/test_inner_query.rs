@@ -61,7 +61,7 @@ fn test_inner_join_query() {
          .having("count(*)", Equality::GT, &1)
          .asc("product.name")
          .desc("product.created");
-    let frag = query.build(db.as_ref());
+    let frag = query.build(db.as_ref()).unwrap();
 
     let expected = "
    SELECT *
@@ -97,8 +97,10 @@ fn test_left_inner_join_query() {
             modifier: Some(Modifier::LEFT),
             join_type: Some(JoinType::INNER),
             table_name: "bazaar.product_category".to_table_name(),
+            alias: None,
             column1: vec!["product_category.product_id".to_owned()],
             column2: vec!["product.product_id".to_owned()],
+            on: vec![],
         };
 
     query.from_table("bazaar.product")
@@ -116,13 +118,12 @@ fn test_left_inner_join_query() {
          .having("count(*)", Equality::GT, &1)
          .asc("product.name")
          .desc("product.created");
-    let frag = query.build(db.as_ref());
+    let frag = query.build(db.as_ref()).unwrap();
 
     let expected = "
    SELECT *
      FROM bazaar.product
-          LEFT\x20
-          INNER JOIN bazaar.product_category\x20
+          LEFT INNER JOIN bazaar.product_category\x20
           ON product_category.product_id = product.product_id\x20
           INNER JOIN bazaar.category\x20
           ON category.category_id = product_category.category_id\x20